@@ -0,0 +1,57 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use johnfig::{
+    testing::{generate_tree, TreeSpec},
+    ConfigBuilder,
+};
+
+const TREES: &[TreeSpec] = &[
+    TreeSpec {
+        files: 10,
+        keys: 10,
+        depth: 2,
+    },
+    TreeSpec {
+        files: 100,
+        keys: 20,
+        depth: 3,
+    },
+    TreeSpec {
+        files: 500,
+        keys: 50,
+        depth: 4,
+    },
+];
+
+fn finder(c: &mut Criterion) {
+    let mut group = c.benchmark_group("finder");
+
+    for spec in TREES {
+        let id = format!("{}x{}x{}", spec.files, spec.keys, spec.depth);
+        let root =
+            std::env::temp_dir().join(format!("johnfig-bench-{}-{}", std::process::id(), id));
+
+        generate_tree(&root, spec).expect("generate tree");
+
+        let finder = ConfigBuilder::new()
+            .with_search_path(root.clone())
+            .expect("search path")
+            .with_name_pattern("*.config.{ext}")
+            .build()
+            .expect("finder");
+
+        group.bench_with_input(BenchmarkId::new("discover", &id), &finder, |b, finder| {
+            b.iter(|| finder.files().count())
+        });
+
+        group.bench_with_input(BenchmarkId::new("load", &id), &finder, |b, finder| {
+            b.iter(|| finder.config().expect("config"))
+        });
+
+        std::fs::remove_dir_all(&root).ok();
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, finder);
+criterion_main!(benches);
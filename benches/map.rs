@@ -0,0 +1,82 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use johnfig::Config;
+use vaerdi::{merge, Map, Value};
+
+// Entries per map; most real configs stay below 16
+const SIZES: &[usize] = &[4, 8, 16, 64];
+
+const DEPTH: usize = 3;
+
+fn map(size: usize, depth: usize, seed: usize) -> Map {
+    let mut map = Map::default();
+
+    for key in 0..size {
+        map.insert(
+            format!("key{}", key),
+            Value::String(format!("value-{}-{}", seed, key)),
+        );
+    }
+
+    if depth > 1 {
+        map.insert("nested", Value::Map(self::map(size, depth - 1, seed)));
+    }
+
+    map
+}
+
+fn lookup(c: &mut Criterion) {
+    let mut group = c.benchmark_group("map/lookup");
+
+    for &size in SIZES {
+        let mut config = Config::default();
+        for (key, value) in map(size, DEPTH, 0).into_iter() {
+            config.set(key, value);
+        }
+
+        let last = format!("key{}", size - 1);
+        let nested = format!("nested.nested.{}", last);
+
+        group.bench_with_input(BenchmarkId::new("get", size), &config, |b, config| {
+            b.iter(|| config.get(&last).is_some())
+        });
+
+        group.bench_with_input(BenchmarkId::new("get_path", size), &config, |b, config| {
+            b.iter(|| config.get_path(&nested).is_some())
+        });
+
+        let compiled = config.compile();
+        group.bench_with_input(
+            BenchmarkId::new("compiled", size),
+            &compiled,
+            |b, compiled| b.iter(|| compiled.get_path(&nested).is_some()),
+        );
+    }
+
+    group.finish();
+}
+
+fn merging(c: &mut Criterion) {
+    let mut group = c.benchmark_group("map/merge");
+
+    for &size in SIZES {
+        let base = Value::Map(map(size, DEPTH, 0));
+        let other = Value::Map(map(size, DEPTH, 1));
+
+        group.bench_with_input(
+            BenchmarkId::new("merge", size),
+            &(base, other),
+            |b, (base, other)| {
+                b.iter(|| {
+                    let mut target = base.clone();
+                    merge(&mut target, other.clone());
+                    target
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, lookup, merging);
+criterion_main!(benches);
@@ -0,0 +1,10 @@
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AccessReport {
+    pub read: Vec<String>,
+    /// Key paths, down to the leaves the config was merged from.
+    pub unread: Vec<String>,
+    /// Loaded files none of whose values were read.
+    pub unread_files: Vec<PathBuf>,
+}
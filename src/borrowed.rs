@@ -0,0 +1,202 @@
+use serde::de::{
+    value::BorrowedStrDeserializer, DeserializeSeed, Deserializer, EnumAccess, Error as _,
+    MapAccess, SeqAccess, VariantAccess, Visitor,
+};
+use vaerdi::{de::DeserializerError, Value};
+
+// Deserializes from a borrowed value, lending out strings instead of cloning
+// the tree. Other scalars are cheap to copy and go through vaerdi's own deserializer
+pub(crate) struct ValueRef<'de>(pub &'de Value);
+
+impl<'de> Deserializer<'de> for ValueRef<'de> {
+    type Error = DeserializerError;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.0 {
+            Value::String(string) => visitor.visit_borrowed_str(string),
+            Value::Map(map) => visitor.visit_map(MapRef {
+                entries: map
+                    .iter()
+                    .map(|(key, value)| -> (&str, &Value) { (key, value) })
+                    .collect::<Vec<_>>()
+                    .into_iter(),
+                value: None,
+            }),
+            Value::List(list) => visitor.visit_seq(SeqRef {
+                items: list.iter().collect::<Vec<_>>().into_iter(),
+            }),
+            value => value.clone().deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.0 {
+            Value::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.0 {
+            Value::String(string) => visitor.visit_enum(BorrowedStrDeserializer::new(string)),
+            Value::Map(map) => {
+                let mut entries = map.iter();
+                match (entries.next(), entries.next()) {
+                    (Some((variant, value)), None) => {
+                        visitor.visit_enum(EnumRef { variant, value })
+                    }
+                    _ => Err(DeserializerError::custom(
+                        "expected a map with a single key for an enum",
+                    )),
+                }
+            }
+            value => value.clone().deserialize_enum(name, variants, visitor),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+struct MapRef<'de> {
+    entries: std::vec::IntoIter<(&'de str, &'de Value)>,
+    value: Option<&'de Value>,
+}
+
+impl<'de> MapAccess<'de> for MapRef<'de> {
+    type Error = DeserializerError;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        let (key, value) = match self.entries.next() {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+
+        self.value = Some(value);
+        seed.deserialize(BorrowedStrDeserializer::new(key))
+            .map(Some)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        match self.value.take() {
+            Some(value) => seed.deserialize(ValueRef(value)),
+            None => Err(DeserializerError::custom("value requested before its key")),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.entries.len())
+    }
+}
+
+struct SeqRef<'de> {
+    items: std::vec::IntoIter<&'de Value>,
+}
+
+impl<'de> SeqAccess<'de> for SeqRef<'de> {
+    type Error = DeserializerError;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        match self.items.next() {
+            Some(value) => seed.deserialize(ValueRef(value)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.items.len())
+    }
+}
+
+// An externally tagged variant, `{ "variant": value }`
+struct EnumRef<'de> {
+    variant: &'de str,
+    value: &'de Value,
+}
+
+impl<'de> EnumAccess<'de> for EnumRef<'de> {
+    type Error = DeserializerError;
+    type Variant = ValueRef<'de>;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant), Self::Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let variant =
+            seed.deserialize(BorrowedStrDeserializer::<Self::Error>::new(self.variant))?;
+        Ok((variant, ValueRef(self.value)))
+    }
+}
+
+impl<'de> VariantAccess<'de> for ValueRef<'de> {
+    type Error = DeserializerError;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        match self.0 {
+            Value::Null => Ok(()),
+            _ => Err(DeserializerError::custom("expected a unit variant")),
+        }
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Self::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_any(visitor)
+    }
+
+    fn struct_variant<V>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_any(visitor)
+    }
+}
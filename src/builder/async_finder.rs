@@ -0,0 +1,267 @@
+use super::builder::ConfigFinder;
+use crate::{Config, Error};
+use futures_core::Stream;
+use serde::de::DeserializeOwned;
+use std::{
+    future::Future,
+    path::PathBuf,
+    pin::Pin,
+    sync::{
+        mpsc::{channel, Sender},
+        Arc, Mutex,
+    },
+    task::{Context, Poll, Waker},
+};
+
+/// A unit of blocking work handed to a [`Spawner`].
+pub type BlockingTask = Box<dyn FnOnce() + Send>;
+
+/// Runs the blocking work of an [`AsyncConfigFinder`], e.g. on the blocking pool
+/// of the runtime in use. Closures taking a [`BlockingTask`] are spawners, so
+/// with tokio `|task| { tokio::task::spawn_blocking(task); }` is one.
+pub trait Spawner: Send + Sync + 'static {
+    fn spawn(&self, task: BlockingTask);
+}
+
+impl<F> Spawner for F
+where
+    F: Fn(BlockingTask) + Send + Sync + 'static,
+{
+    fn spawn(&self, task: BlockingTask) {
+        self(task)
+    }
+}
+
+// The process-wide pool of the blocking crate, sized by BLOCKING_MAX_THREADS
+struct BlockingPool;
+
+impl Spawner for BlockingPool {
+    fn spawn(&self, task: BlockingTask) {
+        blocking::unblock(task).detach();
+    }
+}
+
+// A fixed number of threads of its own, which end with the last finder using them
+struct ThreadPool(Mutex<Sender<BlockingTask>>);
+
+impl ThreadPool {
+    fn new(threads: usize) -> ThreadPool {
+        let (tx, rx) = channel::<BlockingTask>();
+        let rx = Arc::new(Mutex::new(rx));
+
+        for idx in 0..threads.max(1) {
+            let rx = rx.clone();
+            std::thread::Builder::new()
+                .name(format!("johnfig-blocking-{idx}"))
+                .spawn(move || loop {
+                    let task = match rx.lock().expect("task queue lock poisoned").recv() {
+                        Ok(task) => task,
+                        Err(_) => return,
+                    };
+                    task();
+                })
+                .expect("failed to spawn a blocking thread");
+        }
+
+        ThreadPool(Mutex::new(tx))
+    }
+}
+
+impl Spawner for ThreadPool {
+    fn spawn(&self, task: BlockingTask) {
+        self.0
+            .lock()
+            .expect("task queue lock poisoned")
+            .send(task)
+            .ok();
+    }
+}
+
+/// Runs the finder through a [`Spawner`], so discovery and parsing never block
+/// the async executor. Defaults to the thread pool of the `blocking` crate.
+#[derive(Clone)]
+pub struct AsyncConfigFinder {
+    finder: ConfigFinder,
+    spawner: Arc<dyn Spawner>,
+}
+
+impl AsyncConfigFinder {
+    pub(super) fn new(finder: ConfigFinder) -> AsyncConfigFinder {
+        AsyncConfigFinder {
+            finder,
+            spawner: Arc::new(BlockingPool),
+        }
+    }
+
+    /// Runs the blocking work through `spawner` instead.
+    pub fn with_spawner(mut self, spawner: impl Spawner) -> Self {
+        self.spawner = Arc::new(spawner);
+        self
+    }
+
+    /// Runs the blocking work on `threads` threads of its own, shared by the
+    /// clones of this finder, instead of the process-wide pool.
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.spawner = Arc::new(ThreadPool::new(threads));
+        self
+    }
+
+    pub fn finder(&self) -> &ConfigFinder {
+        &self.finder
+    }
+
+    pub async fn config(&self) -> Result<Config, Error> {
+        let finder = self.finder.clone();
+        self.run(move || finder.config()).await
+    }
+
+    pub async fn typed_config<T: DeserializeOwned + Send + 'static>(&self) -> Result<T, Error> {
+        let finder = self.finder.clone();
+        self.run(move || finder.typed_config()).await
+    }
+
+    pub async fn is_stale(&self, config: Config) -> bool {
+        let finder = self.finder.clone();
+        self.run(move || finder.is_stale(&config)).await
+    }
+
+    pub fn files(&self) -> impl Stream<Item = PathBuf> + Send + Unpin + 'static {
+        let finder = self.finder.clone();
+        Files {
+            pending: Some(self.run(move || finder.files().collect())),
+            files: Vec::new().into_iter(),
+        }
+    }
+
+    fn run<T, F>(&self, f: F) -> Pending<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let slot = Arc::new(Mutex::new(Slot {
+            value: None,
+            waker: None,
+            done: false,
+        }));
+
+        let filled = slot.clone();
+        self.spawner.spawn(Box::new(move || {
+            let value = f();
+            let mut slot = filled.lock().expect("task slot lock poisoned");
+            slot.value = Some(value);
+            slot.done = true;
+            if let Some(waker) = slot.waker.take() {
+                waker.wake();
+            }
+        }));
+
+        Pending(slot)
+    }
+}
+
+impl From<ConfigFinder> for AsyncConfigFinder {
+    fn from(finder: ConfigFinder) -> AsyncConfigFinder {
+        AsyncConfigFinder::new(finder)
+    }
+}
+
+struct Slot<T> {
+    value: Option<T>,
+    waker: Option<Waker>,
+    done: bool,
+}
+
+// The result of a spawned task
+struct Pending<T>(Arc<Mutex<Slot<T>>>);
+
+impl<T> Future for Pending<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut slot = self.0.lock().expect("task slot lock poisoned");
+        match slot.value.take() {
+            Some(value) => Poll::Ready(value),
+            None if slot.done => panic!("task result was already taken"),
+            None => {
+                slot.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+struct Files {
+    pending: Option<Pending<Vec<PathBuf>>>,
+    files: std::vec::IntoIter<PathBuf>,
+}
+
+impl Stream for Files {
+    type Item = PathBuf;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<PathBuf>> {
+        if let Some(pending) = self.pending.as_mut() {
+            match Pin::new(pending).poll(cx) {
+                Poll::Ready(files) => {
+                    self.pending = None;
+                    self.files = files.into_iter();
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+
+        Poll::Ready(self.files.next())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AsyncConfigFinder, BlockingTask, Spawner, ThreadPool};
+    use crate::ConfigBuilder;
+    use std::{
+        future::Future,
+        pin::pin,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        task::{Context, Poll, Wake, Waker},
+        thread::Thread,
+    };
+
+    struct Unpark(Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        let waker = Waker::from(Arc::new(Unpark(std::thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            if let Poll::Ready(value) = future.as_mut().poll(&mut cx) {
+                return value;
+            }
+            std::thread::park();
+        }
+    }
+
+    #[test]
+    fn tasks_run_on_the_given_spawner() {
+        let spawned = Arc::new(AtomicUsize::new(0));
+        let counter = spawned.clone();
+        let pool = ThreadPool::new(2);
+
+        let finder = AsyncConfigFinder::new(ConfigBuilder::new().build().unwrap()).with_spawner(
+            move |task: BlockingTask| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                pool.spawn(task);
+            },
+        );
+
+        block_on(finder.config()).unwrap();
+        assert_eq!(spawned.load(Ordering::SeqCst), 1);
+    }
+}
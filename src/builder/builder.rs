@@ -1,47 +1,163 @@
-use super::config_file::ConfigFile;
+#[cfg(feature = "async")]
+use super::async_finder::AsyncConfigFinder;
+#[cfg(feature = "cache")]
+use super::cache::Cache;
+#[cfg(feature = "schemars")]
+use super::schema::Schema;
+#[cfg(feature = "watch")]
+use super::watch::{WatchOptions, WatchableConfig};
+use super::{
+    check::CheckReport,
+    command::CommandResolver,
+    config_file::ConfigFile,
+    decode::{DecodePolicy, Encoding},
+    env::{EnvSource, ENV_PRIORITY, ENV_SEPARATOR},
+    extension,
+    layer::{order, Layer, LayerSource, ValueLayer},
+    lazy::LazyConfig,
+    limits::Limits,
+    loader::{Encoders, Loader},
+    merge::{ConflictPolicy, Merger},
+    pattern::{compile, expand, render},
+    plan::SourceDescriptor,
+    profile,
+    relative::PathKeys,
+    remap::Remap,
+    report::MergeReport,
+    scope::{Scope, Scopes, USER_PRIORITY},
+    search_plan::{SearchPlan, SearchStep},
+    sibling::SiblingPolicy,
+    sort::SortPolicy,
+    trace::{debug_from_env, Trace},
+    trust::{SourceRule, Trust},
+    validator::{validate, ValidationError, Validator},
+};
+#[cfg(feature = "chaos")]
+use crate::chaos::Chaos;
 use crate::config::Config;
 use crate::locator::locatorbox;
+use crate::path::{canonicalize, system_config_dir, user_config_dir, MATCH_OPTIONS};
+use crate::provenance::{Origins, Source};
+use crate::values::{expand_dotted_keys, insert_at};
+use crate::KeyPath;
 use crate::{
-    locator::{BoxLocator, DirLocator, Locator},
+    locator::{BoxLocator, DirLocator, LocatedItem, Locator, MatchStrategy},
     Error,
 };
+use crate::{Coercion, Violation};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
+#[cfg(feature = "watch")]
+use std::time::Duration;
 use std::{
     cmp::Ordering,
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{Arc, RwLock},
+    time::SystemTime,
 };
-use toback::{Encoder, Toback, TobackBuilder};
-use vaerdi::{merge, Map};
+use toback::{Encoder, TobackBuilder};
+use vaerdi::{Map, Value};
 
 #[derive(serde::Serialize)]
 struct Context {
     ext: String,
+    // Left out rather than null, so optional `{-profile}` segments are dropped when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    profile: Option<String>,
 }
 
 pub struct ConfigBuilder {
-    loader: TobackBuilder<Map>,
+    loader: Encoders,
+    scoped_loaders: Vec<(PathBuf, TobackBuilder<Map>)>,
+    encodings: Vec<(PathBuf, Encoding)>,
     search_paths: Vec<BoxLocator>,
     search_names: Vec<String>,
-    sort: Option<Box<dyn Fn(&PathBuf, &PathBuf) -> Ordering + Send + Sync>>,
+    sort: SortPolicy,
     filter: Option<Box<dyn Fn(&PathBuf) -> bool + Send + Sync>>,
+    predicate: Option<Box<dyn Fn(&ConfigFile<Map>) -> bool + Send + Sync>>,
     default: Option<Box<dyn Fn(&mut Config) + Send + Sync>>,
+    commands: Option<CommandResolver>,
+    layers: Vec<ValueLayer>,
+    ordered: Vec<Layer>,
+    limits: Limits,
+    merger: Merger,
+    debug: bool,
+    explicit: Option<PathBuf>,
+    siblings: SiblingPolicy,
+    expand_dotted: bool,
+    profile: Option<String>,
+    profile_parents: HashMap<String, String>,
+    strip_extensions: Vec<String>,
+    decode: DecodePolicy,
+    track_access: bool,
+    source_rules: Vec<SourceRule>,
+    remap: Remap,
+    path_keys: PathKeys,
+    validators: Vec<Validator>,
+    scopes: Scopes,
+    #[cfg(feature = "schemars")]
+    schema: Option<Schema>,
+    #[cfg(feature = "schemars")]
+    coerce: bool,
+    #[cfg(feature = "chaos")]
+    chaos: Option<Chaos>,
+    #[cfg(feature = "cache")]
+    cache: Option<Cache>,
+    #[cfg(feature = "watch")]
+    watch_recursion: Vec<(PathBuf, bool)>,
 }
 
 impl ConfigBuilder {
     pub fn new() -> ConfigBuilder {
         ConfigBuilder {
-            loader: TobackBuilder::default(),
+            loader: default_loader(),
+            scoped_loaders: Vec::default(),
+            encodings: Vec::default(),
             search_paths: Vec::default(),
             search_names: Vec::default(),
-            sort: None,
+            sort: SortPolicy::default(),
             filter: None,
+            predicate: None,
             default: None,
+            commands: None,
+            layers: Vec::default(),
+            ordered: Vec::default(),
+            limits: Limits::default(),
+            merger: Merger::default(),
+            debug: false,
+            explicit: None,
+            siblings: SiblingPolicy::default(),
+            expand_dotted: false,
+            profile: None,
+            profile_parents: HashMap::default(),
+            strip_extensions: Vec::default(),
+            decode: DecodePolicy::default(),
+            track_access: false,
+            source_rules: Vec::default(),
+            remap: Remap::default(),
+            path_keys: PathKeys::default(),
+            validators: Vec::default(),
+            scopes: Scopes::default(),
+            #[cfg(feature = "schemars")]
+            schema: None,
+            #[cfg(feature = "schemars")]
+            coerce: false,
+            #[cfg(feature = "chaos")]
+            chaos: None,
+            #[cfg(feature = "cache")]
+            cache: None,
+            #[cfg(feature = "watch")]
+            watch_recursion: Vec::default(),
         }
     }
 
+    pub fn from_spec(spec: &str) -> Result<ConfigBuilder, Error> {
+        let mut builder = ConfigBuilder::new();
+        super::spec::apply(&mut builder, spec)?;
+        Ok(builder)
+    }
+
     pub fn add_default<F>(&mut self, default: F) -> &mut Self
     where
         F: Fn(&mut Config) + Send + Sync + 'static,
@@ -74,23 +190,56 @@ impl ConfigBuilder {
     }
 
     pub fn with_search_path(self, path: impl Into<PathBuf>) -> Result<Self, Error> {
-        let mut path = path.into();
-
-        if !path.is_absolute() {
-            path = path.canonicalize()?;
-        }
-
+        let path = self.resolve_path(path.into())?;
         Ok(self.with_locator(DirLocator(path)))
     }
 
     pub fn add_search_path(&mut self, path: impl Into<PathBuf>) -> Result<&mut Self, Error> {
-        let mut path = path.into();
+        let path = self.resolve_path(path.into())?;
+        Ok(self.add_locator(DirLocator(path)))
+    }
+
+    /// Search paths, file layers and scope files given to the builder afterwards
+    /// that start with `from` are read from `to` instead, so fixture trees can
+    /// stand in for real roots. Locators passed to `with_locator`/`add_locator`
+    /// are used as given.
+    ///
+    /// Only the search plan and source descriptors map paths back to `from`.
+    /// [`Config::files`] and the `Source::File` origins hold the paths that were
+    /// read, so saving writes into the fixture tree.
+    pub fn with_root_remap(mut self, from: impl Into<PathBuf>, to: impl Into<PathBuf>) -> Self {
+        self.add_root_remap(from, to);
+        self
+    }
+
+    pub fn add_root_remap(
+        &mut self,
+        from: impl Into<PathBuf>,
+        to: impl Into<PathBuf>,
+    ) -> &mut Self {
+        self.remap.add(from.into(), to.into());
+        self
+    }
 
-        if !path.is_absolute() {
-            path = path.canonicalize()?;
+    fn resolve_path(&self, path: PathBuf) -> Result<PathBuf, Error> {
+        let path = self.remap.apply(path);
+
+        if path.is_absolute() {
+            Ok(path)
+        } else {
+            Ok(canonicalize(&path)?)
         }
+    }
 
-        Ok(self.add_locator(DirLocator(path)))
+    pub fn with_explicit_file(mut self, path: impl Into<PathBuf>) -> Result<Self, Error> {
+        self.set_explicit_file(path)?;
+        Ok(self)
+    }
+
+    pub fn set_explicit_file(&mut self, path: impl Into<PathBuf>) -> Result<&mut Self, Error> {
+        let path = self.resolve_path(path.into())?;
+        self.explicit = Some(path);
+        Ok(self)
     }
 
     pub fn with_locator<L: Locator + 'static>(mut self, locator: L) -> Self
@@ -111,8 +260,62 @@ impl ConfigBuilder {
         self
     }
 
+    /// Files and content from this locator are parsed only with the given encoders.
+    pub fn with_locator_encoders<L: Locator + 'static>(
+        mut self,
+        locator: L,
+        encoders: TobackBuilder<Map>,
+    ) -> Self
+    where
+        L::Error: std::error::Error + 'static,
+        L: Send + Sync,
+    {
+        self.add_locator_encoders(locator, encoders);
+        self
+    }
+
+    pub fn add_locator_encoders<L: Locator + 'static>(
+        &mut self,
+        locator: L,
+        encoders: TobackBuilder<Map>,
+    ) -> &mut Self
+    where
+        L::Error: std::error::Error + 'static,
+        L: Send + Sync,
+    {
+        self.scoped_loaders.push((locator.root().clone(), encoders));
+        self.add_locator(locator)
+    }
+
+    /// Files and content from this locator are transcoded from `encoding` to UTF-8
+    /// before the format encoders see them.
+    pub fn with_encoding<L: Locator + 'static>(mut self, locator: L, encoding: Encoding) -> Self
+    where
+        L::Error: std::error::Error + 'static,
+        L: Send + Sync,
+    {
+        self.add_encoding(locator, encoding);
+        self
+    }
+
+    pub fn add_encoding<L: Locator + 'static>(
+        &mut self,
+        locator: L,
+        encoding: Encoding,
+    ) -> &mut Self
+    where
+        L::Error: std::error::Error + 'static,
+        L: Send + Sync,
+    {
+        self.encodings.push((locator.root().clone(), encoding));
+        self.add_locator(locator)
+    }
+
+    /// Registers `encoder` for its extensions, replacing the encoder those
+    /// extensions had, e.g. `Json::with_duplicates(DuplicateKeys::Error)` in
+    /// place of the toback JSON encoder.
     pub fn with_encoder<L: Encoder<Map> + Send + Sync + 'static>(mut self, encoder: L) -> Self {
-        self.loader.add_encoder(encoder);
+        self.loader.add(encoder);
         self
     }
 
@@ -120,7 +323,7 @@ impl ConfigBuilder {
         &mut self,
         encoder: L,
     ) -> &mut Self {
-        self.loader.add_encoder(encoder);
+        self.loader.add(encoder);
         self
     }
 
@@ -128,7 +331,7 @@ impl ConfigBuilder {
         mut self,
         sort: F,
     ) -> Self {
-        self.sort = Some(Box::new(sort));
+        self.sort = SortPolicy::Custom(Box::new(sort));
         self
     }
 
@@ -136,197 +339,2037 @@ impl ConfigBuilder {
         &mut self,
         sort: F,
     ) -> &mut Self {
-        self.sort = Some(Box::new(sort));
+        self.sort = SortPolicy::Custom(Box::new(sort));
         self
     }
 
-    pub fn with_filter<F: 'static + Fn(&PathBuf) -> bool + Send + Sync>(
+    pub fn with_sorting_by_source<
+        F: 'static + Fn(&SourceDescriptor, &SourceDescriptor) -> Ordering + Send + Sync,
+    >(
         mut self,
-        filter: F,
+        sort: F,
     ) -> Self {
-        self.filter = Some(Box::new(filter));
+        self.sort = SortPolicy::BySource(Box::new(sort));
         self
     }
 
-    pub fn set_filter<F: 'static + Fn(&PathBuf) -> bool + Send + Sync>(
+    pub fn set_sorting_by_source<
+        F: 'static + Fn(&SourceDescriptor, &SourceDescriptor) -> Ordering + Send + Sync,
+    >(
         &mut self,
-        filter: F,
+        sort: F,
     ) -> &mut Self {
-        self.filter = Some(Box::new(filter));
+        self.sort = SortPolicy::BySource(Box::new(sort));
         self
     }
 
-    pub fn build_config(self) -> Result<Config, Error> {
-        self.build()?.config()
+    pub fn with_sort_policy(mut self, policy: SortPolicy) -> Self {
+        self.sort = policy;
+        self
     }
 
-    pub fn build(self) -> Result<ConfigFinder, Error> {
-        self.build_with(|ext| Context {
-            ext: ext.to_string(),
-        })
+    pub fn set_sort_policy(&mut self, policy: SortPolicy) -> &mut Self {
+        self.sort = policy;
+        self
     }
 
-    pub fn build_with<C: Serialize, F: Fn(&str) -> C>(
-        self,
-        create_ctx: F,
-    ) -> Result<ConfigFinder, Error> {
-        let mut templates = tinytemplate::TinyTemplate::new();
+    pub fn with_sibling_policy(mut self, policy: SiblingPolicy) -> Self {
+        self.siblings = policy;
+        self
+    }
+
+    pub fn set_sibling_policy(&mut self, policy: SiblingPolicy) -> &mut Self {
+        self.siblings = policy;
+        self
+    }
+
+    pub fn with_dotted_key_expansion(mut self, expand: bool) -> Self {
+        self.expand_dotted = expand;
+        self
+    }
+
+    pub fn set_dotted_key_expansion(&mut self, expand: bool) -> &mut Self {
+        self.expand_dotted = expand;
+        self
+    }
+
+    pub fn with_stripped_extension(mut self, ext: impl ToString) -> Self {
+        self.add_stripped_extension(ext);
+        self
+    }
+
+    pub fn add_stripped_extension(&mut self, ext: impl ToString) -> &mut Self {
+        self.strip_extensions.push(ext.to_string());
+        self
+    }
+
+    pub fn with_source_trust(mut self, root: impl Into<PathBuf>, trust: Trust) -> Self {
+        self.set_source_trust(root, trust);
+        self
+    }
+
+    pub fn set_source_trust(&mut self, root: impl Into<PathBuf>, trust: Trust) -> &mut Self {
+        self.source_rule(root.into()).trust = trust;
+        self
+    }
+
+    /// Values from files under `root` are only kept when their key path matches one of
+    /// the `prefixes` globs, e.g. `plugin.*`.
+    pub fn with_source_restriction(
+        mut self,
+        root: impl Into<PathBuf>,
+        prefixes: &[&str],
+    ) -> Result<Self, Error> {
+        self.add_source_restriction(root, prefixes)?;
+        Ok(self)
+    }
+
+    pub fn add_source_restriction(
+        &mut self,
+        root: impl Into<PathBuf>,
+        prefixes: &[&str],
+    ) -> Result<&mut Self, Error> {
+        self.source_rule(root.into()).restrict_to(prefixes)?;
+        Ok(self)
+    }
+
+    /// String values under keys matching `pattern` are resolved against the
+    /// directory of the file that set them.
+    pub fn with_path_key(mut self, pattern: &str) -> Result<Self, Error> {
+        self.add_path_key(pattern)?;
+        Ok(self)
+    }
+
+    pub fn add_path_key(&mut self, pattern: &str) -> Result<&mut Self, Error> {
+        self.path_keys.add(pattern)?;
+        Ok(self)
+    }
+
+    /// Runs on the merged config before it is returned. All validators run, and
+    /// their errors are reported together.
+    pub fn with_validator<F>(mut self, validator: F) -> Self
+    where
+        F: Fn(&Config) -> Result<(), ValidationError> + Send + Sync + 'static,
+    {
+        self.add_validator(validator);
+        self
+    }
+
+    pub fn add_validator<F>(&mut self, validator: F) -> &mut Self
+    where
+        F: Fn(&Config) -> Result<(), ValidationError> + Send + Sync + 'static,
+    {
+        self.validators.push(Box::new(validator));
+        self
+    }
 
-        let search_names = self.search_names;
+    /// Searches the read only system directory, e.g. `/etc/<app>`, and layers the
+    /// user file, e.g. `~/.config/<app>/<file_name>`, over it. Persisted changes
+    /// only ever go to the user file.
+    pub fn with_app_config(mut self, app: &str, file_name: &str) -> Self {
+        self.set_app_config(app, file_name);
+        self
+    }
 
-        for search_name in &search_names {
-            templates
-                .add_template(&search_name, &search_name)
-                .expect("");
+    pub fn set_app_config(&mut self, app: &str, file_name: &str) -> &mut Self {
+        let system = self.remap.apply(system_config_dir().join(app));
+        self.scopes.system.push(system.clone());
+        self.search_paths.push(locatorbox(DirLocator(system)));
+
+        if let Some(dir) = user_config_dir() {
+            let file = self.remap.apply(dir.join(app).join(file_name));
+            self.layers.push(ValueLayer {
+                name: file.display().to_string(),
+                priority: USER_PRIORITY,
+                source: LayerSource::OptionalFile(file.clone()),
+            });
+            self.scopes.user = Some(file);
         }
 
-        let loader = Arc::new(self.loader.build());
+        self
+    }
 
-        tracing::debug!("loaders registered: {:?}", loader.extensions());
+    fn source_rule(&mut self, root: PathBuf) -> &mut SourceRule {
+        let root = match self.resolve_path(root.clone()) {
+            Ok(root) => root,
+            Err(_) => root,
+        };
 
-        let search_names = loader
-            .extensions()
-            .iter()
-            .flat_map(|ext| {
-                let ctx = create_ctx(ext);
-                search_names
-                    .iter()
-                    .map(|m| {
-                        templates
-                            .render(m, &ctx)
-                            .map_err(|err| Error::Unknown(Box::new(err)))
-                    })
-                    .collect::<Vec<_>>()
-            })
-            .collect::<Result<Vec<_>, Error>>()?;
+        let idx = match self.source_rules.iter().position(|m| m.root == root) {
+            Some(idx) => idx,
+            None => {
+                self.source_rules.push(SourceRule::new(root));
+                self.source_rules.len() - 1
+            }
+        };
 
-        tracing::debug!("using search names: {:?}", search_names);
+        &mut self.source_rules[idx]
+    }
 
-        let patterns = search_names
-            .iter()
-            .map(|p| glob::Pattern::new(p).unwrap())
-            .collect::<Vec<_>>();
+    pub fn with_access_tracking(mut self, track: bool) -> Self {
+        self.track_access = track;
+        self
+    }
 
-        Ok(ConfigFinder(Arc::new(ConfigFinderInner {
-            patterns,
-            locators: self.search_paths,
-            loader,
-            filter: self.filter,
-            sorter: self.sort,
-            default: self.default,
-        })))
+    pub fn set_access_tracking(&mut self, track: bool) -> &mut Self {
+        self.track_access = track;
+        self
     }
-}
 
-pub(crate) struct ConfigFinderInner {
-    patterns: Vec<glob::Pattern>,
-    pub locators: Vec<BoxLocator>,
-    loader: Arc<Toback<Map>>,
-    filter: Option<Box<dyn Fn(&PathBuf) -> bool + Send + Sync>>,
-    sorter: Option<Box<dyn Fn(&PathBuf, &PathBuf) -> Ordering + Send + Sync>>,
-    default: Option<Box<dyn Fn(&mut Config) + Send + Sync>>,
-}
+    pub fn with_decode_policy(mut self, policy: DecodePolicy) -> Self {
+        self.decode = policy;
+        self
+    }
 
-#[derive(Clone)]
-pub struct ConfigFinder(pub(crate) Arc<ConfigFinderInner>);
+    pub fn set_decode_policy(&mut self, policy: DecodePolicy) -> &mut Self {
+        self.decode = policy;
+        self
+    }
 
-impl ConfigFinder {
-    pub fn files<'a>(&'a self) -> impl Iterator<Item = PathBuf> + 'a {
-        find_files(&self.0.locators, &self.0.patterns)
+    pub fn with_profile(mut self, profile: impl ToString) -> Self {
+        self.set_profile(profile);
+        self
     }
 
-    pub fn config_files<T: DeserializeOwned + Serialize + 'static>(
-        &self,
-    ) -> impl Iterator<Item = Result<ConfigFile<T>, Error>> + '_ {
-        let loader = TobackBuilder::<T>::default().build();
-
-        self.files()
-            .filter_map(|search_path| {
-                if let Some(filter) = &self.0.filter {
-                    if filter(&search_path) {
-                        Some(search_path)
-                    } else {
-                        None
-                    }
-                } else {
-                    Some(search_path)
-                }
-            })
-            .map(move |search_path| {
-                let ext = match search_path.extension() {
-                    Some(ext) => ext.to_string_lossy(),
-                    None => "json".into(),
-                };
+    pub fn set_profile(&mut self, profile: impl ToString) -> &mut Self {
+        self.profile = Some(profile.to_string());
+        self
+    }
 
-                let data = std::fs::read(&search_path)?;
+    pub fn with_profile_parent(mut self, profile: impl ToString, parent: impl ToString) -> Self {
+        self.add_profile_parent(profile, parent);
+        self
+    }
 
-                let out = loader.load(&data, &ext)?;
+    pub fn add_profile_parent(
+        &mut self,
+        profile: impl ToString,
+        parent: impl ToString,
+    ) -> &mut Self {
+        self.profile_parents
+            .insert(profile.to_string(), parent.to_string());
+        self
+    }
 
-                tracing::trace!("found path: {:?}", search_path);
+    pub fn with_filter<F: 'static + Fn(&PathBuf) -> bool + Send + Sync>(
+        mut self,
+        filter: F,
+    ) -> Self {
+        self.filter = Some(Box::new(filter));
+        self
+    }
 
-                Result::<_, Error>::Ok(ConfigFile {
-                    config: out,
-                    path: search_path,
-                })
-            })
+    pub fn set_filter<F: 'static + Fn(&PathBuf) -> bool + Send + Sync>(
+        &mut self,
+        filter: F,
+    ) -> &mut Self {
+        self.filter = Some(Box::new(filter));
+        self
     }
 
-    pub fn config(&self) -> Result<Config, Error> {
-        let mut configs = self.config_files().collect::<Result<Vec<_>, _>>()?;
+    /// Like a filter, but decides on the parsed file, e.g. to skip files that
+    /// belong to another application.
+    pub fn with_file_predicate<F: 'static + Fn(&ConfigFile<Map>) -> bool + Send + Sync>(
+        mut self,
+        predicate: F,
+    ) -> Self {
+        self.predicate = Some(Box::new(predicate));
+        self
+    }
 
-        if let Some(sorter) = &self.0.sorter {
-            configs.sort_by(|a, b| sorter(&a.path, &b.path));
-        } else {
-            configs.sort_by(|a, b| a.path.cmp(&b.path));
-        }
+    pub fn set_file_predicate<F: 'static + Fn(&ConfigFile<Map>) -> bool + Send + Sync>(
+        &mut self,
+        predicate: F,
+    ) -> &mut Self {
+        self.predicate = Some(Box::new(predicate));
+        self
+    }
 
-        let files = configs.iter().map(|m| m.path.clone()).collect();
+    pub fn with_command_resolver(mut self, resolver: CommandResolver) -> Self {
+        self.commands = Some(resolver);
+        self
+    }
 
-        let mut config = Config::default();
+    pub fn set_command_resolver(&mut self, resolver: CommandResolver) -> &mut Self {
+        self.commands = Some(resolver);
+        self
+    }
 
-        if let Some(default) = &self.0.default {
-            default(&mut config);
-        }
+    pub fn with_value_layer(
+        mut self,
+        name: impl ToString,
+        priority: i32,
+        value: impl Into<Value>,
+    ) -> Self {
+        self.add_value_layer(name, priority, value);
+        self
+    }
 
-        Ok(Config {
-            inner: merge_config(config.inner, configs),
-            files,
-        })
+    pub fn add_value_layer(
+        &mut self,
+        name: impl ToString,
+        priority: i32,
+        value: impl Into<Value>,
+    ) -> &mut Self {
+        self.layers.push(ValueLayer {
+            name: name.to_string(),
+            priority,
+            source: LayerSource::Value(value.into()),
+        });
+        self
     }
 
-    pub fn matches(&self, path: &Path) -> bool {
-        let path = path.file_name().unwrap();
-        for pattern in &self.0.patterns {
-            if pattern.matches_path(Path::new(path)) {
-                return true;
-            }
-        }
-        false
+    pub fn with_layer(mut self, layer: Layer) -> Self {
+        self.add_layer(layer);
+        self
     }
 
-    pub fn matche_any(&self, paths: &[PathBuf]) -> bool {
-        for path in paths {
-            if self.matches(path) {
-                return true;
-            }
-        }
-        false
+    /// Layers added here are merged in the order given, around the discovered files
+    /// when a Layer::Discovered is among them, otherwise after them.
+    pub fn add_layer(&mut self, layer: Layer) -> &mut Self {
+        let layer = match layer {
+            Layer::File(path) => match self.resolve_path(path.clone()) {
+                Ok(path) => Layer::File(path),
+                Err(_) => Layer::File(path),
+            },
+            layer => layer,
+        };
+        self.ordered.push(layer);
+        self
     }
-}
 
-fn merge_config(mut config: Map, files: Vec<ConfigFile<Map>>) -> Map {
-    for file in files.into_iter() {
-        for (key, value) in file.config.into_iter() {
-            if !config.contains(&key) {
-                config.insert(key, value);
-            } else {
-                let mut prev = config.get_mut(&key).unwrap();
-                merge(&mut prev, value);
-            }
-        }
+    /// Reads variables starting with `prefix` over the files, with `__`
+    /// between key segments: `MYAPP_SERVERS__0__HOST` sets `servers[0].host`.
+    ///
+    /// The separator is `__` rather than `_`, so keys such as `max_connections`
+    /// can be set (`MYAPP_POOL__MAX_CONNECTIONS`). For `MYAPP_SERVERS_0_HOST`,
+    /// with keys free of underscores, use [`with_env_layer`](Self::with_env_layer)
+    /// with `"_"` as the separator.
+    pub fn with_env_prefix(mut self, prefix: impl ToString) -> Self {
+        self.add_env_prefix(prefix);
+        self
+    }
+
+    /// Like [`with_env_prefix`](Self::with_env_prefix). Environment variables
+    /// override files unless given a lower priority with [`add_env_layer`](Self::add_env_layer).
+    pub fn add_env_prefix(&mut self, prefix: impl ToString) -> &mut Self {
+        self.add_env_layer(prefix, ENV_SEPARATOR, ENV_PRIORITY)
+    }
+
+    pub fn with_env_layer(
+        mut self,
+        prefix: impl ToString,
+        separator: impl ToString,
+        priority: i32,
+    ) -> Self {
+        self.add_env_layer(prefix, separator, priority);
+        self
+    }
+
+    pub fn add_env_layer(
+        &mut self,
+        prefix: impl ToString,
+        separator: impl ToString,
+        priority: i32,
+    ) -> &mut Self {
+        let prefix = prefix.to_string();
+        self.layers.push(ValueLayer {
+            name: format!("env:{}", prefix),
+            priority,
+            source: LayerSource::Env(EnvSource {
+                prefix,
+                separator: separator.to_string(),
+            }),
+        });
+        self
+    }
+
+    pub fn with_max_depth(mut self, depth: usize) -> Self {
+        self.limits.max_depth = Some(depth);
+        self
+    }
+
+    pub fn set_max_depth(&mut self, depth: usize) -> &mut Self {
+        self.limits.max_depth = Some(depth);
+        self
     }
 
-    config
+    pub fn with_max_keys(mut self, keys: usize) -> Self {
+        self.limits.max_keys = Some(keys);
+        self
+    }
+
+    pub fn set_max_keys(&mut self, keys: usize) -> &mut Self {
+        self.limits.max_keys = Some(keys);
+        self
+    }
+
+    pub fn with_conflict_policy(mut self, policy: ConflictPolicy) -> Self {
+        self.merger.policy = policy;
+        self
+    }
+
+    pub fn set_conflict_policy(&mut self, policy: ConflictPolicy) -> &mut Self {
+        self.merger.policy = policy;
+        self
+    }
+
+    pub fn with_conflict_policy_for(
+        mut self,
+        prefix: &str,
+        policy: ConflictPolicy,
+    ) -> Result<Self, Error> {
+        self.add_conflict_policy_for(prefix, policy)?;
+        Ok(self)
+    }
+
+    pub fn add_conflict_policy_for(
+        &mut self,
+        prefix: &str,
+        policy: ConflictPolicy,
+    ) -> Result<&mut Self, Error> {
+        self.merger.prefixes.push((prefix.parse()?, policy));
+        Ok(self)
+    }
+
+    /// Strings that override a list are split on `delimiter` into a list.
+    pub fn with_list_delimiter(mut self, delimiter: char) -> Self {
+        self.merger.delimiter = Some(delimiter);
+        self
+    }
+
+    pub fn set_list_delimiter(&mut self, delimiter: char) -> &mut Self {
+        self.merger.delimiter = Some(delimiter);
+        self
+    }
+
+    pub fn with_list_delimiter_for(mut self, prefix: &str, delimiter: char) -> Result<Self, Error> {
+        self.add_list_delimiter_for(prefix, delimiter)?;
+        Ok(self)
+    }
+
+    pub fn add_list_delimiter_for(
+        &mut self,
+        prefix: &str,
+        delimiter: char,
+    ) -> Result<&mut Self, Error> {
+        self.merger.delimiters.push((prefix.parse()?, delimiter));
+        Ok(self)
+    }
+
+    pub fn debug(mut self, debug: bool) -> Self {
+        self.debug = debug;
+        self
+    }
+
+    pub fn set_debug(&mut self, debug: bool) -> &mut Self {
+        self.debug = debug;
+        self
+    }
+
+    #[cfg(feature = "schemars")]
+    pub fn with_schema_of<T: schemars::JsonSchema + DeserializeOwned>(mut self) -> Self {
+        self.schema = Some(Schema::new::<T>());
+        self
+    }
+
+    #[cfg(feature = "schemars")]
+    pub fn with_coercion(mut self, coerce: bool) -> Self {
+        self.coerce = coerce;
+        self
+    }
+
+    #[cfg(feature = "schemars")]
+    pub fn set_coercion(&mut self, coerce: bool) -> &mut Self {
+        self.coerce = coerce;
+        self
+    }
+
+    #[cfg(feature = "schemars")]
+    pub fn set_schema_of<T: schemars::JsonSchema + DeserializeOwned>(&mut self) -> &mut Self {
+        self.schema = Some(Schema::new::<T>());
+        self
+    }
+
+    #[cfg(feature = "cache")]
+    pub fn with_cache_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.set_cache_file(path);
+        self
+    }
+
+    #[cfg(feature = "cache")]
+    pub fn set_cache_file(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.cache = Some(Cache::new(path.into()));
+        self
+    }
+
+    #[cfg(feature = "chaos")]
+    pub fn with_chaos(mut self, chaos: Chaos) -> Self {
+        self.chaos = Some(chaos);
+        self
+    }
+
+    #[cfg(feature = "chaos")]
+    pub fn set_chaos(&mut self, chaos: Chaos) -> &mut Self {
+        self.chaos = Some(chaos);
+        self
+    }
+
+    /// Overrides whether the locator at `root` is watched recursively. By default
+    /// that follows the depth the locator searches.
+    #[cfg(feature = "watch")]
+    pub fn with_watch_recursion(mut self, root: impl Into<PathBuf>, recursive: bool) -> Self {
+        self.set_watch_recursion(root, recursive);
+        self
+    }
+
+    #[cfg(feature = "watch")]
+    pub fn set_watch_recursion(&mut self, root: impl Into<PathBuf>, recursive: bool) -> &mut Self {
+        let root = self.remap.apply(root.into());
+        self.watch_recursion.retain(|(m, _)| *m != root);
+        self.watch_recursion.push((root, recursive));
+        self
+    }
+
+    pub fn build_config(self) -> Result<Config, Error> {
+        self.build()?.config()
+    }
+
+    pub fn build_typed<T: DeserializeOwned>(self) -> Result<T, Error> {
+        self.build()?.typed_config()
+    }
+
+    pub fn build(self) -> Result<ConfigFinder, Error> {
+        let profiles = profile::chain(self.profile.as_deref(), &self.profile_parents)?;
+
+        let contexts = |ext: &str| {
+            if profiles.is_empty() {
+                return vec![Context {
+                    ext: ext.to_string(),
+                    profile: None,
+                }];
+            }
+
+            profiles
+                .iter()
+                .map(|profile| Context {
+                    ext: ext.to_string(),
+                    profile: Some(profile.clone()),
+                })
+                .collect()
+        };
+
+        self.build_contexts(contexts, profiles.clone())
+    }
+
+    #[cfg(feature = "async")]
+    pub fn build_async(self) -> Result<AsyncConfigFinder, Error> {
+        Ok(AsyncConfigFinder::new(self.build()?))
+    }
+
+    pub fn build_with<C: Serialize, F: Fn(&str) -> C>(
+        self,
+        create_ctx: F,
+    ) -> Result<ConfigFinder, Error> {
+        self.build_contexts(|ext| vec![create_ctx(ext)], Vec::default())
+    }
+
+    fn build_contexts<C: Serialize, F: Fn(&str) -> Vec<C>>(
+        self,
+        create_ctx: F,
+        profiles: Vec<String>,
+    ) -> Result<ConfigFinder, Error> {
+        let loader = Arc::new(self.loader.build());
+
+        tracing::debug!("loaders registered: {:?}", loader.extensions());
+
+        let contexts = loader
+            .extensions()
+            .iter()
+            .flat_map(|ext| create_ctx(ext))
+            .map(|ctx| vaerdi::ser::to_value(ctx).map_err(|err| Error::Unknown(Box::new(err))))
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let names = self
+            .search_names
+            .iter()
+            .flat_map(|m| expand(m))
+            .collect::<Vec<_>>();
+
+        let search_names = render(&names, &contexts)?;
+
+        tracing::debug!(
+            "using search names: {:?}",
+            search_names.iter().map(|m| &m.name).collect::<Vec<_>>()
+        );
+
+        let patterns = compile(&search_names)?;
+        let profile_ranks = profile::ranks(&search_names, &profiles);
+
+        let mut layers = self.layers;
+        layers.extend(order(self.ordered));
+        layers.sort_by_key(|layer| layer.priority);
+
+        Ok(ConfigFinder(Arc::new(ConfigFinderInner {
+            patterns: RwLock::new(patterns),
+            profile_ranks: RwLock::new(profile_ranks),
+            contexts,
+            locators: RwLock::new(self.search_paths),
+            loader,
+            scoped_loaders: self
+                .scoped_loaders
+                .into_iter()
+                .map(|(root, loader)| (root, Arc::new(Loader::from(loader.build()))))
+                .collect(),
+            encodings: self.encodings,
+            filter: self.filter,
+            predicate: self.predicate,
+            sorter: self.sort,
+            default: self.default,
+            commands: self.commands,
+            layers,
+            limits: self.limits,
+            merger: self.merger,
+            debug: self.debug || debug_from_env(),
+            explicit: self.explicit,
+            siblings: self.siblings,
+            expand_dotted: self.expand_dotted,
+            strip_extensions: self.strip_extensions,
+            decode: self.decode,
+            track_access: self.track_access,
+            source_rules: self.source_rules,
+            remap: self.remap,
+            path_keys: self.path_keys,
+            validators: self.validators,
+            scopes: self.scopes,
+            profiles,
+            #[cfg(feature = "schemars")]
+            schema: self.schema,
+            #[cfg(feature = "schemars")]
+            coerce: self.coerce,
+            #[cfg(feature = "chaos")]
+            chaos: self.chaos,
+            #[cfg(feature = "cache")]
+            cache: self.cache,
+            #[cfg(feature = "watch")]
+            watch_recursion: self.watch_recursion,
+        })))
+    }
+}
+
+pub(crate) struct ConfigFinderInner {
+    patterns: RwLock<Vec<glob::Pattern>>,
+    // Rendered profile names by their rank in the profile chain
+    profile_ranks: RwLock<HashMap<String, usize>>,
+    contexts: Vec<Value>,
+    locators: RwLock<Vec<BoxLocator>>,
+    loader: Arc<Loader>,
+    scoped_loaders: Vec<(PathBuf, Arc<Loader>)>,
+    encodings: Vec<(PathBuf, Encoding)>,
+    filter: Option<Box<dyn Fn(&PathBuf) -> bool + Send + Sync>>,
+    predicate: Option<Box<dyn Fn(&ConfigFile<Map>) -> bool + Send + Sync>>,
+    sorter: SortPolicy,
+    default: Option<Box<dyn Fn(&mut Config) + Send + Sync>>,
+    commands: Option<CommandResolver>,
+    layers: Vec<ValueLayer>,
+    limits: Limits,
+    merger: Merger,
+    debug: bool,
+    explicit: Option<PathBuf>,
+    siblings: SiblingPolicy,
+    expand_dotted: bool,
+    strip_extensions: Vec<String>,
+    decode: DecodePolicy,
+    track_access: bool,
+    source_rules: Vec<SourceRule>,
+    remap: Remap,
+    path_keys: PathKeys,
+    validators: Vec<Validator>,
+    scopes: Scopes,
+    profiles: Vec<String>,
+    #[cfg(feature = "schemars")]
+    schema: Option<Schema>,
+    #[cfg(feature = "schemars")]
+    coerce: bool,
+    #[cfg(feature = "chaos")]
+    chaos: Option<Chaos>,
+    #[cfg(feature = "cache")]
+    cache: Option<Cache>,
+    #[cfg(feature = "watch")]
+    watch_recursion: Vec<(PathBuf, bool)>,
+}
+
+#[derive(Clone)]
+pub struct ConfigFinder(pub(crate) Arc<ConfigFinderInner>);
+
+impl ConfigFinder {
+    pub fn files<'a>(&'a self) -> impl Iterator<Item = PathBuf> + 'a {
+        // An explicit file replaces discovery entirely
+        let files = match &self.0.explicit {
+            Some(path) => vec![path.clone()],
+            None => {
+                let locators = self.0.locators.read().expect("locators lock poisoned");
+                let patterns = self.0.patterns.read().expect("patterns lock poisoned");
+                let files: Vec<_> = find_files(&locators, &patterns).collect();
+                files
+            }
+        };
+
+        files.into_iter()
+    }
+
+    /// Takes effect on the next load. A WatchableConfig doesn't watch locators
+    /// added here, use its own add_locator for that.
+    pub fn add_locator<L: Locator + 'static>(&self, locator: L)
+    where
+        L::Error: std::error::Error + 'static,
+        L: Send + Sync,
+    {
+        self.0
+            .locators
+            .write()
+            .expect("locators lock poisoned")
+            .push(locatorbox(locator));
+    }
+
+    pub fn add_search_path(&self, path: impl Into<PathBuf>) -> Result<(), Error> {
+        let path = self.resolve_path(path.into())?;
+        self.add_locator(DirLocator(path));
+        Ok(())
+    }
+
+    pub fn remove_locator(&self, root: &Path) -> bool {
+        let mut locators = self.0.locators.write().expect("locators lock poisoned");
+        let len = locators.len();
+        locators.retain(|locator| locator.root() != root);
+        locators.len() != len
+    }
+
+    pub fn add_name_pattern(&self, pattern: &str) -> Result<(), Error> {
+        let names = expand(pattern);
+        let rendered = render(&names, &self.0.contexts)?;
+        let added = compile(&rendered)?;
+
+        self.0
+            .profile_ranks
+            .write()
+            .expect("profile ranks lock poisoned")
+            .extend(profile::ranks(&rendered, &self.0.profiles));
+
+        let mut patterns = self.0.patterns.write().expect("patterns lock poisoned");
+        for pattern in added {
+            if !patterns.iter().any(|m| m.as_str() == pattern.as_str()) {
+                patterns.push(pattern);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn filtered_files(&self, files: Vec<PathBuf>) -> Vec<PathBuf> {
+        // An explicit file isn't subject to the filter
+        if self.0.explicit.is_some() {
+            return files;
+        }
+
+        files
+            .into_iter()
+            .filter(|search_path| self.accepts(search_path))
+            .collect()
+    }
+
+    fn accepts(&self, path: &PathBuf) -> bool {
+        match &self.0.filter {
+            Some(filter) => filter(path),
+            None => true,
+        }
+    }
+
+    // Discovered files after filtering and the sibling policy, in merge order
+    pub(super) fn selected_files(&self) -> Result<Vec<PathBuf>, Error> {
+        self.select(self.filtered_files(self.files().collect()))
+    }
+
+    // Runs the steps of a plan as they were planned, even if the finder's patterns
+    // have changed since. Steps whose locator has been removed find nothing
+    pub(super) fn run_steps(&self, steps: &[SearchStep]) -> Result<Vec<PathBuf>, Error> {
+        let locators = self.0.locators.read().expect("locators lock poisoned");
+
+        let mut seen = HashSet::new();
+        let mut files = Vec::new();
+
+        for step in steps {
+            let root = self.0.remap.apply(step.root.clone());
+            let patterns = step
+                .patterns
+                .iter()
+                .map(|m| glob::Pattern::new(m).map_err(|err| Error::Unknown(Box::new(err))))
+                .collect::<Result<Vec<_>, Error>>()?;
+
+            for locator in locators.iter().filter(|m| *m.root() == root) {
+                let found = match locator.locate(&patterns) {
+                    Ok(found) => found,
+                    Err(_) => continue,
+                };
+
+                for path in found {
+                    if self.accepts(&path) && seen.insert(path.clone()) {
+                        files.push(path);
+                    }
+                }
+            }
+        }
+
+        drop(locators);
+        self.select(files)
+    }
+
+    pub(super) fn select(&self, files: Vec<PathBuf>) -> Result<Vec<PathBuf>, Error> {
+        let mut files = self.0.siblings.apply(files)?;
+        self.0.sorter.sort(&mut files, |path| self.describe(path));
+
+        // Profile rank comes first: a profile's files are merged after the files
+        // it specialises whatever the policy. The sort is stable, so the policy
+        // still orders files of the same rank
+        if !self.0.profiles.is_empty() {
+            let patterns = self.0.patterns.read().expect("patterns lock poisoned");
+            let ranks = self
+                .0
+                .profile_ranks
+                .read()
+                .expect("profile ranks lock poisoned");
+            files.sort_by_key(|path| profile::rank(&patterns, &ranks, path));
+        }
+        Ok(files)
+    }
+
+    // The locator root a file was found under and the pattern its name matched
+    fn describe(&self, path: &Path) -> SourceDescriptor {
+        let locators = self.0.locators.read().expect("locators lock poisoned");
+        let patterns = self.0.patterns.read().expect("patterns lock poisoned");
+
+        let root = locators
+            .iter()
+            .map(|locator| locator.root())
+            .find(|root| path.starts_with(root))
+            .cloned();
+
+        let pattern = path.file_name().and_then(|name| {
+            patterns
+                .iter()
+                .find(|pattern| pattern.matches_path_with(Path::new(name), MATCH_OPTIONS))
+                .map(|pattern| pattern.as_str().to_string())
+        });
+
+        SourceDescriptor::File {
+            path: self.0.remap.reverse(path),
+            root: root.map(|root| self.0.remap.reverse(&root)),
+            pattern,
+        }
+    }
+
+    pub fn plan(&self) -> SearchPlan {
+        let locators = self.0.locators.read().expect("locators lock poisoned");
+        let patterns = self.0.patterns.read().expect("patterns lock poisoned");
+
+        // An explicit file replaces discovery, so there is nothing to search
+        let steps = locators
+            .iter()
+            .filter(|_| self.0.explicit.is_none())
+            .map(|locator| SearchStep {
+                root: self.0.remap.reverse(locator.root()),
+                patterns: patterns.iter().map(|m| m.as_str().to_string()).collect(),
+                strategy: locator.strategy(&patterns),
+            })
+            .collect();
+
+        SearchPlan {
+            finder: self.clone(),
+            explicit: self.0.explicit.clone(),
+            steps,
+        }
+    }
+
+    pub fn merge_plan(&self) -> Result<Vec<SourceDescriptor>, Error> {
+        let mut plan = Vec::new();
+
+        if self.0.default.is_some() {
+            plan.push(SourceDescriptor::Defaults);
+        }
+
+        let layer = |layer: &ValueLayer| match layer.is_defaults() {
+            true => SourceDescriptor::Defaults,
+            false => SourceDescriptor::Layer {
+                name: layer.name.clone(),
+                priority: layer.priority,
+            },
+        };
+
+        plan.extend(
+            self.0
+                .layers
+                .iter()
+                .filter(|m| !m.overrides_files())
+                .map(layer),
+        );
+
+        // Only paths and content names, nothing is read or parsed here
+        let located = self.located();
+        let files = self.select(self.filtered_files(located.files))?;
+        plan.extend(files.iter().map(|path| self.describe(path)));
+
+        for content in located.contents {
+            plan.push(SourceDescriptor::Content {
+                name: content.name,
+                root: self.0.remap.reverse(&content.root),
+            });
+        }
+
+        plan.extend(
+            self.0
+                .layers
+                .iter()
+                .filter(|m| m.overrides_files())
+                .map(layer),
+        );
+
+        Ok(plan)
+    }
+
+    pub fn config_files<T: DeserializeOwned + Serialize + 'static>(
+        &self,
+    ) -> impl Iterator<Item = Result<ConfigFile<T>, Error>> + '_ {
+        // Loaded the same way merged files are, and skipping the ones the predicate vetoes
+        flatten_files(self.selected_files())
+            .map(move |path| path.and_then(|path| self.load_map(path)))
+            .filter(move |file| file.as_ref().map_or(true, |file| self.admits(file)))
+            .map(move |file| file.and_then(|file| self.typed_file(file)))
+    }
+
+    fn typed_file<T: DeserializeOwned>(
+        &self,
+        file: ConfigFile<Map>,
+    ) -> Result<ConfigFile<T>, Error> {
+        self.0.limits.check(&file.config, &file.path)?;
+
+        let config =
+            T::deserialize(Value::Map(file.config)).map_err(|error| Error::Deserialize {
+                files: vec![file.path.clone()],
+                error,
+            })?;
+
+        Ok(ConfigFile {
+            config,
+            path: file.path,
+        })
+    }
+
+    fn admits(&self, file: &ConfigFile<Map>) -> bool {
+        match &self.0.predicate {
+            Some(predicate) => predicate(file),
+            None => true,
+        }
+    }
+
+    // Names of the content items locators hand over, without parsing them
+    // Lists every locator once, through `items`, so directories aren't read a
+    // second time for the content they hand over
+    fn located(&self) -> Located {
+        let locators = self.0.locators.read().expect("locators lock poisoned");
+        let patterns = self.0.patterns.read().expect("patterns lock poisoned");
+
+        let mut seen = HashSet::new();
+        let mut located = Located::default();
+
+        for locator in locators.iter() {
+            let items = match locator.items(&patterns) {
+                Ok(items) => items,
+                Err(_) => continue,
+            };
+
+            for item in items {
+                match item {
+                    LocatedItem::Path(path) => {
+                        if seen.insert(path.clone()) {
+                            located.files.push(path);
+                        }
+                    }
+                    LocatedItem::Content {
+                        name,
+                        bytes,
+                        format,
+                    } => located.contents.push(Content {
+                        root: locator.root().clone(),
+                        name,
+                        bytes,
+                        format,
+                    }),
+                }
+            }
+        }
+
+        // An explicit file replaces discovery entirely
+        if let Some(path) = &self.0.explicit {
+            located.files = vec![path.clone()];
+        }
+
+        located
+    }
+
+    // Items locators hand over as content instead of paths, with the root of their locator
+    pub(super) fn contents(&self) -> Result<Vec<(PathBuf, String, Map)>, Error> {
+        self.parse_contents(self.located().contents)
+    }
+
+    fn parse_contents(&self, contents: Vec<Content>) -> Result<Vec<(PathBuf, String, Map)>, Error> {
+        let mut out = Vec::with_capacity(contents.len());
+
+        for Content {
+            root,
+            name,
+            bytes,
+            format,
+        } in contents
+        {
+            let loader = self.loader_for(&root);
+
+            let ext = match format {
+                Some(format) => format,
+                None => extension::detect(
+                    Path::new(&name),
+                    &loader.extensions(),
+                    &self.0.strip_extensions,
+                )?,
+            };
+
+            let bytes = self.load_options().intercept(Path::new(&name), bytes)?;
+            let bytes = self.0.decode.decode(
+                bytes,
+                Path::new(&name),
+                encoding_for(&self.0.encodings, &root),
+            )?;
+            let mut config = loader.load(&bytes, &ext)?;
+            if self.0.expand_dotted {
+                config = expand_dotted_keys(config);
+            }
+
+            out.push((root, name, config));
+        }
+
+        Ok(out)
+    }
+
+    fn resolve_path(&self, path: PathBuf) -> Result<PathBuf, Error> {
+        let path = self.0.remap.apply(path);
+
+        if path.is_absolute() {
+            Ok(path)
+        } else {
+            Ok(canonicalize(&path)?)
+        }
+    }
+
+    /// Runs one file through the same routing, decoding, transforms and limits as
+    /// discovery does, without merging it. Schema validation is left out, since a
+    /// single file is rarely a complete config.
+    pub fn load_file(&self, path: impl Into<PathBuf>) -> Result<ConfigFile<Map>, Error> {
+        let path = self.resolve_path(path.into())?;
+        let file = self.load_map(path)?;
+        self.0.limits.check(&file.config, &file.path)?;
+
+        Ok(file)
+    }
+
+    fn layer_map(&self, layer: &ValueLayer) -> Result<Map, Error> {
+        match &layer.source {
+            LayerSource::Value(Value::Map(map)) | LayerSource::Defaults(Value::Map(map)) => {
+                Ok(map.clone())
+            }
+            LayerSource::Value(_) | LayerSource::Defaults(_) => {
+                Err(Error::InvalidLayer(layer.name.clone()))
+            }
+            LayerSource::Env(env) => Ok(env.read()),
+            LayerSource::File(path) => Ok(self.load_map(path.clone())?.config),
+            LayerSource::OptionalFile(path) if !path.exists() => Ok(Map::default()),
+            LayerSource::OptionalFile(path) => Ok(self.load_map(path.clone())?.config),
+        }
+    }
+
+    fn load_options(&self) -> LoadOptions<'_> {
+        LoadOptions {
+            strip: &self.0.strip_extensions,
+            decode: self.0.decode,
+            encodings: &self.0.encodings,
+            #[cfg(feature = "chaos")]
+            chaos: self.0.chaos.as_ref(),
+        }
+    }
+
+    fn loader_for(&self, path: &Path) -> &Loader {
+        self.0
+            .scoped_loaders
+            .iter()
+            .find(|(root, _)| path.starts_with(root))
+            .map(|(_, loader)| &**loader)
+            .unwrap_or(&*self.0.loader)
+    }
+
+    fn load_map(&self, path: PathBuf) -> Result<ConfigFile<Map>, Error> {
+        let data = self.read_decoded(&path)?;
+        self.parse_decoded(path, &data)
+    }
+
+    pub(super) fn read_decoded(&self, path: &Path) -> Result<Vec<u8>, Error> {
+        read_decoded(path, &self.load_options())
+    }
+
+    // Parses a file read with read_decoded, the way load_map does
+    pub(super) fn parse_decoded(
+        &self,
+        path: PathBuf,
+        data: &[u8],
+    ) -> Result<ConfigFile<Map>, Error> {
+        let mut file = parse_decoded(self.loader_for(&path), path, data, &self.load_options())?;
+
+        if self.0.expand_dotted {
+            file.config = expand_dotted_keys(file.config);
+        }
+
+        if let Some(dir) = file.path.parent() {
+            self.0.path_keys.resolve(&mut file.config, dir);
+        }
+
+        Ok(file)
+    }
+
+    pub fn for_each_file<T, F>(&self, mut f: F) -> Result<(), Error>
+    where
+        T: DeserializeOwned + Serialize + 'static,
+        F: FnMut(ConfigFile<T>) -> Result<(), Error>,
+    {
+        for file in self.config_files::<T>() {
+            f(file?)?;
+        }
+        Ok(())
+    }
+
+    pub fn lazy(&self) -> Result<LazyConfig, Error> {
+        LazyConfig::new(self.clone())
+    }
+
+    // Whether a parsed file takes part in the merge. Files the predicate vetoes
+    // don't, and files over the limits are an error
+    pub(super) fn admit(&self, file: &ConfigFile<Map>) -> Result<bool, Error> {
+        if !self.admits(file) {
+            return Ok(false);
+        }
+        self.0.limits.check(&file.config, &file.path)?;
+        Ok(true)
+    }
+
+    // Merges a single top level key through the same steps as load_config.
+    // Schema validation and validators need the whole config, so they are left out
+    pub(super) fn merge_section(
+        &self,
+        key: &str,
+        files: &[&ConfigFile<Map>],
+        contents: &[(PathBuf, String, Map)],
+        prepared: &Prepared,
+    ) -> Result<Option<Value>, Error> {
+        let files = files
+            .iter()
+            .map(|file| ConfigFile {
+                config: section(&file.config, key),
+                path: file.path.clone(),
+            })
+            .collect();
+
+        let contents = contents
+            .iter()
+            .map(|(root, name, config)| (root.clone(), name.clone(), section(config, key)))
+            .collect();
+
+        let sources = Sources {
+            files,
+            contents,
+            vetoed: Vec::new(),
+        };
+
+        let config =
+            self.merge_maps(sources, Some(key), Some(prepared), &Trace::new(false), None)?;
+
+        Ok(config.inner.get(key).cloned())
+    }
+
+    pub fn config(&self) -> Result<Config, Error> {
+        let trace = Trace::new(self.0.debug);
+        self.load_config(&trace, None)
+            .map_err(|err| trace.attach(err))
+    }
+
+    /// Deserializes the merged config, pointing errors at the failing key and the
+    /// last file that set it.
+    pub fn typed_config<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        let config = self.config()?;
+
+        let error = match serde_path_to_error::deserialize(Value::Map(config.inner.clone())) {
+            Ok(value) => return Ok(value),
+            Err(error) => error,
+        };
+
+        let mut path = KeyPath::root();
+        for segment in error.path().iter() {
+            match segment {
+                serde_path_to_error::Segment::Map { key } => path.push_key(key),
+                serde_path_to_error::Segment::Seq { index } => path.push_index(*index),
+                serde_path_to_error::Segment::Enum { variant } => path.push_key(variant),
+                serde_path_to_error::Segment::Unknown => break,
+            }
+        }
+
+        let file = config
+            .origins
+            .get(&path)
+            .and_then(Source::path)
+            .map(Path::to_path_buf);
+
+        Err(Error::Typed {
+            path,
+            file,
+            error: error.into_inner(),
+        })
+    }
+
+    /// Runs discovery, parsing, merging and validation, collecting every problem
+    /// instead of stopping at the first.
+    pub fn check(&self) -> CheckReport {
+        let mut report = CheckReport::default();
+
+        let located = self.located();
+        let files = match self.select(self.filtered_files(located.files)) {
+            Ok(files) => files,
+            Err(err) => {
+                report.error(err, None);
+                return report;
+            }
+        };
+
+        let mut configs = Vec::with_capacity(files.len());
+        let mut vetoed = Vec::new();
+        for path in files {
+            match self.load_map(path.clone()) {
+                Ok(file) if self.admits(&file) => configs.push(file),
+                Ok(file) => vetoed.push(file.path),
+                Err(err) => report.error(err, Some(path)),
+            }
+        }
+
+        let contents = match self.parse_contents(located.contents) {
+            Ok(contents) => contents,
+            Err(err) => {
+                report.error(err, None);
+                return report;
+            }
+        };
+
+        // A partial merge would only report follow-up errors
+        if !report.is_ok() {
+            return report;
+        }
+
+        let sources = Sources {
+            files: configs,
+            contents,
+            vetoed,
+        };
+
+        match self.merge_sources(sources, &Trace::new(false), None) {
+            Ok(config) => {
+                for violation in config.violations() {
+                    report.violation(violation);
+                }
+                for coercion in config.coercions() {
+                    report.coercion(coercion);
+                }
+            }
+            Err(err) => report.error(err, None),
+        }
+
+        report
+    }
+
+    /// Like config, but also lists every value that a later source overrode.
+    pub fn config_with_report(&self) -> Result<(Config, MergeReport), Error> {
+        let trace = Trace::new(self.0.debug);
+        let mut report = MergeReport::default();
+        let config = self
+            .load_config(&trace, Some(&mut report))
+            .map_err(|err| trace.attach(err))?;
+        Ok((config, report))
+    }
+
+    fn load_config(
+        &self,
+        trace: &Trace,
+        report: Option<&mut MergeReport>,
+    ) -> Result<Config, Error> {
+        let located = self.located();
+
+        #[cfg(feature = "cache")]
+        if let Some(cache) = &self.0.cache {
+            let key = self.cache_key();
+            let (files, vetoed) = match cache.load(&key) {
+                Some(cached) => {
+                    trace.record(|| "using cached files".to_string());
+                    cached
+                }
+                None => {
+                    let (files, vetoed) = self.parse_files(located.files, trace)?;
+                    cache.store(&key, &self.cache_dirs(&files, &vetoed), &files, &vetoed);
+                    (files, vetoed)
+                }
+            };
+
+            let sources = Sources {
+                files,
+                contents: self.parse_contents(located.contents)?,
+                vetoed,
+            };
+            return self.merge_sources(sources, trace, report);
+        }
+
+        let (files, vetoed) = self.parse_files(located.files, trace)?;
+        let sources = Sources {
+            files,
+            contents: self.parse_contents(located.contents)?,
+            vetoed,
+        };
+
+        self.merge_sources(sources, trace, report)
+    }
+
+    // Merges `files`, already selected and in merge order, with the contents and
+    // layers, the way `config` merges the discovered files
+    pub(super) fn config_from(&self, files: Vec<PathBuf>) -> Result<Config, Error> {
+        let trace = Trace::new(self.0.debug);
+
+        let load = || {
+            let (files, vetoed) = self.parse(files, &trace)?;
+            let sources = Sources {
+                files,
+                contents: self.contents()?,
+                vetoed,
+            };
+            self.merge_sources(sources, &trace, None)
+        };
+
+        load().map_err(|err| trace.attach(err))
+    }
+
+    // Admitted files, and the paths of those the file predicate vetoed
+    fn parse_files(
+        &self,
+        located: Vec<PathBuf>,
+        trace: &Trace,
+    ) -> Result<(Vec<ConfigFile<Map>>, Vec<PathBuf>), Error> {
+        let files = self.filtered_files(located);
+        for path in &files {
+            trace.record(|| format!("discovered {}", path.display()));
+        }
+
+        self.parse(self.select(files)?, trace)
+    }
+
+    fn parse(
+        &self,
+        files: Vec<PathBuf>,
+        trace: &Trace,
+    ) -> Result<(Vec<ConfigFile<Map>>, Vec<PathBuf>), Error> {
+        let mut configs = Vec::new();
+        let mut vetoed = Vec::new();
+        for path in files {
+            let file = self.load_map(path)?;
+            trace.record(|| format!("parsed {}", file.path.display()));
+            if self.admits(&file) {
+                configs.push(file);
+            } else {
+                trace.record(|| format!("vetoed {}", file.path.display()));
+                vetoed.push(file.path);
+            }
+        }
+
+        Ok((configs, vetoed))
+    }
+
+    // Everything that decides which files are read and what they parse to.
+    // Custom encoders are told apart by type and custom sorts not at all
+    #[cfg(feature = "cache")]
+    fn cache_key(&self) -> Vec<String> {
+        let patterns = self.0.patterns.read().expect("patterns lock poisoned");
+        let locators = self.0.locators.read().expect("locators lock poisoned");
+
+        let mut key = patterns
+            .iter()
+            .map(|pattern| pattern.as_str().to_string())
+            .chain(
+                locators
+                    .iter()
+                    .map(|locator| locator.root().display().to_string()),
+            )
+            .chain(self.0.explicit.iter().map(|m| m.display().to_string()))
+            .collect::<Vec<_>>();
+
+        key.push(format!("expand_dotted={}", self.0.expand_dotted));
+        key.push(format!(
+            "path_keys={:?}",
+            self.0.path_keys.patterns().collect::<Vec<_>>()
+        ));
+        key.push(format!("strip={:?}", self.0.strip_extensions));
+        key.push(format!("encoders={:?}", self.0.loader.describe()));
+        for (root, loader) in &self.0.scoped_loaders {
+            key.push(format!(
+                "encoders:{}={:?}",
+                root.display(),
+                loader.describe()
+            ));
+        }
+        key.push(format!("decode={:?}", self.0.decode));
+        key.push(format!("encodings={:?}", self.0.encodings));
+        key.push(format!("sort={}", self.0.sorter.name()));
+        key.push(format!("siblings={:?}", self.0.siblings));
+
+        key
+    }
+
+    // Directories whose mtime changes when files are added or removed. Walked
+    // locators can find files in any directory within their depth, matched yet or not
+    #[cfg(feature = "cache")]
+    fn cache_dirs(&self, configs: &[ConfigFile<Map>], vetoed: &[PathBuf]) -> Vec<PathBuf> {
+        let patterns = self.0.patterns.read().expect("patterns lock poisoned");
+        let locators = self.0.locators.read().expect("locators lock poisoned");
+
+        let walked = locators.iter().flat_map(|locator| {
+            let depth = match locator.strategy(&patterns) {
+                MatchStrategy::Walk { depth } => depth,
+                _ => 0,
+            };
+
+            // Files at `depth` are in directories one level up
+            walkdir::WalkDir::new(locator.root())
+                .max_depth(depth.saturating_sub(1))
+                .into_iter()
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.file_type().is_dir())
+                .map(|entry| entry.into_path())
+        });
+
+        let mut dirs = locators
+            .iter()
+            .map(|locator| locator.root().clone())
+            .chain(walked)
+            .chain(
+                configs
+                    .iter()
+                    .map(|file| &file.path)
+                    .chain(vetoed)
+                    .filter_map(|path| path.parent().map(Path::to_path_buf)),
+            )
+            .collect::<Vec<_>>();
+        dirs.sort();
+        dirs.dedup();
+        dirs
+    }
+
+    /// Each locator root merged on its own, with the same defaults and layers,
+    /// loaded on one thread per root.
+    pub fn config_per_root(&self) -> Result<HashMap<PathBuf, Config>, Error> {
+        let roots = {
+            let locators = self.0.locators.read().expect("locators lock poisoned");
+            let patterns = self.0.patterns.read().expect("patterns lock poisoned");
+
+            let roots = locators
+                .iter()
+                .map(|locator| {
+                    let files = find_files(std::slice::from_ref(locator), &patterns)
+                        .filter(|path| self.accepts(path))
+                        .collect::<Vec<_>>();
+                    (locator.root().clone(), files)
+                })
+                .collect::<Vec<_>>();
+            roots
+        };
+
+        let contents = self.contents()?;
+
+        std::thread::scope(|scope| {
+            let workers = roots
+                .into_iter()
+                .map(|(root, files)| {
+                    let contents = contents
+                        .iter()
+                        .filter(|(m, _, _)| *m == root)
+                        .cloned()
+                        .collect::<Vec<_>>();
+
+                    scope.spawn(move || {
+                        let trace = Trace::new(false);
+                        let mut configs = Vec::new();
+                        let mut vetoed = Vec::new();
+                        for path in self.select(files)? {
+                            let file = self.load_map(path)?;
+                            if self.admits(&file) {
+                                configs.push(file);
+                            } else {
+                                vetoed.push(file.path);
+                            }
+                        }
+                        let sources = Sources {
+                            files: configs,
+                            contents,
+                            vetoed,
+                        };
+                        let config = self.merge_sources(sources, &trace, None)?;
+                        Ok((root, config))
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            workers
+                .into_iter()
+                .map(|worker| worker.join().expect("config worker panicked"))
+                .collect()
+        })
+    }
+
+    fn merge_sources(
+        &self,
+        sources: Sources,
+        trace: &Trace,
+        report: Option<&mut MergeReport>,
+    ) -> Result<Config, Error> {
+        let mut config = self.merge_maps(sources, None, None, trace, report)?;
+
+        #[cfg(feature = "schemars")]
+        if let Some(schema) = &self.0.schema {
+            trace.record(|| "validating schema".to_string());
+            schema.validate(&config.inner, &config.files)?;
+        }
+
+        if !self.0.validators.is_empty() {
+            trace.record(|| "running validators".to_string());
+            let errors = validate(&self.0.validators, &config);
+            if !errors.is_empty() {
+                return Err(Error::Validation(errors));
+            }
+        }
+
+        // Only reads by the application count, not the validators'
+        if self.0.track_access {
+            config.track_access();
+        }
+
+        Ok(config)
+    }
+
+    // Merges defaults, layers, files and content, then resolves commands. With a
+    // `section`, defaults and layers are cut down to that top level key; callers
+    // do the same for the files and content they pass in
+    // The default closure's values, the layer maps and the file stamps, which
+    // are the same for every section of a lazy config
+    pub(super) fn prepare<'a>(
+        &self,
+        files: impl IntoIterator<Item = &'a PathBuf>,
+    ) -> Result<Prepared, Error> {
+        let modified = files
+            .into_iter()
+            .filter_map(|path| {
+                let modified = std::fs::metadata(path).and_then(|m| m.modified()).ok()?;
+                Some((path.clone(), modified))
+            })
+            .collect();
+
+        let mut config = Config::default();
+        if let Some(default) = &self.0.default {
+            default(&mut config);
+        }
+
+        let layers = self
+            .0
+            .layers
+            .iter()
+            .map(|layer| self.layer_map(layer))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Prepared {
+            defaults: config.inner,
+            layers,
+            modified,
+        })
+    }
+
+    fn merge_maps(
+        &self,
+        sources: Sources,
+        section: Option<&str>,
+        prepared: Option<&Prepared>,
+        trace: &Trace,
+        mut report: Option<&mut MergeReport>,
+    ) -> Result<Config, Error> {
+        let scope = |map: &Map| match section {
+            Some(key) => self::section(map, key),
+            None => map.clone(),
+        };
+
+        let owned;
+        let prepared = match prepared {
+            Some(prepared) => prepared,
+            None => {
+                // Vetoed files count too, since an edit may get them admitted
+                let files = sources.files.iter().map(|m| &m.path);
+                owned = self.prepare(files.chain(&sources.vetoed))?;
+                &owned
+            }
+        };
+
+        let Sources {
+            files: configs,
+            contents,
+            vetoed,
+        } = sources;
+
+        let files: Vec<PathBuf> = configs.iter().map(|m| m.path.clone()).collect();
+        let modified = prepared.modified.clone();
+
+        let mut defaults = scope(&prepared.defaults);
+        let mut inner = defaults.clone();
+        let mut coercions = Vec::new();
+        let mut violations = Vec::new();
+        let mut origins = Origins::default();
+        origins.record(&defaults, &Source::Defaults);
+
+        let layers = self.0.layers.iter().zip(&prepared.layers);
+
+        for (layer, map) in layers.clone().filter(|(m, _)| !m.overrides_files()) {
+            trace.record(|| format!("merging layer {}", layer.name));
+            let mut map = scope(map);
+            self.coerce(&mut map, &layer.name, &mut coercions);
+            origins.record(&map, &layer.source());
+            if layer.is_defaults() {
+                self.0
+                    .merger
+                    .merge_map(&mut defaults, map.clone(), &layer.name, None)?;
+            }
+            self.0
+                .merger
+                .merge_map(&mut inner, map, &layer.name, report.as_deref_mut())?;
+        }
+
+        for mut file in configs {
+            trace.record(|| format!("merging file {}", file.path.display()));
+            self.0.limits.check(&file.config, &file.path)?;
+            let origin = file.path.display().to_string();
+            self.restrict(&mut file.config, &file.path, &origin, &mut violations);
+            self.coerce(&mut file.config, &origin, &mut coercions);
+            origins.record(&file.config, &Source::File(file.path.clone()));
+            self.0
+                .merger
+                .merge_map(&mut inner, file.config, &origin, report.as_deref_mut())?;
+            self.0.limits.check(&inner, &file.path)?;
+        }
+
+        for (root, name, mut content) in contents {
+            trace.record(|| format!("merging content {}", name));
+            self.0.limits.check(&content, Path::new(&name))?;
+            self.restrict(&mut content, &root, &name, &mut violations);
+            self.coerce(&mut content, &name, &mut coercions);
+            origins.record(&content, &Source::Content(name.clone()));
+            self.0
+                .merger
+                .merge_map(&mut inner, content, &name, report.as_deref_mut())?;
+            self.0.limits.check(&inner, Path::new(&name))?;
+        }
+
+        for (layer, map) in layers.filter(|(m, _)| m.overrides_files()) {
+            trace.record(|| format!("merging layer {}", layer.name));
+            let mut map = scope(map);
+            self.coerce(&mut map, &layer.name, &mut coercions);
+            origins.record(&map, &layer.source());
+            if layer.is_defaults() {
+                self.0
+                    .merger
+                    .merge_map(&mut defaults, map.clone(), &layer.name, None)?;
+            }
+            self.0
+                .merger
+                .merge_map(&mut inner, map, &layer.name, report.as_deref_mut())?;
+        }
+
+        if let Some(commands) = &self.0.commands {
+            trace.record(|| "resolving commands".to_string());
+            commands.resolve_map(&mut inner)?;
+        }
+
+        Ok(Config {
+            inner,
+            files,
+            defaults,
+            modified,
+            coercions,
+            violations,
+            origins,
+            vetoed,
+            access: None,
+        })
+    }
+
+    fn restrict(&self, map: &mut Map, path: &Path, origin: &str, out: &mut Vec<Violation>) {
+        for rule in self.0.source_rules.iter().filter(|m| m.applies_to(path)) {
+            rule.apply(map, origin, out);
+        }
+    }
+
+    #[allow(unused_variables)]
+    fn coerce(&self, map: &mut Map, origin: &str, coercions: &mut Vec<Coercion>) {
+        #[cfg(feature = "schemars")]
+        if self.0.coerce {
+            if let Some(schema) = &self.0.schema {
+                coercions.extend(schema.coerce(map, origin, &self.0.merger));
+            }
+        }
+    }
+
+    /// Whether `key` was set by the system or the user config, for UIs that mark
+    /// administrator managed settings.
+    pub fn scope(&self, config: &Config, key: &str) -> Option<Scope> {
+        self.0.scopes.of(config.source(key)?)
+    }
+
+    pub fn user_file(&self) -> Option<&Path> {
+        self.0.scopes.user.as_deref()
+    }
+
+    /// Sets `key` in the config and writes it through to the user file.
+    pub fn set_persistent(
+        &self,
+        config: &mut Config,
+        key: &str,
+        value: impl Into<Value>,
+    ) -> Result<Option<Value>, Error> {
+        let user = self.0.scopes.user.as_ref().ok_or(Error::NoUserConfig)?;
+        let path = key.parse::<KeyPath>()?;
+        let value = value.into();
+
+        let mut map = self.read_user(user)?;
+        insert_at(&mut map, &path, value.clone())?;
+        self.write_map(user, &map)?;
+
+        let prev = insert_at(&mut config.inner, &path, value)?;
+        config.origins.set(path, Source::File(user.clone()));
+
+        Ok(prev)
+    }
+
+    /// Writes runtime changes and values already from the user file back to it.
+    /// Anything the system config, defaults or layers provide is left out.
+    pub fn save(&self, config: &Config) -> Result<(), Error> {
+        let user = self.0.scopes.user.as_ref().ok_or(Error::NoUserConfig)?;
+
+        let mut map = self.read_user(user)?;
+        for (path, value) in self.0.scopes.persisted(config) {
+            insert_at(&mut map, &path, value)?;
+        }
+
+        self.write_map(user, &map)
+    }
+
+    // The file as written, without the transforms applied when loading
+    fn read_user(&self, path: &Path) -> Result<Map, Error> {
+        if !path.exists() {
+            return Ok(Map::default());
+        }
+
+        let file = load_file(
+            self.loader_for(path),
+            path.to_path_buf(),
+            &self.load_options(),
+        )?;
+        Ok(file.config)
+    }
+
+    fn write_map(&self, path: &Path, map: &Map) -> Result<(), Error> {
+        save_file(self.loader_for(path), path, map, &self.0.strip_extensions)
+    }
+
+    pub fn is_stale(&self, config: &Config) -> bool {
+        if config.is_stale() {
+            return true;
+        }
+
+        let found = match self.selected_files() {
+            Ok(files) => files.into_iter().collect::<HashSet<_>>(),
+            Err(_) => return true,
+        };
+        let seen = config
+            .files
+            .iter()
+            .chain(&config.vetoed)
+            .collect::<HashSet<_>>();
+        found.len() != seen.len() || seen.iter().any(|m| !found.contains(*m))
+    }
+
+    #[cfg(feature = "schemars")]
+    pub fn schema(&self) -> Option<&schemars::schema::RootSchema> {
+        self.0.schema.as_ref().map(|schema| schema.root())
+    }
+
+    #[cfg(feature = "schemars")]
+    pub fn schema_json(&self) -> Option<String> {
+        self.0.schema.as_ref().map(|schema| schema.json())
+    }
+
+    #[cfg(feature = "watch")]
+    pub fn watch(&self) -> Result<WatchableConfig, Error> {
+        self.watch_with(WatchOptions::default())
+    }
+
+    /// Reloads once no event has arrived for `debounce`.
+    #[cfg(feature = "watch")]
+    pub fn watch_debounced(&self, debounce: Duration) -> Result<WatchableConfig, Error> {
+        self.watch_with(WatchOptions::default().with_debounce(debounce))
+    }
+
+    #[cfg(feature = "watch")]
+    pub fn watch_with(&self, options: WatchOptions) -> Result<WatchableConfig, Error> {
+        WatchableConfig::new(self.clone(), options)
+    }
+
+    // Directories to watch with the depth that is searched below them, and files
+    // outside discovery whose changes matter. A depth of 1 is the directory itself
+    #[cfg(feature = "watch")]
+    pub(super) fn watch_targets(&self) -> (Vec<(PathBuf, usize)>, Vec<PathBuf>) {
+        let mut files = self
+            .0
+            .layers
+            .iter()
+            .filter_map(|layer| match &layer.source {
+                LayerSource::File(path) | LayerSource::OptionalFile(path) => Some(path.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        files.extend(self.0.explicit.clone());
+
+        let locators = self.0.locators.read().expect("locators lock poisoned");
+        let patterns = self.0.patterns.read().expect("patterns lock poisoned");
+
+        let mut dirs = locators
+            .iter()
+            .map(|locator| {
+                let root = locator.root();
+                let recursion = self.0.watch_recursion.iter().find(|(m, _)| m == root);
+                let depth = match (recursion, locator.strategy(&patterns)) {
+                    (Some((_, true)), _) => usize::MAX,
+                    (Some((_, false)), _) => 1,
+                    (None, MatchStrategy::Walk { depth }) => depth,
+                    (None, _) => 1,
+                };
+                (root.clone(), depth)
+            })
+            .chain(
+                files
+                    .iter()
+                    .filter_map(|path| Some((path.parent()?.to_path_buf(), 1))),
+            )
+            .collect::<Vec<_>>();
+
+        // Keeps the deepest entry per directory
+        dirs.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)));
+        dirs.dedup_by(|a, b| a.0 == b.0);
+
+        (dirs, files)
+    }
+
+    pub fn matches(&self, path: &Path) -> bool {
+        let path = match path.file_name() {
+            Some(path) => path,
+            None => return false,
+        };
+        let patterns = self.0.patterns.read().expect("patterns lock poisoned");
+        for pattern in patterns.iter() {
+            if pattern.matches_path_with(Path::new(path), MATCH_OPTIONS) {
+                return true;
+            }
+        }
+        false
+    }
+
+    pub fn matche_any(&self, paths: &[PathBuf]) -> bool {
+        for path in paths {
+            if self.matches(path) {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+// Parsed files and located content, in merge order
+// What the locators found in one pass: paths to load, and content handed over as is
+#[derive(Default)]
+struct Located {
+    files: Vec<PathBuf>,
+    contents: Vec<Content>,
+}
+
+struct Content {
+    root: PathBuf,
+    name: String,
+    bytes: Vec<u8>,
+    format: Option<String>,
+}
+
+pub(super) struct Prepared {
+    defaults: Map,
+    // One per finder layer, in the same order
+    layers: Vec<Map>,
+    modified: HashMap<PathBuf, SystemTime>,
+}
+
+pub(super) struct Sources {
+    pub files: Vec<ConfigFile<Map>>,
+    pub contents: Vec<(PathBuf, String, Map)>,
+    // Files the file predicate rejected
+    pub vetoed: Vec<PathBuf>,
+}
+
+// The top level `key` of `map` on its own
+fn section(map: &Map, key: &str) -> Map {
+    let mut out = Map::default();
+    if let Some(value) = map.get(key) {
+        out.insert(key, value.clone());
+    }
+    out
+}
+
+fn flatten_files(
+    files: Result<Vec<PathBuf>, Error>,
+) -> impl Iterator<Item = Result<PathBuf, Error>> {
+    let (files, error) = match files {
+        Ok(files) => (files, None),
+        Err(err) => (Vec::new(), Some(err)),
+    };
+
+    error.into_iter().map(Err).chain(files.into_iter().map(Ok))
+}
+
+struct LoadOptions<'a> {
+    strip: &'a [String],
+    decode: DecodePolicy,
+    encodings: &'a [(PathBuf, Encoding)],
+    #[cfg(feature = "chaos")]
+    chaos: Option<&'a Chaos>,
+}
+
+impl<'a> LoadOptions<'a> {
+    fn read(&self, path: &Path) -> Result<Vec<u8>, Error> {
+        #[cfg(feature = "chaos")]
+        if let Some(chaos) = self.chaos {
+            return Ok(chaos.intercept(path, || std::fs::read(path))?);
+        }
+
+        Ok(std::fs::read(path)?)
+    }
+
+    #[allow(unused_variables)]
+    fn intercept(&self, path: &Path, bytes: Vec<u8>) -> Result<Vec<u8>, Error> {
+        #[cfg(feature = "chaos")]
+        if let Some(chaos) = self.chaos {
+            return Ok(chaos.intercept(path, || Ok(bytes))?);
+        }
+
+        Ok(bytes)
+    }
+}
+
+// The most specific root wins
+fn encoding_for(encodings: &[(PathBuf, Encoding)], path: &Path) -> Option<Encoding> {
+    encodings
+        .iter()
+        .filter(|(root, _)| path.starts_with(root))
+        .max_by_key(|(root, _)| root.components().count())
+        .map(|(_, encoding)| *encoding)
+}
+
+pub(crate) fn default_loader() -> Encoders {
+    #[allow(unused_mut)]
+    let mut loader = Encoders::default();
+
+    #[cfg(feature = "properties")]
+    loader.add(crate::formats::Properties::default());
+
+    #[cfg(feature = "plist")]
+    loader.add(crate::formats::Plist::default());
+
+    loader
+}
+
+// Encodes with the encoder matching the extension, creating missing directories
+pub(crate) fn save_file(
+    loader: &Loader,
+    path: &Path,
+    map: &Map,
+    strip: &[String],
+) -> Result<(), Error> {
+    let ext = extension::detect(path, &loader.extensions(), strip)?;
+    let data = loader.save(map, &ext)?;
+
+    if let Some(parent) = path.parent() {
+        if !parent.as_os_str().is_empty() {
+            std::fs::create_dir_all(parent)?;
+        }
+    }
+
+    std::fs::write(path, data)?;
+    Ok(())
+}
+
+// The file as written, in the format its extension names
+pub(crate) fn read_file(loader: &Loader, path: &Path) -> Result<Map, Error> {
+    let ext = extension::detect(path, &loader.extensions(), &[])?;
+    let data = std::fs::read(path)?;
+    Ok(loader.load(&data, &ext)?)
+}
+
+fn load_file(
+    loader: &Loader,
+    search_path: PathBuf,
+    options: &LoadOptions<'_>,
+) -> Result<ConfigFile<Map>, Error> {
+    let data = read_decoded(&search_path, options)?;
+    parse_decoded(loader, search_path, &data, options)
+}
+
+// The bytes of a file as the encoders see them
+fn read_decoded(path: &Path, options: &LoadOptions<'_>) -> Result<Vec<u8>, Error> {
+    let data = options.read(path)?;
+    let encoding = encoding_for(options.encodings, path);
+    options.decode.decode(data, path, encoding)
+}
+
+fn parse_decoded(
+    loader: &Loader,
+    search_path: PathBuf,
+    data: &[u8],
+    options: &LoadOptions<'_>,
+) -> Result<ConfigFile<Map>, Error> {
+    let ext = extension::detect(&search_path, &loader.extensions(), options.strip)?;
+    let out = loader.load(data, &ext)?;
+
+    tracing::trace!("found path: {:?}", search_path);
+
+    Ok(ConfigFile {
+        config: out,
+        path: search_path,
+    })
 }
 
 pub fn find_files<'a>(
@@ -0,0 +1,122 @@
+use super::config_file::ConfigFile;
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+use vaerdi::Map;
+
+const VERSION: u32 = 3;
+
+type Stamp = Option<(u64, u32)>;
+
+#[derive(Serialize, Deserialize)]
+struct CacheData {
+    version: u32,
+    key: Vec<String>,
+    stamps: Vec<(PathBuf, Stamp)>,
+    files: Vec<CachedFile>,
+    vetoed: Vec<PathBuf>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedFile {
+    path: PathBuf,
+    config: Map,
+}
+
+// Persists discovered and parsed files between runs. The cache is valid as long
+// as the key (name patterns, roots and loader settings) is the same and no
+// scanned directory or file has a new mtime
+pub(crate) struct Cache {
+    path: PathBuf,
+}
+
+impl Cache {
+    pub fn new(path: PathBuf) -> Cache {
+        Cache { path }
+    }
+
+    // The admitted files and the paths of those the file predicate vetoed
+    pub fn load(&self, key: &[String]) -> Option<(Vec<ConfigFile<Map>>, Vec<PathBuf>)> {
+        let data = std::fs::read(&self.path).ok()?;
+        let data: CacheData = serde_json::from_slice(&data).ok()?;
+
+        if data.version != VERSION || data.key != key {
+            return None;
+        }
+
+        if data
+            .stamps
+            .iter()
+            .any(|(path, stamp)| stamp_of(path) != *stamp)
+        {
+            return None;
+        }
+
+        let files = data
+            .files
+            .into_iter()
+            .map(|file| ConfigFile {
+                config: file.config,
+                path: file.path,
+            })
+            .collect();
+
+        Some((files, data.vetoed))
+    }
+
+    pub fn store(
+        &self,
+        key: &[String],
+        dirs: &[PathBuf],
+        configs: &[ConfigFile<Map>],
+        vetoed: &[PathBuf],
+    ) {
+        let mut stamps = dirs
+            .iter()
+            .map(|dir| (dir.clone(), stamp_of(dir)))
+            .collect::<Vec<_>>();
+
+        // Vetoed files are stamped too, so an edit that gets one admitted is noticed
+        stamps.extend(
+            configs
+                .iter()
+                .map(|file| &file.path)
+                .chain(vetoed)
+                .map(|path| (path.clone(), stamp_of(path))),
+        );
+
+        let data = CacheData {
+            version: VERSION,
+            key: key.to_vec(),
+            stamps,
+            files: configs
+                .iter()
+                .map(|file| CachedFile {
+                    path: file.path.clone(),
+                    config: file.config.clone(),
+                })
+                .collect(),
+            vetoed: vetoed.to_vec(),
+        };
+
+        if let Err(err) = self.write(&data) {
+            tracing::debug!("could not write cache file {:?}: {}", self.path, err);
+        }
+    }
+
+    // Written next to the target and renamed, so a concurrent run never reads half a file
+    fn write(&self, data: &CacheData) -> std::io::Result<()> {
+        let data = serde_json::to_vec(data)?;
+        let tmp = self.path.with_extension("tmp");
+        std::fs::write(&tmp, data)?;
+        std::fs::rename(&tmp, &self.path)
+    }
+}
+
+fn stamp_of(path: &Path) -> Stamp {
+    let modified = std::fs::metadata(path).and_then(|m| m.modified()).ok()?;
+    let since = modified.duration_since(UNIX_EPOCH).ok()?;
+    Some((since.as_secs(), since.subsec_nanos()))
+}
@@ -0,0 +1,89 @@
+use crate::{Coercion, Error, KeyPath, Violation};
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub file: Option<PathBuf>,
+    pub path: Option<KeyPath>,
+}
+
+/// The outcome of ConfigFinder::check, meant for `config check` style commands.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CheckReport {
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl CheckReport {
+    pub fn is_ok(&self) -> bool {
+        self.errors().next().is_none()
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics
+            .iter()
+            .filter(|m| m.severity == Severity::Error)
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics
+            .iter()
+            .filter(|m| m.severity == Severity::Warning)
+    }
+
+    pub(super) fn error(&mut self, error: Error, file: Option<PathBuf>) {
+        match error {
+            Error::Validation(errors) => {
+                for error in errors {
+                    self.diagnostics.push(Diagnostic {
+                        severity: Severity::Error,
+                        message: error.message,
+                        file: error.file,
+                        path: Some(error.path),
+                    });
+                }
+            }
+            Error::Typed { path, file, error } => self.diagnostics.push(Diagnostic {
+                severity: Severity::Error,
+                message: error.to_string(),
+                file,
+                path: Some(path),
+            }),
+            Error::Traced { error, .. } => self.error(*error, file),
+            error => self.diagnostics.push(Diagnostic {
+                severity: Severity::Error,
+                message: error.to_string(),
+                file,
+                path: None,
+            }),
+        }
+    }
+
+    pub(super) fn violation(&mut self, violation: &Violation) {
+        self.diagnostics.push(Diagnostic {
+            severity: Severity::Warning,
+            message: format!("{}: dropped: {}", violation.origin, violation.reason),
+            file: None,
+            path: Some(violation.path.clone()),
+        });
+    }
+
+    pub(super) fn coercion(&mut self, coercion: &Coercion) {
+        self.diagnostics.push(Diagnostic {
+            severity: Severity::Warning,
+            message: format!(
+                "{}: coerced {} to {}",
+                coercion.origin, coercion.from, coercion.to
+            ),
+            file: None,
+            path: Some(coercion.path.clone()),
+        });
+    }
+}
@@ -0,0 +1,232 @@
+use crate::Error;
+use std::{
+    io::Read,
+    process::{Command, Stdio},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+use vaerdi::{Map, Value};
+
+pub(crate) const DIRECTIVE: &str = "@exec:";
+
+/// Runs the commands of `@exec:` values at load time and puts their output in
+/// place. The string form `"@exec: op read 'op://vault/my item'"` is split into
+/// arguments on whitespace, keeping quoted runs together; the list form
+/// `["@exec:", "op", "read", "op://vault/my item"]` passes each item as is.
+pub struct CommandResolver {
+    allowed: Vec<String>,
+    timeout: Duration,
+}
+
+impl Default for CommandResolver {
+    fn default() -> Self {
+        CommandResolver::new()
+    }
+}
+
+impl CommandResolver {
+    pub fn new() -> CommandResolver {
+        CommandResolver {
+            allowed: Vec::default(),
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    pub fn allow(mut self, program: impl ToString) -> Self {
+        self.allowed.push(program.to_string());
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub(crate) fn resolve_map(&self, map: &mut Map) -> Result<(), Error> {
+        for (_, value) in map.iter_mut() {
+            self.resolve(value)?;
+        }
+        Ok(())
+    }
+
+    fn resolve(&self, value: &mut Value) -> Result<(), Error> {
+        match value {
+            Value::Map(map) => self.resolve_map(map),
+            Value::List(list) => {
+                if let Some(argv) = list_argv(list.iter()) {
+                    let output = self.run(argv)?;
+                    *value = Value::String(output);
+                    return Ok(());
+                }
+                for item in list.iter_mut() {
+                    self.resolve(item)?;
+                }
+                Ok(())
+            }
+            Value::String(string) => {
+                if let Some(command) = string.strip_prefix(DIRECTIVE) {
+                    let argv = split_args(command).map_err(|m| command_error(command.trim(), m))?;
+                    let output = self.run(argv)?;
+                    *value = Value::String(output);
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn run(&self, argv: Vec<String>) -> Result<String, Error> {
+        let command = argv.join(" ");
+
+        let (program, args) = match argv.split_first() {
+            Some(split) => split,
+            None => return Err(command_error(&command, "empty command")),
+        };
+
+        if !self.allowed.iter().any(|allowed| allowed == program) {
+            return Err(command_error(&command, "program is not allowed"));
+        }
+
+        tracing::debug!("running command: {}", command);
+
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        // Drain stdout and stderr on separate threads so a chatty command can't
+        // fill a pipe and stall until the timeout.
+        let stdout = drain(child.stdout.take().expect("stdout is piped"));
+        let stderr = drain(child.stderr.take().expect("stderr is piped"));
+
+        let deadline = Instant::now() + self.timeout;
+
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+
+            if Instant::now() >= deadline {
+                child.kill().ok();
+                child.wait().ok();
+                return Err(command_error(&command, "timed out"));
+            }
+
+            std::thread::sleep(Duration::from_millis(10));
+        };
+
+        let output = stdout.join().expect("stdout reader panicked")?;
+        let errors = stderr.join().expect("stderr reader panicked")?;
+
+        if !status.success() {
+            let errors = String::from_utf8_lossy(&errors);
+            let reason = match errors.trim() {
+                "" => format!("exited with {}", status),
+                errors => format!("exited with {}: {}", status, errors),
+            };
+            return Err(command_error(&command, &reason));
+        }
+
+        let output = String::from_utf8(output)
+            .map_err(|_| command_error(&command, "output is not valid utf-8"))?;
+
+        Ok(output.trim_end_matches(&['\r', '\n'][..]).to_string())
+    }
+}
+
+fn drain(mut pipe: impl Read + Send + 'static) -> JoinHandle<std::io::Result<Vec<u8>>> {
+    std::thread::spawn(move || {
+        let mut output = Vec::new();
+        pipe.read_to_end(&mut output).map(|_| output)
+    })
+}
+
+// `["@exec:", "program", "arg", ..]`, where every item is a string
+fn list_argv<'a>(mut items: impl Iterator<Item = &'a Value>) -> Option<Vec<String>> {
+    match items.next() {
+        Some(Value::String(first)) if first.trim() == DIRECTIVE => {}
+        _ => return None,
+    }
+
+    items
+        .map(|item| match item {
+            Value::String(arg) => Some(arg.to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+// Splits on whitespace, except inside single or double quotes. A backslash
+// escapes the next character outside single quotes
+fn split_args(command: &str) -> Result<Vec<String>, &'static str> {
+    let mut args = Vec::new();
+    let mut current: Option<String> = None;
+    let mut quote = None;
+    let mut chars = command.chars();
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"') | None, '\\') => {
+                let escaped = chars.next().ok_or("trailing backslash")?;
+                current.get_or_insert_with(String::new).push(escaped);
+            }
+            (Some(_), c) => current.get_or_insert_with(String::new).push(c),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                current.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => args.extend(current.take()),
+            (None, c) => current.get_or_insert_with(String::new).push(c),
+        }
+    }
+
+    if quote.is_some() {
+        return Err("unterminated quote");
+    }
+
+    args.extend(current);
+    Ok(args)
+}
+
+fn command_error(command: &str, reason: &str) -> Error {
+    Error::Command {
+        command: command.to_string(),
+        reason: reason.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{split_args, CommandResolver};
+    use vaerdi::{value, Map};
+
+    #[test]
+    fn quoted_arguments_stay_together() {
+        assert_eq!(
+            split_args(r#" op read 'op://vault/my item' "a \"b\"" c\ d "#).unwrap(),
+            vec!["op", "read", "op://vault/my item", r#"a "b""#, "c d"]
+        );
+        assert_eq!(split_args("echo ''").unwrap(), vec!["echo", ""]);
+        assert!(split_args("echo 'open").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn list_form_and_stderr() {
+        let resolver = CommandResolver::new().allow("sh");
+
+        let mut map = Map::default();
+        map.insert("ok", value!(["@exec:", "sh", "-c", "echo 'one two'"]));
+        map.insert("fails", value!("@exec: sh -c 'echo denied >&2; exit 3'"));
+
+        let err = resolver.resolve_map(&mut map).unwrap_err();
+        assert!(err.to_string().contains("denied"));
+
+        map.insert("fails", value!("plain"));
+        resolver.resolve_map(&mut map).unwrap();
+        assert_eq!(map.get("ok"), Some(&value!("one two")));
+    }
+}
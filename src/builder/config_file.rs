@@ -1,4 +1,8 @@
+use super::{default_loader, save_file};
+use crate::Error;
+use serde::Serialize;
 use std::path::PathBuf;
+use vaerdi::Value;
 
 #[derive(Clone, Debug)]
 pub struct ConfigFile<T> {
@@ -6,6 +10,20 @@ pub struct ConfigFile<T> {
     pub path: PathBuf,
 }
 
+impl<T: Serialize> ConfigFile<T> {
+    /// Writes the file back where it was loaded from, in the format its
+    /// extension names.
+    pub fn save(&self) -> Result<(), Error> {
+        let map = match vaerdi::ser::to_value(&self.config) {
+            Ok(Value::Map(map)) => map,
+            Ok(_) => return Err(Error::NotAMap(self.path.clone())),
+            Err(err) => return Err(Error::Unknown(Box::new(err))),
+        };
+
+        save_file(&default_loader().build(), &self.path, &map, &[])
+    }
+}
+
 impl<T> std::ops::Deref for ConfigFile<T> {
     type Target = T;
     fn deref(&self) -> &T {
@@ -0,0 +1,224 @@
+use crate::Error;
+use std::path::Path;
+
+const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+const UTF16_LE_BOM: &[u8] = &[0xFF, 0xFE];
+const UTF16_BE_BOM: &[u8] = &[0xFE, 0xFF];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodePolicy {
+    // Bytes are handed to the encoder as they are, so binary formats keep working
+    Raw,
+    Strict,
+    Lossy,
+}
+
+/// A declared source encoding, for files that carry no byte order mark.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    // ISO-8859-1, where every byte is the code point of the same value
+    Latin1,
+}
+
+impl Default for DecodePolicy {
+    fn default() -> Self {
+        DecodePolicy::Raw
+    }
+}
+
+impl DecodePolicy {
+    // Byte order marks are handled under every policy: UTF-16 is transcoded to
+    // UTF-8 and a UTF-8 BOM is dropped. A declared encoding takes precedence
+    pub(crate) fn decode(
+        &self,
+        data: Vec<u8>,
+        path: &Path,
+        encoding: Option<Encoding>,
+    ) -> Result<Vec<u8>, Error> {
+        // UTF-16 comes out transcoded and always valid, so only a UTF-8 BOM
+        // shifts the offsets of the checks below
+        let bom = match encoding {
+            None | Some(Encoding::Utf8) if data.starts_with(UTF8_BOM) => UTF8_BOM.len(),
+            _ => 0,
+        };
+
+        let data = match encoding {
+            Some(Encoding::Utf8) => match data.strip_prefix(UTF8_BOM) {
+                Some(rest) => rest.to_vec(),
+                None => data,
+            },
+            Some(Encoding::Utf16Le) => match data.strip_prefix(UTF16_LE_BOM) {
+                Some(rest) => self.utf16(rest, 2, u16::from_le_bytes, path)?,
+                None => self.utf16(&data, 0, u16::from_le_bytes, path)?,
+            },
+            Some(Encoding::Utf16Be) => match data.strip_prefix(UTF16_BE_BOM) {
+                Some(rest) => self.utf16(rest, 2, u16::from_be_bytes, path)?,
+                None => self.utf16(&data, 0, u16::from_be_bytes, path)?,
+            },
+            Some(Encoding::Latin1) => data
+                .iter()
+                .map(|&byte| byte as char)
+                .collect::<String>()
+                .into_bytes(),
+            None => {
+                if let Some(rest) = data.strip_prefix(UTF8_BOM) {
+                    rest.to_vec()
+                } else if let Some(rest) = data.strip_prefix(UTF16_LE_BOM) {
+                    self.utf16(rest, 2, u16::from_le_bytes, path)?
+                } else if let Some(rest) = data.strip_prefix(UTF16_BE_BOM) {
+                    self.utf16(rest, 2, u16::from_be_bytes, path)?
+                } else {
+                    data
+                }
+            }
+        };
+
+        match self {
+            DecodePolicy::Raw => Ok(data),
+            DecodePolicy::Strict => match std::str::from_utf8(&data) {
+                Ok(_) => Ok(data),
+                Err(err) => Err(decode_error(
+                    path,
+                    err.valid_up_to() + bom,
+                    "invalid utf-8 sequence",
+                )),
+            },
+            DecodePolicy::Lossy => match String::from_utf8(data) {
+                Ok(data) => Ok(data.into_bytes()),
+                Err(err) => Ok(String::from_utf8_lossy(err.as_bytes())
+                    .into_owned()
+                    .into_bytes()),
+            },
+        }
+    }
+
+    // `bom` is the length of the stripped byte order mark, so offsets point into
+    // the original data
+    fn utf16(
+        &self,
+        data: &[u8],
+        bom: usize,
+        unit: fn([u8; 2]) -> u16,
+        path: &Path,
+    ) -> Result<Vec<u8>, Error> {
+        if data.len() % 2 != 0 && *self != DecodePolicy::Lossy {
+            return Err(decode_error(
+                path,
+                data.len() + bom - 1,
+                "truncated utf-16 data",
+            ));
+        }
+
+        let units = data.chunks_exact(2).map(|m| unit([m[0], m[1]]));
+
+        let mut out = String::with_capacity(data.len() / 2);
+        // Counted in code units, since a surrogate pair is two of them
+        let mut offset = 0;
+        for c in char::decode_utf16(units) {
+            match c {
+                Ok(c) => {
+                    out.push(c);
+                    offset += c.len_utf16();
+                }
+                Err(_) if *self == DecodePolicy::Lossy => {
+                    out.push(char::REPLACEMENT_CHARACTER);
+                    offset += 1;
+                }
+                Err(_) => {
+                    return Err(decode_error(
+                        path,
+                        offset * 2 + bom,
+                        "unpaired utf-16 surrogate",
+                    ))
+                }
+            }
+        }
+
+        Ok(out.into_bytes())
+    }
+}
+
+fn decode_error(path: &Path, offset: usize, reason: &str) -> Error {
+    Error::Decode {
+        path: path.to_path_buf(),
+        offset,
+        reason: reason.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DecodePolicy, Encoding};
+    use crate::Error;
+    use std::path::Path;
+
+    fn decode(policy: DecodePolicy, data: &[u8], encoding: Option<Encoding>) -> Vec<u8> {
+        policy
+            .decode(data.to_vec(), Path::new("test"), encoding)
+            .unwrap()
+    }
+
+    fn offset(policy: DecodePolicy, data: &[u8], encoding: Option<Encoding>) -> usize {
+        match policy.decode(data.to_vec(), Path::new("test"), encoding) {
+            Err(Error::Decode { offset, .. }) => offset,
+            other => panic!("expected a decode error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    fn utf16_le(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    #[test]
+    fn byte_order_marks() {
+        let raw = DecodePolicy::Raw;
+        assert_eq!(decode(raw, b"\xEF\xBB\xBFa=1", None), b"a=1");
+
+        let mut data = vec![0xFF, 0xFE];
+        data.extend(utf16_le("a=\u{1F600}"));
+        assert_eq!(decode(raw, &data, None), "a=\u{1F600}".as_bytes());
+
+        let data: Vec<u8> = "a=1".encode_utf16().flat_map(u16::to_be_bytes).collect();
+        assert_eq!(decode(raw, &data, Some(Encoding::Utf16Be)), b"a=1");
+    }
+
+    #[test]
+    fn declared_latin1() {
+        assert_eq!(
+            decode(DecodePolicy::Strict, b"a=\xE9", Some(Encoding::Latin1)),
+            "a=\u{e9}".as_bytes()
+        );
+    }
+
+    #[test]
+    fn strict_and_lossy() {
+        assert_eq!(decode(DecodePolicy::Raw, b"a=\xFF", None), b"a=\xFF");
+        assert_eq!(
+            decode(DecodePolicy::Lossy, b"a=\xFF", None),
+            "a=\u{FFFD}".as_bytes()
+        );
+        assert_eq!(offset(DecodePolicy::Strict, b"a=\xFF", None), 2);
+        assert_eq!(offset(DecodePolicy::Strict, b"\xEF\xBB\xBFa=\xFF", None), 5);
+    }
+
+    #[test]
+    fn utf16_offsets_count_surrogate_pairs() {
+        // BOM, then a surrogate pair, then a lone low surrogate
+        let mut data = vec![0xFF, 0xFE];
+        data.extend(utf16_le("\u{1F600}"));
+        data.extend([0x00, 0xDC]);
+        assert_eq!(offset(DecodePolicy::Strict, &data, None), 6);
+
+        assert_eq!(
+            offset(
+                DecodePolicy::Strict,
+                &[0x61, 0x00, 0x62],
+                Some(Encoding::Utf16Le)
+            ),
+            2
+        );
+    }
+}
@@ -0,0 +1,100 @@
+use crate::values::{insert_path, restore_lists};
+use vaerdi::{Map, Value};
+
+pub(crate) const ENV_PRIORITY: i32 = 10;
+pub(crate) const ENV_SEPARATOR: &str = "__";
+
+// Reads variables such as `MYAPP_DATABASE__HOST=foo` into `database.host = "foo"`.
+// Numeric segments index lists, so `MYAPP_SERVERS__0__HOST` sets `servers[0].host`.
+// The default separator is `__` rather than the single `_` of `MYAPP_SERVERS_0_HOST`,
+// since a single one can't tell `pool.max_connections` from `pool.max.connections`.
+// Layers built with a `_` separator read the single form, for keys without underscores
+pub(crate) struct EnvSource {
+    pub prefix: String,
+    pub separator: String,
+}
+
+impl EnvSource {
+    pub fn read(&self) -> Map {
+        let vars = std::env::vars_os().filter_map(|(key, value)| {
+            match (key.into_string(), value.into_string()) {
+                (Ok(key), Ok(value)) => Some((key, value)),
+                _ => None,
+            }
+        });
+
+        self.map(vars)
+    }
+
+    fn map(&self, vars: impl Iterator<Item = (String, String)>) -> Map {
+        let mut map = Map::default();
+
+        for (key, value) in vars {
+            let rest = match key.strip_prefix(&self.prefix) {
+                Some(rest) if !rest.is_empty() => rest,
+                _ => continue,
+            };
+
+            let path = rest
+                .split(self.separator.as_str())
+                .map(|m| m.to_lowercase())
+                .collect::<Vec<_>>();
+
+            if path.iter().any(|m| m.is_empty()) {
+                continue;
+            }
+
+            insert_path(&mut map, &path, Value::String(value));
+        }
+
+        let mut out = Map::default();
+        for (key, value) in map.into_iter() {
+            out.insert(key, restore_lists(value));
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EnvSource, ENV_SEPARATOR};
+    use vaerdi::{value, Map, Value};
+
+    fn map(separator: &str, vars: &[(&str, &str)]) -> Map {
+        let source = EnvSource {
+            prefix: "MYAPP_".to_string(),
+            separator: separator.to_string(),
+        };
+        source.map(vars.iter().map(|(k, v)| (k.to_string(), v.to_string())))
+    }
+
+    #[test]
+    fn nested_keys_and_lists() {
+        let map = map(
+            ENV_SEPARATOR,
+            &[
+                ("MYAPP_POOL__MAX_CONNECTIONS", "10"),
+                ("MYAPP_SERVERS__1__HOST", "b"),
+                ("MYAPP_SERVERS__0__HOST", "a"),
+                ("MYAPP_", "ignored"),
+                ("MYAPP_EMPTY____SEGMENT", "ignored"),
+                ("OTHER_NAME", "ignored"),
+            ],
+        );
+
+        assert_eq!(
+            Value::Map(map),
+            value!({
+                "pool": {"max_connections": "10"},
+                "servers": [{"host": "a"}, {"host": "b"}]
+            })
+        );
+    }
+
+    #[test]
+    fn single_underscore_separator() {
+        let map = map("_", &[("MYAPP_SERVERS_0_HOST", "a")]);
+        assert_eq!(Value::Map(map), value!({"servers": [{"host": "a"}]}));
+    }
+}
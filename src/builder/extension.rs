@@ -0,0 +1,39 @@
+use crate::Error;
+use std::path::Path;
+
+// Picks the encoder extension for a file. Suffixes in `strip` (such as `tmpl` in
+// `app.yaml.tmpl`) are dropped first, then the longest registered extension the
+// name ends with wins, ignoring case, so `APP.JSON` and `app.local.yaml` both resolve
+pub(crate) fn detect(path: &Path, known: &[&str], strip: &[String]) -> Result<String, Error> {
+    let name = match path.file_name() {
+        Some(name) => match name.to_str() {
+            Some(name) => name,
+            None => return Err(Error::NonUnicodePath(path.to_path_buf())),
+        },
+        None => return Ok(String::from("json")),
+    };
+
+    let mut parts = name.split('.').collect::<Vec<_>>();
+
+    while parts.len() > 1 {
+        let last = parts[parts.len() - 1];
+        if strip.iter().any(|m| m.eq_ignore_ascii_case(last)) {
+            parts.pop();
+        } else {
+            break;
+        }
+    }
+
+    // The first part is the file stem and never counts as an extension
+    for start in 1..parts.len() {
+        let candidate = parts[start..].join(".");
+        if let Some(ext) = known.iter().find(|m| m.eq_ignore_ascii_case(&candidate)) {
+            return Ok(ext.to_string());
+        }
+    }
+
+    match parts.len() {
+        1 => Ok(String::from("json")),
+        n => Ok(parts[n - 1].to_ascii_lowercase()),
+    }
+}
@@ -0,0 +1,215 @@
+use super::{
+    builder::ConfigBuilder,
+    layer::Layer,
+    watch::{VetoError, WatchableConfig},
+};
+use crate::{path::user_config_dir, Change, Config, Error};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    marker::PhantomData,
+    sync::{
+        mpsc::{Receiver, RecvError},
+        Arc, Mutex, Weak,
+    },
+};
+use vaerdi::Value;
+
+/// A typed application config with batteries included: the `Default` of `T`,
+/// overridden by `<app>.<ext>` files in the user config directory (e.g.
+/// `~/.config/<app>/`) and then the working directory, overridden by `<APP>_`
+/// environment variables, and reloaded whenever the files change.
+///
+/// Environment variables arrive as strings. Fields of other types need the
+/// builder's schema coercion, set up through [`with_builder`](Self::with_builder).
+pub struct Johnfig<T> {
+    inner: Arc<Inner<T>>,
+}
+
+struct Inner<T> {
+    watch: WatchableConfig,
+    // The typed value along with the config it came from
+    typed: Mutex<(Arc<Config>, Arc<T>)>,
+}
+
+impl<T> Johnfig<T>
+where
+    T: DeserializeOwned + Serialize + Default + Send + Sync + 'static,
+{
+    pub fn new(app: &str) -> Result<Johnfig<T>, Error> {
+        let mut builder = ConfigBuilder::new().with_name_pattern(format!("{app}.{{ext}}"));
+        // Only searched when it exists at startup
+        if let Some(dir) = user_config_dir().map(|dir| dir.join(app)) {
+            if dir.is_dir() {
+                builder = builder.with_search_path(dir)?;
+            }
+        }
+
+        Johnfig::with_builder(app, builder.with_current_path()?)
+    }
+
+    /// Like [`new`](Self::new), but searches where `builder` does. The defaults
+    /// of `T` go under the discovered files and the `<APP>_` variables over them.
+    pub fn with_builder(app: &str, builder: ConfigBuilder) -> Result<Johnfig<T>, Error> {
+        let defaults =
+            vaerdi::ser::to_value(T::default()).map_err(|err| Error::Unknown(Box::new(err)))?;
+
+        let watch = builder
+            .with_layer(Layer::Defaults(defaults))
+            .with_layer(Layer::Discovered)
+            .with_layer(Layer::Env(env_prefix(app)))
+            .build()?
+            .watch()?;
+
+        // A reload that doesn't deserialize is refused, so there always is a
+        // current value, and the reason is received on the watch events
+        watch.on_reload(|config, _| {
+            deserialize::<T>(config)
+                .map(|_| ())
+                .map_err(|err| VetoError::new(err.to_string()))
+        });
+
+        let config = watch.snapshot();
+        let typed = Arc::new(deserialize::<T>(&config)?);
+
+        Ok(Johnfig {
+            inner: Arc::new(Inner {
+                watch,
+                typed: Mutex::new((config, typed)),
+            }),
+        })
+    }
+
+    /// The current value.
+    pub fn get(&self) -> Arc<T> {
+        self.inner.get()
+    }
+
+    /// Receives the new value after each reload that changes the config. Ends
+    /// once the `Johnfig` is dropped.
+    pub fn subscribe(&self) -> Result<Subscription<T>, Error> {
+        Ok(Subscription {
+            changes: self.inner.watch.watch_pattern("**")?,
+            inner: Arc::downgrade(&self.inner),
+            _type: PhantomData,
+        })
+    }
+
+    /// The watched config underneath, for its events, key subscriptions and hooks.
+    pub fn watchable(&self) -> &WatchableConfig {
+        &self.inner.watch
+    }
+}
+
+impl<T: DeserializeOwned> Inner<T> {
+    fn get(&self) -> Arc<T> {
+        let config = self.watch.snapshot();
+        let mut typed = self.typed.lock().expect("typed config lock poisoned");
+
+        if !Arc::ptr_eq(&typed.0, &config) {
+            // Only a replaced config can fail here, since the hook refuses
+            // reloads that do. The last value that deserialized is kept then
+            if let Ok(value) = deserialize::<T>(&config) {
+                typed.1 = Arc::new(value);
+            }
+            typed.0 = config;
+        }
+
+        typed.1.clone()
+    }
+}
+
+/// New values of a [`Johnfig`], from [`Johnfig::subscribe`].
+pub struct Subscription<T> {
+    changes: Receiver<Vec<Change>>,
+    // Weak, so a subscription doesn't keep the watcher alive
+    inner: Weak<Inner<T>>,
+    _type: PhantomData<fn() -> T>,
+}
+
+impl<T: DeserializeOwned> Subscription<T> {
+    /// Blocks until the next reload that changes the config.
+    pub fn recv(&self) -> Result<Arc<T>, RecvError> {
+        self.changes.recv()?;
+        self.inner
+            .upgrade()
+            .map(|inner| inner.get())
+            .ok_or(RecvError)
+    }
+}
+
+impl<T: DeserializeOwned> Iterator for Subscription<T> {
+    type Item = Arc<T>;
+
+    fn next(&mut self) -> Option<Arc<T>> {
+        self.recv().ok()
+    }
+}
+
+fn deserialize<T: DeserializeOwned>(config: &Config) -> Result<T, Error> {
+    T::deserialize(Value::Map(config.inner.clone())).map_err(|error| Error::Deserialize {
+        files: config.files().to_vec(),
+        error,
+    })
+}
+
+// `my-app` reads `MY_APP_*`
+fn env_prefix(app: &str) -> String {
+    format!("{}_", app.to_uppercase().replace('-', "_"))
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use super::Johnfig;
+    use crate::{ConfigBuilder, DirLocator};
+    use serde::{Deserialize, Serialize};
+    use std::time::Duration;
+
+    #[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
+    struct App {
+        name: String,
+        workers: u64,
+    }
+
+    #[test]
+    fn files_override_defaults_and_reloads_are_typed() {
+        let dir = std::env::temp_dir().join(format!("johnfig-typed-{}", std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("typed.json"), r#"{ "name": "first" }"#).unwrap();
+
+        let builder = ConfigBuilder::new()
+            .with_name_pattern("typed.{ext}")
+            .with_locator(DirLocator(dir.clone()));
+        let johnfig = Johnfig::<App>::with_builder("johnfig-typed-test", builder).unwrap();
+        let updates = johnfig.subscribe().unwrap();
+
+        assert_eq!(
+            *johnfig.get(),
+            App {
+                name: "first".to_string(),
+                workers: 0,
+            }
+        );
+
+        // Doesn't deserialize, so the current value stays
+        std::fs::write(dir.join("typed.json"), r#"{ "workers": "many" }"#).unwrap();
+        let event = johnfig
+            .watchable()
+            .recv_timeout(Duration::from_secs(10))
+            .unwrap();
+        assert!(event.is_err());
+        assert_eq!(johnfig.get().name, "first");
+
+        std::fs::write(
+            dir.join("typed.json"),
+            r#"{ "name": "second", "workers": 4 }"#,
+        )
+        .unwrap();
+        let update = updates.recv().unwrap();
+        assert_eq!(update.name, "second");
+        assert_eq!(update.workers, 4);
+
+        drop(johnfig);
+        assert!(updates.recv().is_err());
+    }
+}
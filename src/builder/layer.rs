@@ -0,0 +1,87 @@
+use super::env::{EnvSource, ENV_SEPARATOR};
+use crate::provenance::Source;
+use std::path::PathBuf;
+use vaerdi::Value;
+
+pub(crate) const FILE_PRIORITY: i32 = 0;
+
+/// Explicitly ordered sources; each one overrides the layers before it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Layer {
+    Defaults(Value),
+    Value(String, Value),
+    Env(String),
+    File(PathBuf),
+    // Where discovered files are merged
+    Discovered,
+}
+
+pub(crate) enum LayerSource {
+    Value(Value),
+    // Merged like a value, but also counted as part of the config's defaults
+    Defaults(Value),
+    // Read again on every load, so changes to the environment are picked up
+    Env(EnvSource),
+    File(PathBuf),
+    // Read only when it exists, e.g. a user file that is created on first save
+    OptionalFile(PathBuf),
+}
+
+pub(crate) struct ValueLayer {
+    pub name: String,
+    pub priority: i32,
+    pub source: LayerSource,
+}
+
+impl ValueLayer {
+    pub fn overrides_files(&self) -> bool {
+        self.priority > FILE_PRIORITY
+    }
+
+    pub fn is_defaults(&self) -> bool {
+        matches!(self.source, LayerSource::Defaults(_))
+    }
+
+    pub fn source(&self) -> Source {
+        match &self.source {
+            LayerSource::Defaults(_) => Source::Defaults,
+            LayerSource::File(path) | LayerSource::OptionalFile(path) => Source::File(path.clone()),
+            _ => Source::Layer(self.name.clone()),
+        }
+    }
+}
+
+// Turns the ordered layers into prioritized ones, relative to discovered files
+pub(crate) fn order(layers: Vec<Layer>) -> Vec<ValueLayer> {
+    let discovered = layers
+        .iter()
+        .position(|m| *m == Layer::Discovered)
+        .map(|idx| idx as i32)
+        .unwrap_or(-1);
+
+    layers
+        .into_iter()
+        .enumerate()
+        .filter_map(|(idx, layer)| {
+            let priority = idx as i32 - discovered;
+            let (name, source) = match layer {
+                Layer::Discovered => return None,
+                Layer::Defaults(value) => ("defaults".to_string(), LayerSource::Defaults(value)),
+                Layer::Value(name, value) => (name, LayerSource::Value(value)),
+                Layer::Env(prefix) => (
+                    format!("env:{}", prefix),
+                    LayerSource::Env(EnvSource {
+                        prefix,
+                        separator: ENV_SEPARATOR.to_string(),
+                    }),
+                ),
+                Layer::File(path) => (path.display().to_string(), LayerSource::File(path)),
+            };
+            Some(ValueLayer {
+                name,
+                priority,
+                source,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,190 @@
+use super::builder::{ConfigFinder, Prepared};
+use super::config_file::ConfigFile;
+use crate::{Config, Error};
+use serde::de::DeserializeOwned;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Mutex, RwLock},
+};
+use vaerdi::{Map, Value};
+
+/// Discovers files up front and reads none of them until the first lookup.
+///
+/// A lookup parses only the files that may set its top level section, merges
+/// that section and caches it. A file that never spells out the key can't set
+/// it, so it is left unparsed; files with escapes or binary content are always
+/// parsed. Parsed files are kept for later lookups, and the default closure,
+/// the layers and the file stamps are worked out once, on the first lookup.
+pub struct LazyConfig {
+    finder: ConfigFinder,
+    files: Vec<PathBuf>,
+    state: Mutex<Option<State>>,
+    sections: RwLock<HashMap<String, Option<Value>>>,
+}
+
+impl LazyConfig {
+    pub(super) fn new(finder: ConfigFinder) -> Result<LazyConfig, Error> {
+        let files = finder.selected_files()?;
+
+        Ok(LazyConfig {
+            finder,
+            files,
+            state: Mutex::new(None),
+            sections: RwLock::new(HashMap::default()),
+        })
+    }
+
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    pub fn get(&self, name: impl AsRef<str>) -> Result<Option<Value>, Error> {
+        let name = name.as_ref();
+
+        if let Some(section) = self
+            .sections
+            .read()
+            .expect("sections lock poisoned")
+            .get(name)
+        {
+            return Ok(section.clone());
+        }
+
+        let mut state = self.state.lock().expect("lazy state lock poisoned");
+        if state.is_none() {
+            *state = Some(State {
+                prepared: self.finder.prepare(&self.files)?,
+                contents: self.finder.contents()?,
+                files: self.files.iter().map(|_| FileState::Unread).collect(),
+            });
+        }
+        let state = state.as_mut().expect("lazy state is set above");
+
+        for (path, file) in self.files.iter().zip(state.files.iter_mut()) {
+            file.parse_for(&self.finder, path, name)?;
+        }
+
+        let files = state
+            .files
+            .iter()
+            .filter_map(FileState::admitted)
+            .collect::<Vec<_>>();
+        let section = self
+            .finder
+            .merge_section(name, &files, &state.contents, &state.prepared)?;
+
+        self.sections
+            .write()
+            .expect("sections lock poisoned")
+            .insert(name.to_string(), section.clone());
+
+        Ok(section)
+    }
+
+    pub fn try_get<T: DeserializeOwned>(&self, name: &str) -> Result<T, Error> {
+        let value = self.get(name)?.unwrap_or(Value::Null);
+        T::deserialize(value).map_err(|error| Error::Deserialize {
+            files: self.files.clone(),
+            error,
+        })
+    }
+
+    /// Loads everything, exactly like ConfigFinder::config.
+    pub fn config(&self) -> Result<Config, Error> {
+        self.finder.config()
+    }
+}
+
+// What the first lookup works out for all of them
+struct State {
+    prepared: Prepared,
+    contents: Vec<(PathBuf, String, Map)>,
+    // One per selected file, in merge order
+    files: Vec<FileState>,
+}
+
+enum FileState {
+    Unread,
+    // Read and decoded, but not needed by any lookup so far
+    Read(Vec<u8>),
+    Parsed(ConfigFile<Map>),
+    // Left out by the file predicate
+    Vetoed,
+}
+
+impl FileState {
+    // A file that fails to parse stays read, and fails again on the next lookup
+    fn parse_for(&mut self, finder: &ConfigFinder, path: &Path, key: &str) -> Result<(), Error> {
+        if let FileState::Unread = self {
+            *self = FileState::Read(finder.read_decoded(path)?);
+        }
+
+        let data = match &*self {
+            FileState::Read(data) if may_set(data, key) => data,
+            _ => return Ok(()),
+        };
+
+        let file = finder.parse_decoded(path.to_path_buf(), data)?;
+        *self = if finder.admit(&file)? {
+            FileState::Parsed(file)
+        } else {
+            FileState::Vetoed
+        };
+
+        Ok(())
+    }
+
+    fn admitted(&self) -> Option<&ConfigFile<Map>> {
+        match self {
+            FileState::Parsed(file) => Some(file),
+            _ => None,
+        }
+    }
+}
+
+// Whether a document may set the top level `key`. Every text format spells keys
+// out unless they are escaped (`\u0073erver`, `&#115;erver`), so only documents
+// with escapes, or that aren't UTF-8 at all, need parsing without the key
+fn may_set(data: &[u8], key: &str) -> bool {
+    match std::str::from_utf8(data) {
+        Ok(text) => text.contains(key) || text.contains('\\') || text.contains('&'),
+        Err(_) => true,
+    }
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use super::may_set;
+    use crate::{ConfigBuilder, DirLocator};
+    use vaerdi::value;
+
+    #[test]
+    fn documents_without_the_key_cant_set_it() {
+        assert!(may_set(br#"{ "server": {} }"#, "server"));
+        assert!(!may_set(br#"{ "client": {} }"#, "server"));
+        assert!(may_set(br#"{ "\u0073erver": {} }"#, "server"));
+        assert!(may_set(b"bplist00\xd1\x01", "server"));
+    }
+
+    #[test]
+    fn only_files_naming_a_section_are_parsed() {
+        let dir = std::env::temp_dir().join(format!("johnfig-lazy-{}", std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.json"), r#"{ "server": { "port": 1 } }"#).unwrap();
+        // Doesn't parse, but never needs to for `server`
+        std::fs::write(dir.join("b.json"), r#"{ "client": "#).unwrap();
+
+        let lazy = ConfigBuilder::new()
+            .with_name_pattern("*.{ext}")
+            .with_locator(DirLocator(dir))
+            .build()
+            .unwrap()
+            .lazy()
+            .unwrap();
+
+        assert_eq!(lazy.get("server").unwrap(), Some(value!({ "port": 1 })));
+        assert!(lazy.get("client").is_err());
+    }
+}
@@ -0,0 +1,73 @@
+use crate::Error;
+use std::path::Path;
+use vaerdi::{Map, Value};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Limits {
+    pub max_depth: Option<usize>,
+    pub max_keys: Option<usize>,
+}
+
+impl Limits {
+    pub fn check(&self, map: &Map, path: &Path) -> Result<(), Error> {
+        if self.max_depth.is_none() && self.max_keys.is_none() {
+            return Ok(());
+        }
+
+        let (depth, keys) = measure_map(map);
+
+        if let Some(max) = self.max_depth {
+            if depth > max {
+                return Err(limit_error(
+                    path,
+                    format!("depth {} exceeds {}", depth, max),
+                ));
+            }
+        }
+
+        if let Some(max) = self.max_keys {
+            if keys > max {
+                return Err(limit_error(path, format!("{} keys exceeds {}", keys, max)));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn limit_error(path: &Path, reason: String) -> Error {
+    Error::LimitExceeded {
+        path: path.to_path_buf(),
+        reason,
+    }
+}
+
+fn measure_map(map: &Map) -> (usize, usize) {
+    let mut depth = 0;
+    let mut keys = 0;
+
+    for (_, value) in map.iter() {
+        let (d, k) = measure(value);
+        depth = depth.max(d);
+        keys += k + 1;
+    }
+
+    (depth + 1, keys)
+}
+
+fn measure(value: &Value) -> (usize, usize) {
+    match value {
+        Value::Map(map) => measure_map(map),
+        Value::List(list) => {
+            let mut depth = 0;
+            let mut keys = 0;
+            for item in list.iter() {
+                let (d, k) = measure(item);
+                depth = depth.max(d);
+                keys += k;
+            }
+            (depth + 1, keys)
+        }
+        _ => (0, 0),
+    }
+}
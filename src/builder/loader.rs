@@ -0,0 +1,125 @@
+use std::sync::Arc;
+use toback::{Encoder, Error as TobackError, Toback, TobackBuilder};
+use vaerdi::Map;
+
+type SharedEncoder = Arc<dyn Encoder<Map> + Send + Sync>;
+
+#[derive(Clone)]
+struct Registered {
+    // The type of the encoder, to tell encoders for the same extension apart
+    name: &'static str,
+    encoder: SharedEncoder,
+}
+
+// The encoders added to a builder, on top of the ones toback comes with. An
+// encoder takes over the extensions it names from everything added before it,
+// so adding `Properties::with_duplicates(..)` replaces the default one
+#[derive(Clone, Default)]
+pub(crate) struct Encoders(Vec<Registered>);
+
+impl Encoders {
+    pub fn add<E: Encoder<Map> + Send + Sync + 'static>(&mut self, encoder: E) {
+        let encoder = Registered {
+            name: std::any::type_name::<E>(),
+            encoder: Arc::new(encoder),
+        };
+        // Encoders with no extension of their own left are dropped
+        self.0.retain(|m| {
+            m.encoder
+                .extensions()
+                .iter()
+                .any(|ext| !encoder.encoder.extensions().contains(ext))
+        });
+        self.0.push(encoder);
+    }
+
+    pub fn build(&self) -> Loader {
+        Loader {
+            base: TobackBuilder::default().build(),
+            encoders: self.0.clone(),
+        }
+    }
+}
+
+// Loads and saves by extension, with the added encoders before toback's own
+pub(crate) struct Loader {
+    base: Toback<Map>,
+    encoders: Vec<Registered>,
+}
+
+impl Loader {
+    pub fn extensions(&self) -> Vec<&str> {
+        let mut out = self.base.extensions().iter().copied().collect::<Vec<_>>();
+        for registered in &self.encoders {
+            for ext in registered.encoder.extensions() {
+                if !out.contains(ext) {
+                    out.push(ext);
+                }
+            }
+        }
+        out
+    }
+
+    pub fn load(&self, content: &[u8], ext: &str) -> Result<Map, TobackError> {
+        match self.encoder(ext) {
+            Some(encoder) => encoder.load(content),
+            None => self.base.load(content, ext),
+        }
+    }
+
+    pub fn save(&self, content: &Map, ext: &str) -> Result<Vec<u8>, TobackError> {
+        match self.encoder(ext) {
+            Some(encoder) => encoder.save(content),
+            None => self.base.save(content, ext),
+        }
+    }
+
+    // Each extension with the encoder it is loaded with, for cache keys
+    pub fn describe(&self) -> Vec<String> {
+        self.extensions()
+            .into_iter()
+            .map(|ext| match self.registered(ext) {
+                Some(registered) => format!("{}={}", ext, registered.name),
+                None => format!("{}=toback", ext),
+            })
+            .collect()
+    }
+
+    fn registered(&self, ext: &str) -> Option<&Registered> {
+        self.encoders
+            .iter()
+            .rev()
+            .find(|m| m.encoder.extensions().contains(&ext))
+    }
+
+    fn encoder(&self, ext: &str) -> Option<&SharedEncoder> {
+        self.registered(ext).map(|m| &m.encoder)
+    }
+}
+
+// Locator scoped encoders are used as given
+impl From<Toback<Map>> for Loader {
+    fn from(base: Toback<Map>) -> Loader {
+        Loader {
+            base,
+            encoders: Vec::new(),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "properties"))]
+mod tests {
+    use super::Encoders;
+    use crate::formats::{DuplicateKeys, Properties};
+
+    #[test]
+    fn encoders_replace_earlier_ones_by_extension() {
+        let mut encoders = Encoders::default();
+        encoders.add(Properties::default());
+        encoders.add(Properties::with_duplicates(DuplicateKeys::Error));
+        assert_eq!(encoders.0.len(), 1);
+
+        let loader = encoders.build();
+        assert!(loader.load(b"a=1\na=2\n", "properties").is_err());
+    }
+}
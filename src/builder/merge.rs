@@ -0,0 +1,167 @@
+use super::report::{MergeReport, Override};
+use crate::{key_path::KeyPath, Error};
+use std::mem::discriminant;
+use vaerdi::{merge, List, Map, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    Overwrite,
+    Warn,
+    Error,
+}
+
+impl Default for ConflictPolicy {
+    fn default() -> Self {
+        ConflictPolicy::Overwrite
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Merger {
+    pub policy: ConflictPolicy,
+    pub prefixes: Vec<(KeyPath, ConflictPolicy)>,
+    // Splits a string overriding a list, e.g. `hosts=a,b,c` from the environment
+    pub delimiter: Option<char>,
+    pub delimiters: Vec<(KeyPath, char)>,
+}
+
+impl Merger {
+    pub fn merge_map(
+        &self,
+        target: &mut Map,
+        other: Map,
+        origin: &str,
+        mut report: Option<&mut MergeReport>,
+    ) -> Result<(), Error> {
+        self.merge_into(&KeyPath::root(), target, other, origin, &mut report)
+    }
+
+    fn policy_for(&self, path: &KeyPath) -> ConflictPolicy {
+        self.prefixes
+            .iter()
+            .filter(|(prefix, _)| path.starts_with(prefix))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, policy)| *policy)
+            .unwrap_or(self.policy)
+    }
+
+    pub fn delimiter_for(&self, path: &KeyPath) -> Option<char> {
+        self.delimiters
+            .iter()
+            .filter(|(prefix, _)| path.starts_with(prefix))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, delimiter)| *delimiter)
+            .or(self.delimiter)
+    }
+
+    fn merge_into(
+        &self,
+        path: &KeyPath,
+        target: &mut Map,
+        other: Map,
+        origin: &str,
+        report: &mut Option<&mut MergeReport>,
+    ) -> Result<(), Error> {
+        for (key, value) in other.into_iter() {
+            match target.get_mut(&key) {
+                Some(prev) => self.merge_value(&path.key(&key), prev, value, origin, report)?,
+                None => {
+                    target.insert(key, value);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn merge_value(
+        &self,
+        path: &KeyPath,
+        prev: &mut Value,
+        value: Value,
+        origin: &str,
+        report: &mut Option<&mut MergeReport>,
+    ) -> Result<(), Error> {
+        match (prev, value) {
+            (Value::Map(prev), Value::Map(value)) => {
+                self.merge_into(path, prev, value, origin, report)
+            }
+            (prev, value) => {
+                let value = match (&*prev, value) {
+                    (Value::List(_), Value::String(string)) => match self.delimiter_for(path) {
+                        Some(delimiter) => split(&string, delimiter),
+                        None => Value::String(string),
+                    },
+                    (_, value) => value,
+                };
+
+                if is_conflict(prev, &value) {
+                    match self.policy_for(path) {
+                        ConflictPolicy::Overwrite => {}
+                        ConflictPolicy::Warn => tracing::warn!(
+                            "{} overrides {} at {} with {}",
+                            origin,
+                            kind(prev),
+                            path,
+                            kind(&value)
+                        ),
+                        ConflictPolicy::Error => {
+                            return Err(Error::MergeConflict {
+                                path: path.clone(),
+                                origin: origin.to_string(),
+                                existing: kind(prev),
+                                incoming: kind(&value),
+                            })
+                        }
+                    }
+                }
+                // Only values that actually change are worth reporting
+                let previous = match report {
+                    Some(_) if !matches!(prev, Value::Null) && *prev != value => Some(prev.clone()),
+                    _ => None,
+                };
+
+                merge(prev, value);
+
+                if let (Some(report), Some(previous)) = (report.as_deref_mut(), previous) {
+                    report.overrides.push(Override {
+                        path: path.clone(),
+                        origin: origin.to_string(),
+                        previous,
+                        value: prev.clone(),
+                    });
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+pub(super) fn split(string: &str, delimiter: char) -> Value {
+    let mut list = List::default();
+    for item in string
+        .split(delimiter)
+        .map(str::trim)
+        .filter(|m| !m.is_empty())
+    {
+        list.push(Value::String(item.to_string()));
+    }
+    Value::List(list)
+}
+
+fn is_conflict(prev: &Value, value: &Value) -> bool {
+    !matches!(prev, Value::Null)
+        && !matches!(value, Value::Null)
+        && discriminant(prev) != discriminant(value)
+}
+
+pub(crate) fn kind(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Map(_) => "map",
+        Value::List(_) => "list",
+        Value::String(_) => "string",
+        Value::Bool(_) => "bool",
+        _ => "scalar",
+    }
+}
@@ -1,7 +1,75 @@
+#[cfg(feature = "async")]
+mod async_finder;
 mod builder;
+#[cfg(feature = "cache")]
+mod cache;
+mod check;
+mod command;
 mod config_file;
+mod decode;
+mod env;
+mod extension;
+#[cfg(feature = "watch")]
+mod johnfig;
+mod layer;
+mod lazy;
+mod limits;
+mod loader;
+mod merge;
+mod pattern;
+mod plan;
+mod profile;
+mod relative;
+mod remap;
+mod report;
+#[cfg(feature = "schemars")]
+mod schema;
+mod scope;
+mod search_plan;
+#[cfg(feature = "watch")]
+mod shared_watcher;
+mod sibling;
+mod sort;
+mod spec;
+mod split;
+mod trace;
+mod trust;
+mod validator;
+#[cfg(feature = "watch")]
+mod watch;
+
+pub(crate) use self::{
+    builder::{default_loader, save_file},
+    split::save_split,
+};
 
 pub use self::{
     builder::{ConfigBuilder, ConfigFinder},
+    check::{CheckReport, Diagnostic, Severity},
+    command::CommandResolver,
     config_file::ConfigFile,
+    decode::{DecodePolicy, Encoding},
+    layer::Layer,
+    lazy::LazyConfig,
+    merge::ConflictPolicy,
+    plan::SourceDescriptor,
+    report::{MergeReport, Override},
+    scope::Scope,
+    search_plan::{SearchPlan, SearchStep},
+    sibling::SiblingPolicy,
+    sort::SortPolicy,
+    split::SplitPlan,
+    trust::Trust,
+    validator::ValidationError,
+};
+
+#[cfg(feature = "async")]
+pub use self::async_finder::{AsyncConfigFinder, BlockingTask, Spawner};
+
+#[cfg(feature = "watch")]
+pub use self::johnfig::{Johnfig, Subscription};
+
+#[cfg(feature = "watch")]
+pub use self::watch::{
+    ConfigCell, TypedWatch, VetoError, WatchEvent, WatchMode, WatchOptions, WatchableConfig,
 };
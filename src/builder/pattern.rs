@@ -0,0 +1,140 @@
+use crate::Error;
+use std::collections::HashSet;
+use vaerdi::Value;
+
+pub(crate) const OPTIONAL_PREFIXES: &[char] = &['-', '.', '_'];
+
+pub(crate) struct NameTemplate {
+    pub template: String,
+    // Variables of the optional segments this template includes
+    pub optional: Vec<String>,
+}
+
+// Expands optional segments such as `app{-profile}.{ext}` into one template
+// with the segment (`app-{profile}.{ext}`) and one without (`app.{ext}`)
+pub(crate) fn expand(pattern: &str) -> Vec<NameTemplate> {
+    let mut out = vec![NameTemplate {
+        template: String::new(),
+        optional: Vec::new(),
+    }];
+
+    let mut rest = pattern;
+
+    while let Some(start) = rest.find('{') {
+        let (literal, tail) = rest.split_at(start);
+        push_all(&mut out, literal);
+
+        match optional_segment(tail) {
+            Some((prefix, name, len)) => {
+                let segment = format!("{}{{{}}}", prefix, name);
+                out = out
+                    .into_iter()
+                    .flat_map(|m| {
+                        let mut optional = m.optional.clone();
+                        optional.push(name.to_string());
+                        let included = NameTemplate {
+                            template: format!("{}{}", m.template, segment),
+                            optional,
+                        };
+                        [included, m]
+                    })
+                    .collect();
+                rest = &tail[len..];
+            }
+            None => {
+                push_all(&mut out, "{");
+                rest = &tail[1..];
+            }
+        }
+    }
+
+    push_all(&mut out, rest);
+
+    out
+}
+
+fn push_all(templates: &mut [NameTemplate], literal: &str) {
+    for template in templates {
+        template.template.push_str(literal);
+    }
+}
+
+fn optional_segment(s: &str) -> Option<(char, &str, usize)> {
+    let end = s.find('}')?;
+    let mut chars = s[1..end].chars();
+
+    let prefix = chars.next()?;
+    if !OPTIONAL_PREFIXES.contains(&prefix) {
+        return None;
+    }
+
+    let name = chars.as_str();
+    if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        return None;
+    }
+
+    Some((prefix, name, end + 1))
+}
+
+pub(crate) struct Rendered {
+    pub name: String,
+    // The profile the name was rendered for, when its template uses one
+    pub profile: Option<String>,
+}
+
+pub(crate) fn render(names: &[NameTemplate], contexts: &[Value]) -> Result<Vec<Rendered>, Error> {
+    let mut templates = tinytemplate::TinyTemplate::new();
+
+    for name in names {
+        templates
+            .add_template(&name.template, &name.template)
+            .map_err(|err| Error::Unknown(Box::new(err)))?;
+    }
+
+    let mut seen = HashSet::new();
+    let mut out = Vec::new();
+
+    for ctx in contexts {
+        for name in names {
+            // A template with an optional segment whose value is missing from the
+            // context is left out. Any other failure is a real error
+            if !name.optional.iter().all(|var| is_set(ctx, var)) {
+                continue;
+            }
+
+            let ret = templates
+                .render(&name.template, ctx)
+                .map_err(|err| Error::Unknown(Box::new(err)))?;
+
+            if seen.insert(ret.clone()) {
+                let profile = match ctx {
+                    Value::Map(map) if name.template.contains("{profile}") => {
+                        match map.get("profile") {
+                            Some(Value::String(profile)) => Some(profile.clone()),
+                            _ => None,
+                        }
+                    }
+                    _ => None,
+                };
+
+                out.push(Rendered { name: ret, profile });
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+fn is_set(ctx: &Value, name: &str) -> bool {
+    match ctx {
+        Value::Map(map) => !matches!(map.get(name), None | Some(Value::Null)),
+        _ => false,
+    }
+}
+
+pub(crate) fn compile(names: &[Rendered]) -> Result<Vec<glob::Pattern>, Error> {
+    names
+        .iter()
+        .map(|m| glob::Pattern::new(&m.name).map_err(|err| Error::Unknown(Box::new(err))))
+        .collect()
+}
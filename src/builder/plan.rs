@@ -0,0 +1,19 @@
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SourceDescriptor {
+    Defaults,
+    Layer {
+        name: String,
+        priority: i32,
+    },
+    File {
+        path: PathBuf,
+        root: Option<PathBuf>,
+        pattern: Option<String>,
+    },
+    Content {
+        name: String,
+        root: PathBuf,
+    },
+}
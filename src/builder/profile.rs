@@ -0,0 +1,60 @@
+use super::pattern::Rendered;
+use crate::{path::MATCH_OPTIONS, Error};
+use std::{collections::HashMap, path::Path};
+
+// Resolves the inheritance chain of a profile, base profile first
+pub(crate) fn chain(
+    profile: Option<&str>,
+    parents: &HashMap<String, String>,
+) -> Result<Vec<String>, Error> {
+    let mut out = Vec::new();
+    let mut next = profile;
+
+    while let Some(profile) = next {
+        if out.iter().any(|m| m == profile) {
+            out.push(profile.to_string());
+            out.reverse();
+            return Err(Error::ProfileCycle(out));
+        }
+        out.push(profile.to_string());
+        next = parents.get(profile).map(|m| m.as_str());
+    }
+
+    out.reverse();
+    Ok(out)
+}
+
+// The names rendered for a profile, ranked by the profile's place in the chain,
+// so a derived profile is merged after the profiles it extends
+pub(crate) fn ranks(rendered: &[Rendered], chain: &[String]) -> HashMap<String, usize> {
+    rendered
+        .iter()
+        .filter_map(|m| {
+            let profile = m.profile.as_ref()?;
+            let idx = chain.iter().position(|p| p == profile)?;
+            Some((m.name.clone(), idx + 1))
+        })
+        .collect()
+}
+
+// The highest rank among the patterns the file name matches. Matching goes by
+// whole rendered names, so `production.json` is no `prod` file. Files that match
+// no profile name rank lowest
+pub(crate) fn rank(
+    patterns: &[glob::Pattern],
+    ranks: &HashMap<String, usize>,
+    path: &Path,
+) -> usize {
+    let name = match path.file_name() {
+        Some(name) => Path::new(name),
+        None => return 0,
+    };
+
+    patterns
+        .iter()
+        .filter(|pattern| pattern.matches_path_with(name, MATCH_OPTIONS))
+        .filter_map(|pattern| ranks.get(pattern.as_str()))
+        .copied()
+        .max()
+        .unwrap_or(0)
+}
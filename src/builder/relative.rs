@@ -0,0 +1,111 @@
+use crate::{values::walk_mut, Error, Segment};
+use std::path::{Component, Path, PathBuf};
+use vaerdi::{Map, Value};
+
+// Keys, matched by name, whose string values are paths relative to the file that
+// set them, e.g. `*_path` or `*_dir`
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PathKeys(Vec<glob::Pattern>);
+
+impl PathKeys {
+    pub fn add(&mut self, pattern: &str) -> Result<(), Error> {
+        let pattern = glob::Pattern::new(pattern).map_err(|err| Error::Unknown(Box::new(err)))?;
+        self.0.push(pattern);
+        Ok(())
+    }
+
+    pub fn patterns(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(glob::Pattern::as_str)
+    }
+
+    fn matches(&self, key: &str) -> bool {
+        self.0.iter().any(|pattern| pattern.matches(key))
+    }
+
+    pub fn resolve(&self, map: &mut Map, dir: &Path) {
+        if self.0.is_empty() {
+            return;
+        }
+
+        let mut value = Value::Map(std::mem::take(map));
+
+        walk_mut(&mut value, |path, value| {
+            let key = match path.segments().last() {
+                Some(Segment::Key(key)) => key,
+                _ => return,
+            };
+
+            if !self.matches(key) {
+                return;
+            }
+
+            match value {
+                Value::List(list) => {
+                    for item in list.iter_mut() {
+                        resolve_value(item, dir);
+                    }
+                }
+                value => resolve_value(value, dir),
+            }
+        });
+
+        if let Value::Map(value) = value {
+            *map = value;
+        }
+    }
+}
+
+fn resolve_value(value: &mut Value, dir: &Path) {
+    let path = match value {
+        Value::String(path) if Path::new(path.as_str()).is_relative() => join(dir, path.as_str()),
+        _ => return,
+    };
+
+    // Non unicode paths are left as written, since values are strings
+    if let Some(path) = path.to_str() {
+        *value = Value::String(path.to_string());
+    }
+}
+
+// `./templates` joins as `templates`, so resolved paths carry no `.` segments
+fn join(dir: &Path, path: &str) -> PathBuf {
+    Path::new(path)
+        .components()
+        .filter(|m| *m != Component::CurDir)
+        .fold(dir.to_path_buf(), |dir, m| dir.join(m))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PathKeys;
+    use std::path::Path;
+    use vaerdi::{value, List, Value};
+
+    #[test]
+    fn resolves_against_the_dir() {
+        let mut keys = PathKeys::default();
+        keys.add("*_dir").unwrap();
+
+        let dir = Path::new("etc").join("app");
+        let mut map = match value!({
+            "templates_dir": "./templates",
+            "static_dir": ["assets", "."],
+            "name": "./app"
+        }) {
+            Value::Map(map) => map,
+            _ => unreachable!(),
+        };
+        keys.resolve(&mut map, &dir);
+
+        let path = |path: &Path| Value::String(path.to_str().unwrap().to_string());
+        assert_eq!(
+            map.get("templates_dir"),
+            Some(&path(&dir.join("templates")))
+        );
+        let mut list = List::default();
+        list.push(path(&dir.join("assets")));
+        list.push(path(&dir));
+        assert_eq!(map.get("static_dir"), Some(&Value::List(list)));
+        assert_eq!(map.get("name"), Some(&value!("./app")));
+    }
+}
@@ -0,0 +1,41 @@
+use std::path::{Path, PathBuf};
+
+// Maps real-looking roots such as `/etc/myapp` onto fixture trees, and back again
+// in search plans and source descriptors
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Remap(Vec<(PathBuf, PathBuf)>);
+
+impl Remap {
+    pub fn add(&mut self, from: PathBuf, to: PathBuf) {
+        self.0.push((from, to));
+        // The most specific root wins
+        self.0
+            .sort_by(|a, b| b.0.components().count().cmp(&a.0.components().count()));
+    }
+
+    pub fn apply(&self, path: PathBuf) -> PathBuf {
+        for (from, to) in &self.0 {
+            if let Ok(rest) = path.strip_prefix(from) {
+                return join(to, rest);
+            }
+        }
+        path
+    }
+
+    pub fn reverse(&self, path: &Path) -> PathBuf {
+        for (from, to) in &self.0 {
+            if let Ok(rest) = path.strip_prefix(to) {
+                return join(from, rest);
+            }
+        }
+        path.to_path_buf()
+    }
+}
+
+fn join(root: &Path, rest: &Path) -> PathBuf {
+    if rest.as_os_str().is_empty() {
+        root.to_path_buf()
+    } else {
+        root.join(rest)
+    }
+}
@@ -0,0 +1,15 @@
+use crate::KeyPath;
+use vaerdi::Value;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Override {
+    pub path: KeyPath,
+    pub origin: String,
+    pub previous: Value,
+    pub value: Value,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MergeReport {
+    pub overrides: Vec<Override>,
+}
@@ -0,0 +1,186 @@
+use super::merge::{kind, split, Merger};
+use crate::{Coercion, Error, KeyPath};
+use schemars::{
+    schema::{
+        InstanceType, ObjectValidation, RootSchema, Schema as JsonSchemaDef, SchemaObject,
+        SingleOrVec,
+    },
+    JsonSchema,
+};
+use serde::de::DeserializeOwned;
+use std::path::PathBuf;
+use vaerdi::{List, Map, Value};
+
+pub(crate) struct Schema {
+    root: RootSchema,
+    validate: Box<dyn Fn(Map) -> Result<(), vaerdi::de::DeserializerError> + Send + Sync>,
+}
+
+impl Schema {
+    pub fn new<T: JsonSchema + DeserializeOwned>() -> Schema {
+        Schema {
+            root: schemars::schema_for!(T),
+            validate: Box::new(|map| T::deserialize(Value::Map(map)).map(|_| ())),
+        }
+    }
+
+    pub fn root(&self) -> &RootSchema {
+        &self.root
+    }
+
+    pub fn json(&self) -> String {
+        serde_json::to_string_pretty(&self.root).expect("json schema serializes")
+    }
+
+    pub fn validate(&self, map: &Map, files: &[PathBuf]) -> Result<(), Error> {
+        (self.validate)(map.clone()).map_err(|error| Error::Deserialize {
+            files: files.to_vec(),
+            error,
+        })
+    }
+}
+
+impl Schema {
+    // Converts obviously convertible scalars to the types the schema expects,
+    // e.g. "8080" to an integer or a single value to a one element list. A
+    // string is split on the list delimiter of its key first, so `A=x,y` from
+    // the environment becomes two elements rather than one
+    pub fn coerce(&self, map: &mut Map, origin: &str, merger: &Merger) -> Vec<Coercion> {
+        let mut out = Vec::new();
+
+        if let Some(object) = self.root.schema.object.as_deref() {
+            self.coerce_properties(object, map, &KeyPath::root(), origin, merger, &mut out);
+        }
+
+        out
+    }
+
+    fn coerce_properties(
+        &self,
+        object: &ObjectValidation,
+        map: &mut Map,
+        path: &KeyPath,
+        origin: &str,
+        merger: &Merger,
+        out: &mut Vec<Coercion>,
+    ) {
+        for (key, value) in map.iter_mut() {
+            if let Some(schema) = object.properties.get(&key.to_string()) {
+                self.coerce_value(schema, value, &path.key(key), origin, merger, out);
+            }
+        }
+    }
+
+    fn coerce_value(
+        &self,
+        schema: &JsonSchemaDef,
+        value: &mut Value,
+        path: &KeyPath,
+        origin: &str,
+        merger: &Merger,
+        out: &mut Vec<Coercion>,
+    ) {
+        let schema = match self.resolve(schema) {
+            Some(schema) => schema,
+            None => return,
+        };
+
+        if let Some(coerced) = coerce_scalar(schema, value, merger.delimiter_for(path)) {
+            out.push(Coercion {
+                origin: origin.to_string(),
+                path: path.clone(),
+                from: kind(value),
+                to: kind(&coerced),
+            });
+            *value = coerced;
+        }
+
+        match value {
+            Value::Map(map) => {
+                if let Some(object) = schema.object.as_deref() {
+                    self.coerce_properties(object, map, path, origin, merger, out);
+                }
+            }
+            Value::List(list) => {
+                if let Some(SingleOrVec::Single(items)) =
+                    schema.array.as_deref().and_then(|m| m.items.as_ref())
+                {
+                    for (idx, item) in list.iter_mut().enumerate() {
+                        self.coerce_value(items, item, &path.index(idx), origin, merger, out);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn resolve<'a>(&'a self, schema: &'a JsonSchemaDef) -> Option<&'a SchemaObject> {
+        let object = match schema {
+            JsonSchemaDef::Object(object) => object,
+            JsonSchemaDef::Bool(_) => return None,
+        };
+
+        match &object.reference {
+            Some(reference) => {
+                let name = reference.strip_prefix("#/definitions/")?;
+                self.resolve(self.root.definitions.get(name)?)
+            }
+            None => Some(object),
+        }
+    }
+}
+
+fn allows(schema: &SchemaObject, ty: InstanceType) -> bool {
+    match &schema.instance_type {
+        Some(SingleOrVec::Single(single)) => **single == ty,
+        Some(SingleOrVec::Vec(types)) => types.contains(&ty),
+        None => false,
+    }
+}
+
+fn coerce_scalar(schema: &SchemaObject, value: &Value, delimiter: Option<char>) -> Option<Value> {
+    if let Value::String(string) = value {
+        if allows(schema, InstanceType::String) {
+            return None;
+        }
+
+        let string = string.trim();
+
+        if allows(schema, InstanceType::Integer) {
+            if let Ok(int) = string.parse::<i64>() {
+                return vaerdi::ser::to_value(int).ok();
+            }
+            if let Ok(int) = string.parse::<u64>() {
+                return vaerdi::ser::to_value(int).ok();
+            }
+        }
+
+        if allows(schema, InstanceType::Number) {
+            if let Ok(float) = string.parse::<f64>() {
+                return vaerdi::ser::to_value(float).ok();
+            }
+        }
+
+        if allows(schema, InstanceType::Boolean) {
+            match string {
+                "true" => return Some(Value::Bool(true)),
+                "false" => return Some(Value::Bool(false)),
+                _ => {}
+            }
+        }
+    }
+
+    let scalar = !matches!(value, Value::Null | Value::Map(_) | Value::List(_));
+
+    if scalar && allows(schema, InstanceType::Array) {
+        if let (Value::String(string), Some(delimiter)) = (value, delimiter) {
+            return Some(split(string, delimiter));
+        }
+
+        let mut list = List::default();
+        list.push(value.clone());
+        return Some(Value::List(list));
+    }
+
+    None
+}
@@ -0,0 +1,54 @@
+use crate::{provenance::Source, Config, KeyPath};
+use std::path::PathBuf;
+use vaerdi::{Map, Value};
+
+// Sits between discovered files and the environment
+pub(crate) const USER_PRIORITY: i32 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    // Managed by an administrator, never written to
+    System,
+    User,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Scopes {
+    pub system: Vec<PathBuf>,
+    pub user: Option<PathBuf>,
+}
+
+impl Scopes {
+    pub fn of(&self, source: &Source) -> Option<Scope> {
+        let path = source.path()?;
+
+        if self.user.as_deref() == Some(path) {
+            Some(Scope::User)
+        } else if self.system.iter().any(|root| path.starts_with(root)) {
+            Some(Scope::System)
+        } else {
+            None
+        }
+    }
+
+    // Leaves that belong in the user file: those it already provided and those
+    // set at runtime, which have no recorded source
+    pub fn persisted(&self, config: &Config) -> Vec<(KeyPath, Value)> {
+        let mut out = Vec::new();
+        self.collect(config, &KeyPath::root(), &config.inner, &mut out);
+        out
+    }
+
+    fn collect(&self, config: &Config, path: &KeyPath, map: &Map, out: &mut Vec<(KeyPath, Value)>) {
+        for (key, value) in map.iter() {
+            let path = path.key(key);
+            match value {
+                Value::Map(child) if !child.is_empty() => self.collect(config, &path, child, out),
+                value => match config.origins.get(&path) {
+                    Some(source) if self.of(source) != Some(Scope::User) => {}
+                    _ => out.push((path, value.clone())),
+                },
+            }
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use super::builder::ConfigFinder;
+use crate::{locator::MatchStrategy, Config, Error};
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchStep {
+    pub root: PathBuf,
+    pub patterns: Vec<String>,
+    pub strategy: MatchStrategy,
+}
+
+/// Where a finder will look, worked out without touching the file system.
+#[derive(Clone)]
+pub struct SearchPlan {
+    pub(super) finder: ConfigFinder,
+    pub(super) explicit: Option<PathBuf>,
+    pub(super) steps: Vec<SearchStep>,
+}
+
+impl SearchPlan {
+    /// Set when an explicit file replaces discovery.
+    pub fn explicit(&self) -> Option<&PathBuf> {
+        self.explicit.as_ref()
+    }
+
+    pub fn steps(&self) -> &[SearchStep] {
+        &self.steps
+    }
+
+    /// Runs the planned steps, returning the files that would be merged, in merge order.
+    pub fn execute(&self) -> Result<Vec<PathBuf>, Error> {
+        match &self.explicit {
+            Some(path) => self.finder.select(vec![path.clone()]),
+            None => self.finder.run_steps(&self.steps),
+        }
+    }
+
+    /// Merges the files the planned steps find, rather than searching again.
+    pub fn config(&self) -> Result<Config, Error> {
+        self.finder.config_from(self.execute()?)
+    }
+}
+
+impl std::fmt::Debug for SearchPlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SearchPlan")
+            .field("explicit", &self.explicit)
+            .field("steps", &self.steps)
+            .finish()
+    }
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use crate::{ConfigBuilder, DirLocator};
+    use vaerdi::value;
+
+    #[test]
+    fn config_merges_what_the_planned_steps_find() {
+        let dir = std::env::temp_dir().join(format!("johnfig-plan-{}", std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("app.json"), r#"{ "port": 1 }"#).unwrap();
+        std::fs::write(dir.join("local.json"), r#"{ "port": 2 }"#).unwrap();
+
+        let finder = ConfigBuilder::new()
+            .with_name_pattern("app.{ext}")
+            .with_locator(DirLocator(dir))
+            .build()
+            .unwrap();
+
+        let plan = finder.plan();
+        finder.add_name_pattern("local.{ext}").unwrap();
+
+        assert_eq!(plan.config().unwrap().get("port"), Some(&value!(1)));
+        assert_eq!(finder.config().unwrap().get("port"), Some(&value!(2)));
+    }
+}
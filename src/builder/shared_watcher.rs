@@ -0,0 +1,219 @@
+use crate::Error;
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard, OnceLock},
+};
+
+type Handler = Box<dyn Fn(notify::Result<Event>) + Send>;
+
+// One native watcher for the whole process, so configs watching the same roots
+// don't each use up inotify watches. A directory is watched once however many
+// handles want it, and events go to the handles watching a directory above them
+struct Registry {
+    watcher: RecommendedWatcher,
+    // How many handles watch each directory recursively, and not
+    counts: HashMap<PathBuf, (usize, usize)>,
+    sinks: Arc<Mutex<Vec<Sink>>>,
+    next: usize,
+}
+
+struct Sink {
+    id: usize,
+    handler: Handler,
+    dirs: Vec<PathBuf>,
+}
+
+fn registry() -> Result<MutexGuard<'static, Registry>, Error> {
+    static REGISTRY: OnceLock<Mutex<Registry>> = OnceLock::new();
+
+    if REGISTRY.get().is_none() {
+        let sinks = Arc::new(Mutex::new(Vec::<Sink>::new()));
+        let dispatch = sinks.clone();
+
+        let watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            dispatch_event(&dispatch, event)
+        })
+        .map_err(|err| Error::Unknown(Box::new(err)))?;
+
+        // Another thread may have won the race, then this watcher is dropped
+        REGISTRY.get_or_init(|| {
+            Mutex::new(Registry {
+                watcher,
+                counts: HashMap::new(),
+                sinks,
+                next: 0,
+            })
+        });
+    }
+
+    Ok(REGISTRY
+        .get()
+        .expect("registry is initialized")
+        .lock()
+        .expect("watch registry lock poisoned"))
+}
+
+fn dispatch_event(sinks: &Mutex<Vec<Sink>>, event: notify::Result<Event>) {
+    let sinks = sinks.lock().expect("watch sinks lock poisoned");
+    match event {
+        Ok(event) => {
+            for sink in sinks.iter() {
+                let wanted = event
+                    .paths
+                    .iter()
+                    .any(|path| sink.dirs.iter().any(|dir| path.starts_with(dir)));
+                if wanted {
+                    (sink.handler)(Ok(event.clone()));
+                }
+            }
+        }
+        // Errors aren't tied to a directory, and can't be cloned
+        Err(err) => {
+            let message = err.to_string();
+            for sink in sinks.iter() {
+                (sink.handler)(Err(notify::Error::generic(&message)));
+            }
+        }
+    }
+}
+
+impl Registry {
+    fn mode(&self, dir: &Path) -> Option<RecursiveMode> {
+        match self.counts.get(dir) {
+            Some((recursive, _)) if *recursive > 0 => Some(RecursiveMode::Recursive),
+            Some((_, flat)) if *flat > 0 => Some(RecursiveMode::NonRecursive),
+            _ => None,
+        }
+    }
+
+    // Brings the native watch on `dir` in line with its counts after `change`
+    fn update(
+        &mut self,
+        dir: &Path,
+        change: impl FnOnce(&mut (usize, usize)),
+    ) -> Result<(), Error> {
+        let before = self.mode(dir);
+        change(self.counts.entry(dir.to_path_buf()).or_default());
+        let after = self.mode(dir);
+
+        if after.is_none() {
+            self.counts.remove(dir);
+        }
+
+        if before == after {
+            return Ok(());
+        }
+
+        if before.is_some() {
+            self.watcher.unwatch(dir).ok();
+        }
+
+        match after {
+            Some(mode) => self
+                .watcher
+                .watch(dir, mode)
+                .map_err(|err| Error::Unknown(Box::new(err))),
+            None => Ok(()),
+        }
+    }
+
+    fn sink<R>(&self, id: usize, f: impl FnOnce(&mut Vec<PathBuf>) -> R) -> R {
+        let mut sinks = self.sinks.lock().expect("watch sinks lock poisoned");
+        let sink = sinks
+            .iter_mut()
+            .find(|m| m.id == id)
+            .expect("shared watch handle is registered");
+        f(&mut sink.dirs)
+    }
+}
+
+// A handle's share of the process-wide watcher. Dropping it releases its
+// directories and stops its events
+pub(super) struct SharedWatch {
+    id: usize,
+    watched: Vec<(PathBuf, RecursiveMode)>,
+}
+
+impl SharedWatch {
+    pub(super) fn new<F>(handler: F) -> Result<SharedWatch, Error>
+    where
+        F: Fn(notify::Result<Event>) + Send + 'static,
+    {
+        let mut registry = registry()?;
+        let id = registry.next;
+        registry.next += 1;
+
+        registry
+            .sinks
+            .lock()
+            .expect("watch sinks lock poisoned")
+            .push(Sink {
+                id,
+                handler: Box::new(handler),
+                dirs: Vec::new(),
+            });
+
+        Ok(SharedWatch {
+            id,
+            watched: Vec::new(),
+        })
+    }
+
+    pub(super) fn watch(&mut self, dir: &Path, mode: RecursiveMode) -> Result<(), Error> {
+        let mut registry = registry()?;
+        let watched = registry.update(dir, |counts| match mode {
+            RecursiveMode::Recursive => counts.0 += 1,
+            RecursiveMode::NonRecursive => counts.1 += 1,
+        });
+
+        if let Err(err) = watched {
+            registry.update(dir, |counts| release(counts, mode)).ok();
+            return Err(err);
+        }
+
+        registry.sink(self.id, |dirs| dirs.push(dir.to_path_buf()));
+        self.watched.push((dir.to_path_buf(), mode));
+        Ok(())
+    }
+
+    pub(super) fn unwatch(&mut self, dir: &Path, mode: RecursiveMode) -> Result<(), Error> {
+        let Some(idx) = self.watched.iter().position(|m| m.0 == dir && m.1 == mode) else {
+            return Ok(());
+        };
+        self.watched.remove(idx);
+
+        let mut registry = registry()?;
+        registry.sink(self.id, |dirs| {
+            if let Some(idx) = dirs.iter().position(|m| m == dir) {
+                dirs.remove(idx);
+            }
+        });
+
+        registry.update(dir, |counts| release(counts, mode))
+    }
+}
+
+fn release(counts: &mut (usize, usize), mode: RecursiveMode) {
+    match mode {
+        RecursiveMode::Recursive => counts.0 -= 1,
+        RecursiveMode::NonRecursive => counts.1 -= 1,
+    }
+}
+
+impl Drop for SharedWatch {
+    fn drop(&mut self) {
+        while let Some((dir, mode)) = self.watched.last().cloned() {
+            self.unwatch(&dir, mode).ok();
+        }
+
+        if let Ok(registry) = registry() {
+            registry
+                .sinks
+                .lock()
+                .expect("watch sinks lock poisoned")
+                .retain(|m| m.id != self.id);
+        }
+    }
+}
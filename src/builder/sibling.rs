@@ -0,0 +1,72 @@
+use std::{collections::HashMap, path::PathBuf};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SiblingPolicy {
+    MergeAll,
+    PreferOrder(Vec<String>),
+    Error,
+}
+
+impl Default for SiblingPolicy {
+    fn default() -> Self {
+        SiblingPolicy::MergeAll
+    }
+}
+
+impl SiblingPolicy {
+    // Siblings are files in the same directory that only differ by extension,
+    // e.g. `app.yaml` and `app.json`
+    pub(crate) fn apply(&self, files: Vec<PathBuf>) -> Result<Vec<PathBuf>, crate::Error> {
+        if let SiblingPolicy::MergeAll = self {
+            return Ok(files);
+        }
+
+        let mut groups = HashMap::<PathBuf, Vec<usize>>::new();
+        for (idx, path) in files.iter().enumerate() {
+            groups.entry(path.with_extension("")).or_default().push(idx);
+        }
+
+        let mut groups = groups
+            .into_values()
+            .filter(|m| m.len() > 1)
+            .collect::<Vec<_>>();
+        groups.sort();
+
+        let mut keep = vec![true; files.len()];
+
+        for group in groups {
+            match self {
+                SiblingPolicy::MergeAll => {}
+                SiblingPolicy::Error => {
+                    return Err(crate::Error::AmbiguousFormats(
+                        group.iter().map(|&idx| files[idx].clone()).collect(),
+                    ))
+                }
+                SiblingPolicy::PreferOrder(order) => {
+                    let best = group
+                        .iter()
+                        .copied()
+                        .min_by_key(|&idx| (rank(order, &files[idx]), idx))
+                        .expect("group is not empty");
+                    for idx in group {
+                        keep[idx] = idx == best;
+                    }
+                }
+            }
+        }
+
+        Ok(files
+            .into_iter()
+            .zip(keep)
+            .filter_map(|(path, keep)| if keep { Some(path) } else { None })
+            .collect())
+    }
+}
+
+fn rank(order: &[String], path: &PathBuf) -> usize {
+    let ext = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+    order
+        .iter()
+        .position(|m| m.eq_ignore_ascii_case(ext))
+        .unwrap_or(order.len())
+}
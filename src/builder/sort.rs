@@ -0,0 +1,62 @@
+use super::plan::SourceDescriptor;
+use std::{cmp::Ordering, path::PathBuf, time::SystemTime};
+
+/// With a profile set, the policy orders files within each profile rank; a
+/// profile's files always come after the files it specialises.
+pub enum SortPolicy {
+    ByPath,
+    ByModifiedTime,
+    Custom(Box<dyn Fn(&PathBuf, &PathBuf) -> Ordering + Send + Sync>),
+    // Compares the locator root and matched pattern of each file, not just its path
+    BySource(Box<dyn Fn(&SourceDescriptor, &SourceDescriptor) -> Ordering + Send + Sync>),
+}
+
+impl Default for SortPolicy {
+    fn default() -> Self {
+        SortPolicy::ByPath
+    }
+}
+
+impl SortPolicy {
+    // Custom orderings can't be told apart by name
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            SortPolicy::ByPath => "path",
+            SortPolicy::ByModifiedTime => "modified",
+            SortPolicy::Custom(_) => "custom",
+            SortPolicy::BySource(_) => "source",
+        }
+    }
+
+    // Files are always put in path order first, so the result doesn't depend on
+    // the order locators happen to yield them in (read_dir order differs per platform)
+    pub(crate) fn sort<D>(&self, files: &mut Vec<PathBuf>, describe: D)
+    where
+        D: Fn(&PathBuf) -> SourceDescriptor,
+    {
+        files.sort();
+
+        match self {
+            SortPolicy::ByPath => {}
+            SortPolicy::ByModifiedTime => {
+                // Oldest first, so the most recently edited file is merged last and wins
+                files.sort_by_cached_key(|path| modified(path))
+            }
+            SortPolicy::Custom(sort) => files.sort_by(|a, b| sort(a, b)),
+            SortPolicy::BySource(sort) => {
+                let mut described = std::mem::take(files)
+                    .into_iter()
+                    .map(|path| (describe(&path), path))
+                    .collect::<Vec<_>>();
+                described.sort_by(|a, b| sort(&a.0, &b.0));
+                files.extend(described.into_iter().map(|(_, path)| path));
+            }
+        }
+    }
+}
+
+fn modified(path: &PathBuf) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+}
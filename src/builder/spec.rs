@@ -0,0 +1,81 @@
+use super::{builder::ConfigBuilder, layer::Layer};
+use crate::Error;
+
+// Parses a compact discovery description such as
+// `file:./app.json;dir:/etc/myapp;env:MYAPP_;name:app{-profile}.{ext};profile:prod`.
+// A `file:` is merged over the files discovered in the `dir:`s, and under the env
+pub(crate) fn apply(builder: &mut ConfigBuilder, spec: &str) -> Result<(), Error> {
+    for entry in spec.split(';').map(str::trim).filter(|m| !m.is_empty()) {
+        let (kind, value) = match entry.split_once(':') {
+            Some((kind, value)) => (kind.trim(), value.trim()),
+            None => return Err(spec_error(entry, "expected `kind:value`")),
+        };
+
+        if value.is_empty() {
+            return Err(spec_error(entry, "missing value"));
+        }
+
+        match kind {
+            "file" => {
+                builder.add_layer(Layer::File(value.into()));
+            }
+            "dir" => {
+                builder.add_search_path(value)?;
+            }
+            "name" => {
+                builder.add_name_pattern(value);
+            }
+            "profile" => {
+                builder.set_profile(value);
+            }
+            "env" => {
+                builder.add_env_prefix(value);
+            }
+            _ => return Err(spec_error(entry, "unknown source kind")),
+        }
+    }
+
+    Ok(())
+}
+
+fn spec_error(entry: &str, reason: &str) -> Error {
+    Error::InvalidSpec {
+        entry: entry.to_string(),
+        reason: reason.to_string(),
+    }
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use crate::ConfigBuilder;
+    use vaerdi::value;
+
+    #[test]
+    fn files_merge_with_discovered_ones() {
+        let dir = std::env::temp_dir().join(format!("johnfig-spec-{}", std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("app.json"), r#"{ "name": "app", "port": 1 }"#).unwrap();
+        std::fs::write(dir.join("local.json"), r#"{ "port": 2 }"#).unwrap();
+
+        let spec = format!(
+            "file:{};dir:{};name:app.{{ext}}",
+            dir.join("local.json").display(),
+            dir.display()
+        );
+        let config = ConfigBuilder::from_spec(&spec)
+            .unwrap()
+            .build_config()
+            .unwrap();
+
+        assert_eq!(config.get("name"), Some(&value!("app")));
+        assert_eq!(config.get("port"), Some(&value!(2)));
+    }
+
+    #[test]
+    fn entries_need_a_kind_and_a_value() {
+        assert!(ConfigBuilder::from_spec("dir").is_err());
+        assert!(ConfigBuilder::from_spec("dir:").is_err());
+        assert!(ConfigBuilder::from_spec("url:http://example.com").is_err());
+    }
+}
@@ -0,0 +1,183 @@
+use super::{
+    builder::{read_file, save_file},
+    loader::Loader,
+};
+use crate::{
+    values::{insert_at, lookup},
+    Config, Error, KeyPath, Source,
+};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+use vaerdi::{Map, Value};
+
+/// Where [`Config::save_split`] writes, and what it considers changed.
+#[derive(Debug, Clone)]
+pub struct SplitPlan {
+    overrides: PathBuf,
+    baseline: Option<Config>,
+}
+
+impl SplitPlan {
+    /// Keys that no file provided, from defaults, env or other layers, or set
+    /// at runtime, go to `overrides`, which is created when needed.
+    pub fn new(overrides: impl Into<PathBuf>) -> SplitPlan {
+        SplitPlan {
+            overrides: overrides.into(),
+            baseline: None,
+        }
+    }
+
+    /// Only keys that differ from `baseline`, usually the config as it was
+    /// loaded, are written. Without one, every key differing from what its
+    /// file or the defaults hold is, along with all env and layer keys.
+    pub fn with_baseline(mut self, baseline: Config) -> Self {
+        self.baseline = Some(baseline);
+        self
+    }
+
+    pub fn overrides(&self) -> &Path {
+        &self.overrides
+    }
+}
+
+// Groups the changed leaves by the file they go back to, then merges them into
+// what each file holds now. Lists are leaves, like in the origins, and removed
+// keys are left alone
+pub(crate) fn save_split(
+    loader: &Loader,
+    config: &Config,
+    plan: &SplitPlan,
+) -> Result<Vec<PathBuf>, Error> {
+    let mut targets = BTreeMap::<PathBuf, Vec<(KeyPath, &Value)>>::new();
+
+    for (path, value) in leaves(&config.inner) {
+        let source = config.origins.get(&path);
+
+        let unchanged = match (&plan.baseline, source) {
+            (Some(baseline), _) => lookup(&baseline.inner, &path) == Some(value),
+            (None, Some(Source::Defaults)) => lookup(&config.defaults, &path) == Some(value),
+            // Compared against the file below
+            (None, _) => false,
+        };
+        if unchanged {
+            continue;
+        }
+
+        let target = match source {
+            Some(Source::File(file)) => file.clone(),
+            _ => plan.overrides.clone(),
+        };
+        targets.entry(target).or_default().push((path, value));
+    }
+
+    let mut written = Vec::new();
+    for (file, changes) in targets {
+        let mut map = if file.exists() {
+            read_file(loader, &file)?
+        } else {
+            Map::default()
+        };
+
+        let mut changed = false;
+        for (path, value) in changes {
+            if lookup(&map, &path) != Some(value) {
+                insert_at(&mut map, &path, value.clone())?;
+                changed = true;
+            }
+        }
+
+        if changed {
+            save_file(loader, &file, &map, &[])?;
+            written.push(file);
+        }
+    }
+
+    Ok(written)
+}
+
+fn leaves(map: &Map) -> Vec<(KeyPath, &Value)> {
+    let mut out = Vec::new();
+    collect(&KeyPath::root(), map, &mut out);
+    out
+}
+
+fn collect<'a>(path: &KeyPath, map: &'a Map, out: &mut Vec<(KeyPath, &'a Value)>) {
+    for (key, value) in map.iter() {
+        let path = path.key(key);
+        match value {
+            Value::Map(child) => collect(&path, child, out),
+            _ => out.push((path, value)),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use super::SplitPlan;
+    use crate::{ConfigBuilder, DirLocator, Layer};
+    use std::path::PathBuf;
+    use vaerdi::value;
+
+    fn fixture(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("johnfig-split-{name}-{}", std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn changes_go_back_to_their_files() {
+        let dir = fixture("files");
+        std::fs::write(dir.join("app.json"), r#"{ "port": 1, "name": "app" }"#).unwrap();
+
+        let config = ConfigBuilder::new()
+            .with_name_pattern("app.{ext}")
+            .with_locator(DirLocator(dir.clone()))
+            .with_layer(Layer::Defaults(value!({ "workers": 2 })))
+            .with_layer(Layer::Discovered)
+            .build_config()
+            .unwrap();
+
+        let mut changed = config.clone();
+        changed.set_path("name", "renamed").unwrap();
+        changed.set_path("workers", value!(4)).unwrap();
+
+        let overrides = dir.join("overrides.json");
+        let written = changed
+            .save_split(&SplitPlan::new(overrides.clone()).with_baseline(config))
+            .unwrap();
+        assert_eq!(written.len(), 1);
+
+        // `name` was set at runtime, so it has no file left to go back to
+        let overrides = std::fs::read_to_string(overrides).unwrap();
+        assert!(overrides.contains("renamed"));
+        assert!(overrides.contains("workers"));
+        assert!(!overrides.contains("port"));
+    }
+
+    #[test]
+    fn file_keys_are_written_in_place() {
+        let dir = fixture("in-place");
+        std::fs::write(dir.join("app.json"), r#"{ "port": 1 }"#).unwrap();
+
+        let mut config = ConfigBuilder::new()
+            .with_name_pattern("app.{ext}")
+            .with_locator(DirLocator(dir.clone()))
+            .build_config()
+            .unwrap();
+
+        config["port"] = value!(2);
+
+        let overrides = dir.join("overrides.json");
+        let written = config
+            .save_split(&SplitPlan::new(overrides.clone()))
+            .unwrap();
+        assert_eq!(written, vec![dir.join("app.json")]);
+        assert!(!overrides.exists());
+
+        let saved = std::fs::read_to_string(dir.join("app.json")).unwrap();
+        assert!(saved.contains('2'));
+    }
+}
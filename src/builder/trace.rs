@@ -0,0 +1,47 @@
+use crate::Error;
+use std::cell::RefCell;
+
+pub(crate) const DEBUG_ENV: &str = "JOHNFIG_DEBUG";
+
+pub(crate) fn debug_from_env() -> bool {
+    match std::env::var(DEBUG_ENV) {
+        Ok(value) => !value.is_empty() && value != "0",
+        Err(_) => false,
+    }
+}
+
+pub(crate) struct Trace {
+    lines: Option<RefCell<Vec<String>>>,
+}
+
+impl Trace {
+    pub fn new(enabled: bool) -> Trace {
+        Trace {
+            lines: if enabled {
+                Some(RefCell::default())
+            } else {
+                None
+            },
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.lines.is_some()
+    }
+
+    pub fn record(&self, line: impl FnOnce() -> String) {
+        if let Some(lines) = &self.lines {
+            lines.borrow_mut().push(line());
+        }
+    }
+
+    pub fn attach(&self, error: Error) -> Error {
+        match &self.lines {
+            Some(lines) => Error::Traced {
+                error: Box::new(error),
+                trace: lines.take(),
+            },
+            None => error,
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use super::command::DIRECTIVE;
+use crate::{Error, KeyPath, Violation};
+use std::path::{Path, PathBuf};
+use vaerdi::{Map, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trust {
+    Trusted,
+    // May not set values that run commands
+    Untrusted,
+}
+
+impl Default for Trust {
+    fn default() -> Self {
+        Trust::Trusted
+    }
+}
+
+pub(crate) struct SourceRule {
+    pub root: PathBuf,
+    pub trust: Trust,
+    pub allowed: Option<Vec<glob::Pattern>>,
+}
+
+impl SourceRule {
+    pub fn new(root: PathBuf) -> SourceRule {
+        SourceRule {
+            root,
+            trust: Trust::default(),
+            allowed: None,
+        }
+    }
+
+    pub fn restrict_to(&mut self, prefixes: &[&str]) -> Result<(), Error> {
+        let patterns = prefixes
+            .iter()
+            .map(|prefix| glob::Pattern::new(prefix).map_err(|err| Error::Unknown(Box::new(err))))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.allowed
+            .get_or_insert_with(Vec::default)
+            .extend(patterns);
+
+        Ok(())
+    }
+
+    pub fn applies_to(&self, path: &Path) -> bool {
+        path.starts_with(&self.root)
+    }
+
+    // Drops every value this source isn't allowed to set, reporting each one
+    pub fn apply(&self, map: &mut Map, origin: &str, out: &mut Vec<Violation>) {
+        self.apply_map(map, &KeyPath::root(), origin, out)
+    }
+
+    fn apply_map(&self, map: &mut Map, path: &KeyPath, origin: &str, out: &mut Vec<Violation>) {
+        for (key, mut value) in std::mem::take(map).into_iter() {
+            let path = path.key(&key);
+
+            if let Value::Map(child) = &mut value {
+                self.apply_map(child, &path, origin, out);
+                if !child.is_empty() {
+                    map.insert(key, value);
+                }
+                continue;
+            }
+
+            match self.check(&path, &value) {
+                Some(reason) => {
+                    tracing::warn!("{}: ignoring {}: {}", origin, path, reason);
+                    out.push(Violation {
+                        origin: origin.to_string(),
+                        path,
+                        reason,
+                    });
+                }
+                None => {
+                    map.insert(key, value);
+                }
+            }
+        }
+    }
+
+    fn check(&self, path: &KeyPath, value: &Value) -> Option<&'static str> {
+        if let Some(allowed) = &self.allowed {
+            let key = path.to_string();
+            if !allowed.iter().any(|pattern| pattern.matches(&key)) {
+                return Some("key is outside the prefixes this source may set");
+            }
+        }
+
+        if self.trust == Trust::Untrusted && runs_command(value) {
+            return Some("untrusted sources may not run commands");
+        }
+
+        None
+    }
+}
+
+fn runs_command(value: &Value) -> bool {
+    match value {
+        Value::String(string) => string.starts_with(DIRECTIVE),
+        Value::List(list) => list.iter().any(runs_command),
+        Value::Map(map) => map.iter().any(|(_, value)| runs_command(value)),
+        _ => false,
+    }
+}
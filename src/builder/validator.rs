@@ -0,0 +1,54 @@
+use crate::{Config, KeyPath};
+use std::{fmt, path::PathBuf};
+
+pub(crate) type Validator = Box<dyn Fn(&Config) -> Result<(), ValidationError> + Send + Sync>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    pub path: KeyPath,
+    pub message: String,
+    /// The file that set the offending key, filled in by the finder.
+    pub file: Option<PathBuf>,
+}
+
+impl ValidationError {
+    /// `path` is a key path such as `servers[0].port`; anything unparsable is
+    /// taken as a single top level key.
+    pub fn new(path: &str, message: impl ToString) -> ValidationError {
+        ValidationError {
+            path: path.parse().unwrap_or_else(|_| KeyPath::root().key(path)),
+            message: message.to_string(),
+            file: None,
+        }
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)?;
+        if let Some(file) = &self.file {
+            write!(f, " (from {:?})", file)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+// Runs every validator, so all problems are reported at once
+pub(crate) fn validate(validators: &[Validator], config: &Config) -> Vec<ValidationError> {
+    validators
+        .iter()
+        .filter_map(|validator| validator(config).err())
+        .map(|mut error| {
+            if error.file.is_none() {
+                error.file = config
+                    .origins
+                    .get(&error.path)
+                    .and_then(|source| source.path())
+                    .map(|path| path.to_path_buf());
+            }
+            error
+        })
+        .collect()
+}
@@ -0,0 +1,1405 @@
+use super::{builder::ConfigFinder, shared_watcher::SharedWatch};
+use crate::{
+    config::deserialize_field, locator::Locator, values::lookup, Change, Config, ConfigDiff, Error,
+    KeyPath, KeyPattern,
+};
+use arc_swap::ArcSwap;
+#[cfg(feature = "async")]
+use blocking::Unblock;
+#[cfg(feature = "async")]
+use futures_core::Stream;
+use notify::{Event, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::de::DeserializeOwned;
+use std::{
+    collections::VecDeque,
+    fmt,
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{
+            channel, sync_channel, Receiver, RecvError, RecvTimeoutError, Sender, SyncSender,
+            TryRecvError,
+        },
+        Arc, Condvar, Mutex, RwLock,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+use vaerdi::Value;
+
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+// Reload results kept for a receiver that falls behind, beyond which the
+// oldest are dropped
+const BACKLOG: usize = 16;
+
+type Subscribers = Arc<Mutex<Keys>>;
+
+type Hook = dyn Fn(&Config, &ConfigDiff) -> Result<(), VetoError> + Send + Sync;
+
+type Hooks = Arc<RwLock<Vec<Arc<Hook>>>>;
+
+/// The current config of a [`WatchableConfig`], from
+/// [`WatchableConfig::cell`]. Once the `WatchableConfig` is dropped it keeps the
+/// last config.
+#[derive(Clone)]
+pub struct ConfigCell(Arc<ArcSwap<Config>>);
+
+impl ConfigCell {
+    /// The current config, without locking. A reload swaps in a new config and
+    /// leaves the one returned here untouched.
+    pub fn load(&self) -> Arc<Config> {
+        self.0.load_full()
+    }
+}
+
+/// What [`WatchableConfig::recv`] receives, besides reload errors.
+#[derive(Debug)]
+pub enum WatchEvent {
+    /// The config was reloaded and is now the current one.
+    Reloaded(Config),
+    /// A file registered with [`WatchableConfig::watch_also`] changed, was
+    /// created or was removed.
+    DependencyChanged(PathBuf),
+}
+
+/// Returned by an [`on_reload`](WatchableConfig::on_reload) hook to keep the
+/// current config instead of the reloaded one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VetoError {
+    pub reason: String,
+}
+
+impl VetoError {
+    pub fn new(reason: impl Into<String>) -> VetoError {
+        VetoError {
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for VetoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "reload vetoed: {}", self.reason)
+    }
+}
+
+impl std::error::Error for VetoError {}
+
+/// How a [`WatchableConfig`] finds out about changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchMode {
+    /// The platform's file events: inotify, FSEvents or ReadDirectoryChangesW.
+    Native,
+    /// Scans the watched directories every `interval`, for filesystems whose
+    /// events never arrive, such as network shares, FUSE mounts and WSL1. Files
+    /// are hashed as well as stat'ed, since mtimes there can be coarse or stale.
+    Poll { interval: Duration },
+    /// Native events through one watcher shared by every `WatchableConfig` in
+    /// the process that uses this mode. A directory several of them watch is
+    /// watched once, which keeps many configs over the same roots under the
+    /// inotify watch limit.
+    Shared,
+}
+
+/// How a [`WatchableConfig`] watches and reloads.
+#[derive(Debug, Clone)]
+pub struct WatchOptions {
+    debounce: Duration,
+    key_debounce: Duration,
+    max_backoff: Duration,
+    failure_threshold: usize,
+    mode: WatchMode,
+    notify: notify::Config,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        WatchOptions {
+            debounce: DEBOUNCE,
+            key_debounce: Duration::ZERO,
+            max_backoff: Duration::from_secs(30),
+            failure_threshold: 5,
+            mode: WatchMode::Native,
+            notify: notify::Config::default(),
+        }
+    }
+}
+
+impl WatchOptions {
+    pub fn new() -> WatchOptions {
+        WatchOptions::default()
+    }
+
+    /// File events are coalesced until none has arrived for `debounce`, so an
+    /// editor's write, rename and chmod on save trigger a single reload.
+    /// Defaults to 500ms.
+    pub fn with_debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = debounce;
+        self
+    }
+
+    /// Changes to a key subscribed with [`WatchableConfig::watch_key`] are held
+    /// back for `window` after the first one, then delivered once with the final
+    /// value, and not at all when it ended up where it started. Off by default.
+    pub fn with_key_debounce(mut self, window: Duration) -> Self {
+        self.key_debounce = window;
+        self
+    }
+
+    /// After a reload fails, the debounce doubles with each failure in a row, up
+    /// to `max`, so a file that is being edited live isn't parsed on every save.
+    /// Defaults to 30s.
+    pub fn with_max_backoff(mut self, max: Duration) -> Self {
+        self.max_backoff = max;
+        self
+    }
+
+    /// Only the first of a run of failed reloads is received as an error, and
+    /// then [`Error::PersistentReload`] once `failures` in a row have failed.
+    /// The rest are left out until a reload succeeds. Defaults to 5.
+    pub fn with_failure_threshold(mut self, failures: usize) -> Self {
+        self.failure_threshold = failures.max(1);
+        self
+    }
+
+    /// Defaults to [`WatchMode::Native`].
+    pub fn with_mode(mut self, mode: WatchMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Tuning passed on to the notify watcher, such as the poll interval of the
+    /// fallback watcher used where there are no native events.
+    /// [`WatchMode::Poll`] sets its own interval on top, and
+    /// [`WatchMode::Shared`] ignores it, as the shared watcher has one config.
+    pub fn with_notify_config(mut self, config: notify::Config) -> Self {
+        self.notify = config;
+        self
+    }
+}
+
+/// A config that reloads itself when the files it was loaded from change.
+///
+/// The watching happens on a background thread, which owns the notify watcher
+/// and does every reload, including those asked for by the methods below.
+/// Dropping the `WatchableConfig` stops the thread and the watcher with it.
+pub struct WatchableConfig {
+    finder: ConfigFinder,
+    config: Arc<ArcSwap<Config>>,
+    events: Arc<Events>,
+    subscribers: Subscribers,
+    hooks: Hooks,
+    control: Sender<Message>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl WatchableConfig {
+    pub(super) fn new(
+        finder: ConfigFinder,
+        options: WatchOptions,
+    ) -> Result<WatchableConfig, Error> {
+        let config = Arc::new(ArcSwap::from_pointee(finder.config()?));
+
+        let (control, messages) = channel();
+        let notify_tx = control.clone();
+
+        let handler = move |event| {
+            notify_tx.send(Message::Notify(event)).ok();
+        };
+
+        let watcher = match options.mode {
+            WatchMode::Native => Backend::Own(Box::new(
+                RecommendedWatcher::new(handler, options.notify).map_err(notify_error)?,
+            )),
+            WatchMode::Poll { interval } => {
+                let config = options
+                    .notify
+                    .with_poll_interval(interval)
+                    .with_compare_contents(true);
+                Backend::Own(Box::new(
+                    PollWatcher::new(handler, config).map_err(notify_error)?,
+                ))
+            }
+            WatchMode::Shared => Backend::Shared(SharedWatch::new(handler)?),
+        };
+
+        let mut watches = Watches {
+            watcher,
+            watched: Vec::new(),
+            missing: Vec::new(),
+        };
+
+        let targets = finder.watch_targets();
+        watches.register(&targets.0)?;
+
+        let events = Arc::new(Events::default());
+        let subscribers = Subscribers::new(Mutex::new(Keys::new(options.key_debounce)));
+        let hooks = Hooks::default();
+
+        let worker = Worker {
+            finder: finder.clone(),
+            config: config.clone(),
+            events: events.clone(),
+            subscribers: subscribers.clone(),
+            hooks: hooks.clone(),
+            watches,
+            targets,
+            dependencies: Vec::new(),
+            frozen: false,
+            missed: false,
+            failures: Failures::new(options.failure_threshold, options.max_backoff),
+        };
+
+        let thread = std::thread::Builder::new()
+            .name("johnfig-watch".to_string())
+            .spawn(move || worker.run(messages, options.debounce))?;
+
+        Ok(WatchableConfig {
+            finder,
+            config,
+            events,
+            subscribers,
+            hooks,
+            control,
+            thread: Some(thread),
+        })
+    }
+
+    /// Adds a locator to the finder, starts watching its root and reloads.
+    pub fn add_locator<L: Locator + 'static>(&self, locator: L) -> Result<(), Error>
+    where
+        L::Error: std::error::Error + 'static,
+        L: Send + Sync,
+    {
+        self.finder.add_locator(locator);
+        self.refresh()
+    }
+
+    /// Adds a search path to the finder, starts watching it and reloads.
+    pub fn add_search_path(&self, path: impl Into<PathBuf>) -> Result<(), Error> {
+        self.finder.add_search_path(path)?;
+        self.refresh()
+    }
+
+    /// Removes the locator at `root`, stops watching it and reloads. False when
+    /// no locator has that root.
+    pub fn remove_locator(&self, root: &Path) -> Result<bool, Error> {
+        let removed = self.finder.remove_locator(root);
+        if removed {
+            self.refresh()?;
+        }
+        Ok(removed)
+    }
+
+    /// Adds a name pattern to the finder and reloads.
+    pub fn add_name_pattern(&self, pattern: &str) -> Result<(), Error> {
+        self.finder.add_name_pattern(pattern)?;
+        self.refresh()
+    }
+
+    // The watches are brought in line with the finder and the reload done on the
+    // worker thread, so it can't race one triggered by a file change. Fails when
+    // a new root can't be watched; the reload itself reports on the events
+    fn refresh(&self) -> Result<(), Error> {
+        let (tx, rx) = channel();
+        self.control
+            .send(Message::Refresh(tx))
+            .map_err(|_| stopped())?;
+        rx.recv().map_err(|_| stopped())?
+    }
+
+    pub fn finder(&self) -> &ConfigFinder {
+        &self.finder
+    }
+
+    /// A handle on the current config for hot paths. Its reads never lock or
+    /// wait for a reload.
+    pub fn cell(&self) -> ConfigCell {
+        ConfigCell(self.config.clone())
+    }
+
+    /// The current config, without copying it.
+    pub fn snapshot(&self) -> Arc<Config> {
+        self.config.load_full()
+    }
+
+    /// Makes `config` the current one as if it had been reloaded, telling key
+    /// subscribers and [`recv`](Self::recv) about it, but without running the
+    /// [`on_reload`](Self::on_reload) hooks. Meant for tests that inject config
+    /// states without touching the filesystem; a later reload replaces it.
+    pub fn replace(&self, config: Config) {
+        let (tx, rx) = channel();
+        if self.control.send(Message::Replace(config, tx)).is_ok() {
+            rx.recv().ok();
+        }
+    }
+
+    /// Stops reloading until [`unfreeze`](Self::unfreeze), which reloads if
+    /// anything changed in the meantime. [`replace`](Self::replace) still works
+    /// while frozen.
+    pub fn freeze(&self) {
+        self.set_frozen(true)
+    }
+
+    pub fn unfreeze(&self) {
+        self.set_frozen(false)
+    }
+
+    // Returns once the worker has taken it in, so no reload starts afterwards
+    fn set_frozen(&self, frozen: bool) {
+        let (tx, rx) = channel();
+        if self.control.send(Message::Freeze(frozen, tx)).is_ok() {
+            rx.recv().ok();
+        }
+    }
+
+    /// The last config that loaded without errors.
+    pub fn config(&self) -> Config {
+        Config::clone(&self.config.load())
+    }
+
+    /// Blocks until the next reload or dependency change.
+    ///
+    /// Up to 16 events are kept for a receiver that falls behind; past that the
+    /// oldest are dropped.
+    pub fn recv(&self) -> Result<Result<WatchEvent, Error>, RecvError> {
+        self.events.recv()
+    }
+
+    /// Like [`recv`](Self::recv), giving up after `timeout`.
+    pub fn recv_timeout(
+        &self,
+        timeout: Duration,
+    ) -> Result<Result<WatchEvent, Error>, RecvTimeoutError> {
+        self.events.recv_timeout(timeout)
+    }
+
+    /// The oldest event not received yet, without blocking.
+    pub fn try_recv(&self) -> Result<Result<WatchEvent, Error>, TryRecvError> {
+        self.events.try_recv()
+    }
+
+    /// Also watches `path`, a file the config refers to such as a certificate,
+    /// without loading it. Changes to it are received as
+    /// [`WatchEvent::DependencyChanged`], debounced like reloads.
+    pub fn watch_also(&self, path: impl Into<PathBuf>) -> Result<(), Error> {
+        let mut path = path.into();
+        if path.is_relative() {
+            path = std::env::current_dir()?.join(path);
+        }
+
+        let (tx, rx) = channel();
+        self.control
+            .send(Message::WatchAlso(path, tx))
+            .map_err(|_| stopped())?;
+        rx.recv().map_err(|_| stopped())?
+    }
+
+    /// Runs `hook` on every reload that changes something, before the new config
+    /// replaces the current one. When any hook returns an error the current
+    /// config stays, and the error is received as [`Error::Vetoed`] in place of
+    /// the reload, e.g. to refuse changes to keys that can't change at runtime.
+    pub fn on_reload<F>(&self, hook: F)
+    where
+        F: Fn(&Config, &ConfigDiff) -> Result<(), VetoError> + Send + Sync + 'static,
+    {
+        self.hooks
+            .write()
+            .expect("hooks lock poisoned")
+            .push(Arc::new(hook));
+    }
+
+    /// Receives the new value of `key`, a key path such as `server.port`, each
+    /// time a reload changes it. None when the key was removed.
+    pub fn watch_key(&self, key: &str) -> Result<Receiver<Option<Value>>, Error> {
+        let path = key.parse::<KeyPath>()?;
+        let (tx, rx) = channel();
+
+        // Held while loading the config, so a reload swapping it in the meantime
+        // still compares against the value read here
+        let mut keys = self.subscribers.lock().expect("subscribers lock poisoned");
+        let sent = lookup(&self.config.load().inner, &path).cloned();
+        keys.subscribe(path, tx, sent);
+
+        Ok(rx)
+    }
+
+    /// Receives the changes a reload makes at, above or below the paths that
+    /// match `pattern`, such as `services.*.endpoint` or `logging.**` (see
+    /// [`KeyPattern`]). Reloads that change none of them send nothing. Unlike key
+    /// subscriptions these aren't debounced, each reload sends its own changes.
+    pub fn watch_pattern(&self, pattern: &str) -> Result<Receiver<Vec<Change>>, Error> {
+        let pattern = pattern.parse::<KeyPattern>()?;
+        let (tx, rx) = channel();
+
+        self.subscribers
+            .lock()
+            .expect("subscribers lock poisoned")
+            .patterns
+            .push((pattern, tx));
+
+        Ok(rx)
+    }
+
+    /// Like [`watch_key`](Self::watch_key), but deserializes each new value the
+    /// way [`Config::try_get`] does. Values that fail to deserialize are skipped
+    /// and reported on [`TypedWatch::errors`].
+    pub fn watch_typed<T: DeserializeOwned>(&self, key: &str) -> Result<TypedWatch<T>, Error> {
+        let values = self.watch_key(key)?;
+        let (errors, failed) = sync_channel(BACKLOG);
+
+        Ok(TypedWatch {
+            decoder: Decoder {
+                name: key.to_string(),
+                path: key.parse()?,
+                values,
+                errors,
+                _type: PhantomData,
+            },
+            errors: failed,
+        })
+    }
+
+    /// [`watch_key`](Self::watch_key) as a stream, received on the blocking
+    /// thread pool. Ends once the `WatchableConfig` is dropped.
+    #[cfg(feature = "async")]
+    pub fn watch_key_stream(
+        &self,
+        key: &str,
+    ) -> Result<impl Stream<Item = Option<Value>> + Send + Unpin + 'static, Error> {
+        Ok(Unblock::new(self.watch_key(key)?.into_iter()))
+    }
+}
+
+impl Drop for WatchableConfig {
+    fn drop(&mut self) {
+        self.control.send(Message::Shutdown).ok();
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
+
+/// Deserialized values of a key, from [`WatchableConfig::watch_typed`]. Ends
+/// once the `WatchableConfig` is dropped.
+pub struct TypedWatch<T> {
+    decoder: Decoder<T>,
+    errors: Receiver<Error>,
+}
+
+impl<T: DeserializeOwned> TypedWatch<T> {
+    /// Blocks until a reload changes the key to a value that deserializes.
+    pub fn recv(&self) -> Result<T, RecvError> {
+        self.decoder.recv()
+    }
+
+    /// Why skipped values failed to deserialize. Holds up to 16 errors that
+    /// haven't been received; later ones are dropped until there is room.
+    pub fn errors(&self) -> &Receiver<Error> {
+        &self.errors
+    }
+
+    /// The values as a stream, received on the blocking thread pool, along with
+    /// the errors.
+    #[cfg(feature = "async")]
+    pub fn into_stream(
+        self,
+    ) -> (
+        impl Stream<Item = T> + Send + Unpin + 'static,
+        Receiver<Error>,
+    )
+    where
+        T: Send + 'static,
+    {
+        (Unblock::new(self.decoder), self.errors)
+    }
+}
+
+impl<T: DeserializeOwned> Iterator for TypedWatch<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.decoder.next()
+    }
+}
+
+struct Decoder<T> {
+    name: String,
+    path: KeyPath,
+    values: Receiver<Option<Value>>,
+    errors: SyncSender<Error>,
+    _type: PhantomData<fn() -> T>,
+}
+
+impl<T: DeserializeOwned> Decoder<T> {
+    fn recv(&self) -> Result<T, RecvError> {
+        loop {
+            let value = self.values.recv()?;
+            match deserialize_field(&self.name, value) {
+                Ok(value) => return Ok(value),
+                Err(error) => {
+                    let error = Error::Typed {
+                        path: self.path.clone(),
+                        file: None,
+                        error,
+                    };
+                    self.errors.try_send(error).ok();
+                }
+            }
+        }
+    }
+}
+
+impl<T: DeserializeOwned> Iterator for Decoder<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.recv().ok()
+    }
+}
+
+fn stopped() -> Error {
+    Error::Unknown("the watcher thread has stopped".into())
+}
+
+// What the worker thread acts on
+enum Message {
+    Notify(notify::Result<Event>),
+    // Re-registers the watches and reloads, answering once both are done
+    Refresh(Sender<Result<(), Error>>),
+    WatchAlso(PathBuf, Sender<Result<(), Error>>),
+    Replace(Config, Sender<()>),
+    Freeze(bool, Sender<()>),
+    Shutdown,
+}
+
+// The events for `recv`. Bounded, since nothing guarantees anyone receives them
+#[derive(Default)]
+struct Events {
+    queue: Mutex<(VecDeque<Result<WatchEvent, Error>>, bool)>,
+    ready: Condvar,
+}
+
+impl Events {
+    fn push(&self, event: Result<WatchEvent, Error>) {
+        let mut queue = self.queue.lock().expect("events lock poisoned");
+        if queue.0.len() == BACKLOG {
+            queue.0.pop_front();
+        }
+        queue.0.push_back(event);
+        self.ready.notify_all();
+    }
+
+    // Once closed, receiving fails when the queue is empty
+    fn close(&self) {
+        self.queue.lock().expect("events lock poisoned").1 = true;
+        self.ready.notify_all();
+    }
+
+    fn recv(&self) -> Result<Result<WatchEvent, Error>, RecvError> {
+        let mut queue = self.queue.lock().expect("events lock poisoned");
+        loop {
+            if let Some(event) = queue.0.pop_front() {
+                return Ok(event);
+            }
+            if queue.1 {
+                return Err(RecvError);
+            }
+            queue = self.ready.wait(queue).expect("events lock poisoned");
+        }
+    }
+
+    fn recv_timeout(
+        &self,
+        timeout: Duration,
+    ) -> Result<Result<WatchEvent, Error>, RecvTimeoutError> {
+        let deadline = Instant::now() + timeout;
+        let mut queue = self.queue.lock().expect("events lock poisoned");
+        loop {
+            if let Some(event) = queue.0.pop_front() {
+                return Ok(event);
+            }
+            if queue.1 {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(RecvTimeoutError::Timeout);
+            }
+            queue = self
+                .ready
+                .wait_timeout(queue, deadline - now)
+                .expect("events lock poisoned")
+                .0;
+        }
+    }
+
+    fn try_recv(&self) -> Result<Result<WatchEvent, Error>, TryRecvError> {
+        let mut queue = self.queue.lock().expect("events lock poisoned");
+        match queue.0.pop_front() {
+            Some(event) => Ok(event),
+            None if queue.1 => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+}
+
+// Everything the worker thread owns. The watcher lives here, so it goes away
+// with the thread
+struct Worker {
+    finder: ConfigFinder,
+    config: Arc<ArcSwap<Config>>,
+    events: Arc<Events>,
+    subscribers: Subscribers,
+    hooks: Hooks,
+    watches: Watches,
+    // Watched directories with their search depth, and files outside discovery
+    targets: (Vec<(PathBuf, usize)>, Vec<PathBuf>),
+    // Files from watch_also
+    dependencies: Vec<PathBuf>,
+    // While frozen, reloads are skipped and remembered in `missed`
+    frozen: bool,
+    missed: bool,
+    failures: Failures,
+}
+
+impl Worker {
+    fn run(mut self, messages: Receiver<Message>, debounce: Duration) {
+        // When the pending reload is due. Set by a relevant event and pushed back
+        // by every event after it
+        let mut due: Option<Instant> = None;
+        // Dependencies that changed, reported once `changed_due` has passed
+        let mut changed = Vec::new();
+        let mut changed_due: Option<Instant> = None;
+
+        loop {
+            let wake = [due, changed_due, self.keys().next_due()]
+                .into_iter()
+                .flatten()
+                .min();
+
+            let message = match wake {
+                Some(at) => {
+                    match messages.recv_timeout(at.saturating_duration_since(Instant::now())) {
+                        Ok(message) => Some(message),
+                        Err(RecvTimeoutError::Timeout) => None,
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+                }
+                None => match messages.recv() {
+                    Ok(message) => Some(message),
+                    Err(_) => break,
+                },
+            };
+
+            match message {
+                Some(Message::Notify(Ok(event))) => {
+                    if due.is_some() || self.relevant(&event) {
+                        due = Some(Instant::now() + self.failures.backoff(debounce));
+                    }
+
+                    for path in &event.paths {
+                        if self.dependencies.contains(path) {
+                            if !changed.contains(path) {
+                                changed.push(path.clone());
+                            }
+                            changed_due = Some(Instant::now() + debounce);
+                        }
+                    }
+                }
+                Some(Message::Notify(Err(err))) => self.events.push(Err(notify_error(err))),
+                Some(Message::Refresh(reply)) => {
+                    // The reload below covers any pending one
+                    due = None;
+                    self.targets = self.finder.watch_targets();
+                    let registered = self.register();
+                    if registered.is_ok() {
+                        self.reload();
+                    }
+                    reply.send(registered).ok();
+                }
+                Some(Message::WatchAlso(path, reply)) => {
+                    if !self.dependencies.contains(&path) {
+                        self.dependencies.push(path);
+                    }
+                    reply.send(self.register()).ok();
+                }
+                Some(Message::Replace(config, reply)) => {
+                    self.apply(config.clone(), &self.config.load().diff(&config));
+                    self.events.push(Ok(WatchEvent::Reloaded(config)));
+                    reply.send(()).ok();
+                }
+                Some(Message::Freeze(frozen, reply)) => {
+                    self.frozen = frozen;
+                    if !frozen && std::mem::take(&mut self.missed) {
+                        due = None;
+                        self.reload();
+                    }
+                    reply.send(()).ok();
+                }
+                Some(Message::Shutdown) => break,
+                None => {}
+            }
+
+            let now = Instant::now();
+            if due.map_or(false, |at| at <= now) {
+                due = None;
+                self.reload();
+            }
+
+            if changed_due.map_or(false, |at| at <= now) {
+                changed_due = None;
+                for path in changed.drain(..) {
+                    self.events.push(Ok(WatchEvent::DependencyChanged(path)));
+                }
+            }
+
+            // Keys before config, the order watch_key locks them in
+            let mut keys = self.keys();
+            keys.flush(&self.config.load(), now);
+        }
+
+        self.events.close();
+    }
+
+    fn keys(&self) -> std::sync::MutexGuard<'_, Keys> {
+        self.subscribers.lock().expect("subscribers lock poisoned")
+    }
+
+    fn relevant(&self, event: &Event) -> bool {
+        let (dirs, files) = &self.targets;
+        event.paths.iter().any(|path| {
+            files.contains(path)
+                || (self.finder.matches(path) && within(dirs, path))
+                || self.watches.may_create(path)
+        })
+    }
+
+    fn reload(&mut self) {
+        if self.frozen {
+            self.missed = true;
+            return;
+        }
+
+        match self.load() {
+            Ok(config) => {
+                self.failures.count = 0;
+                self.events.push(Ok(WatchEvent::Reloaded(config)));
+            }
+            // A veto is the config working as intended, not a failure
+            Err(err @ Error::Vetoed(_)) => self.events.push(Err(err)),
+            Err(err) => {
+                if let Some(err) = self.failures.failed(err) {
+                    self.events.push(Err(err));
+                }
+            }
+        }
+    }
+
+    // Dependencies are watched through the directory they are in, which also sees
+    // them replaced by a rename as editors do on save
+    fn register(&mut self) -> Result<(), Error> {
+        let mut dirs = self.targets.0.clone();
+        for dependency in &self.dependencies {
+            if let Some(parent) = dependency.parent() {
+                dirs.push((parent.to_path_buf(), 1));
+            }
+        }
+        self.watches.register(&dirs)
+    }
+
+    // Reloads the config, keeping the last good one and telling subscribers what
+    // changed, unless a hook vetoes it. Roots that were missing may exist now, and
+    // watched ones may be gone
+    fn load(&mut self) -> Result<Config, Error> {
+        self.register()?;
+
+        let config = self.finder.config()?;
+        let diff = self.config.load().diff(&config);
+
+        if !diff.is_empty() {
+            // Cloned out, so a hook may register another
+            let hooks = self.hooks.read().expect("hooks lock poisoned").clone();
+            for hook in hooks {
+                hook(&config, &diff)?;
+            }
+        }
+
+        self.apply(config.clone(), &diff);
+        Ok(config)
+    }
+
+    fn apply(&mut self, config: Config, diff: &ConfigDiff) {
+        let config = Arc::new(config);
+        self.config.store(config.clone());
+        self.keys().changed(&config, diff, Instant::now());
+    }
+}
+
+// Consecutive failed reloads, which back off and are reported sparingly
+struct Failures {
+    count: usize,
+    threshold: usize,
+    max_backoff: Duration,
+}
+
+impl Failures {
+    fn new(threshold: usize, max_backoff: Duration) -> Failures {
+        Failures {
+            count: 0,
+            threshold,
+            max_backoff,
+        }
+    }
+
+    // The debounce doubled for each failure in a row
+    fn backoff(&self, debounce: Duration) -> Duration {
+        let factor = 1u32 << self.count.min(16);
+        debounce
+            .saturating_mul(factor)
+            .min(self.max_backoff.max(debounce))
+    }
+
+    // The error to report for another failure, if any: the first of a run, and
+    // once more when the run reaches the threshold
+    fn failed(&mut self, error: Error) -> Option<Error> {
+        self.count += 1;
+
+        if self.count == self.threshold && self.count > 1 {
+            return Some(Error::PersistentReload {
+                failures: self.count,
+                error: Box::new(error),
+            });
+        }
+
+        (self.count == 1).then_some(error)
+    }
+}
+
+// The watcher along with the directories it currently watches
+struct Watches {
+    watcher: Backend,
+    watched: Vec<(PathBuf, RecursiveMode)>,
+    // Roots that don't exist yet
+    missing: Vec<PathBuf>,
+}
+
+impl Watches {
+    // Brings the watches in line with `dirs`, leaving unchanged ones alone. A
+    // missing root is covered by watching the closest directory above it that
+    // exists, until it is created
+    fn register(&mut self, dirs: &[(PathBuf, usize)]) -> Result<(), Error> {
+        let mut wanted = Vec::new();
+        let mut missing = Vec::new();
+
+        for (dir, depth) in dirs {
+            if dir.is_dir() {
+                // Recursive watches also cover subdirectories created later on
+                let mode = if *depth > 1 {
+                    RecursiveMode::Recursive
+                } else {
+                    RecursiveMode::NonRecursive
+                };
+                want(&mut wanted, dir, mode);
+                continue;
+            }
+
+            if let Some(ancestor) = dir.ancestors().skip(1).find(|m| m.is_dir()) {
+                want(&mut wanted, ancestor, RecursiveMode::NonRecursive);
+            }
+            missing.push(dir.clone());
+        }
+
+        for (dir, mode) in &self.watched {
+            if !wanted.contains(&(dir.clone(), *mode)) {
+                // Fails when the directory is already gone, which ends the watch anyway
+                self.watcher.unwatch(dir, *mode).ok();
+            }
+        }
+
+        for (dir, mode) in &wanted {
+            if !self.watched.contains(&(dir.clone(), *mode)) {
+                self.watcher.watch(dir, *mode)?;
+            }
+        }
+
+        self.watched = wanted;
+        self.missing = missing;
+        Ok(())
+    }
+
+    // Whether a change at `path` may have created a missing root or a directory above it
+    fn may_create(&self, path: &Path) -> bool {
+        self.missing.iter().any(|root| root.starts_with(path))
+    }
+}
+
+// A watcher of our own, or a share of the process-wide one
+enum Backend {
+    Own(Box<dyn Watcher + Send>),
+    Shared(SharedWatch),
+}
+
+impl Backend {
+    fn watch(&mut self, dir: &Path, mode: RecursiveMode) -> Result<(), Error> {
+        match self {
+            Backend::Own(watcher) => watcher.watch(dir, mode).map_err(notify_error),
+            Backend::Shared(shared) => shared.watch(dir, mode),
+        }
+    }
+
+    fn unwatch(&mut self, dir: &Path, mode: RecursiveMode) -> Result<(), Error> {
+        match self {
+            Backend::Own(watcher) => watcher.unwatch(dir).map_err(notify_error),
+            Backend::Shared(shared) => shared.unwatch(dir, mode),
+        }
+    }
+}
+
+fn notify_error(err: notify::Error) -> Error {
+    Error::Unknown(Box::new(err))
+}
+
+// A directory is watched once, recursively if any entry needs it
+fn want(wanted: &mut Vec<(PathBuf, RecursiveMode)>, dir: &Path, mode: RecursiveMode) {
+    match wanted.iter_mut().find(|(m, _)| m == dir) {
+        Some((_, existing)) => {
+            if mode == RecursiveMode::Recursive {
+                *existing = mode;
+            }
+        }
+        None => wanted.push((dir.to_path_buf(), mode)),
+    }
+}
+
+// A key subscription, with what it was last sent
+struct Subscriber {
+    path: KeyPath,
+    tx: Sender<Option<Value>>,
+    sent: Option<Value>,
+    // When a held back change is to be delivered
+    due: Option<Instant>,
+}
+
+// The key subscriptions, delivering changes right away or once per window
+struct Keys {
+    window: Duration,
+    subscribers: Vec<Subscriber>,
+    patterns: Vec<(KeyPattern, Sender<Vec<Change>>)>,
+}
+
+impl Keys {
+    fn new(window: Duration) -> Keys {
+        Keys {
+            window,
+            subscribers: Vec::new(),
+            patterns: Vec::new(),
+        }
+    }
+
+    fn subscribe(&mut self, path: KeyPath, tx: Sender<Option<Value>>, sent: Option<Value>) {
+        self.subscribers.push(Subscriber {
+            path,
+            tx,
+            sent,
+            due: None,
+        });
+    }
+
+    fn changed(&mut self, new: &Config, diff: &ConfigDiff, now: Instant) {
+        if diff.is_empty() {
+            return;
+        }
+
+        self.patterns.retain(|(pattern, tx)| {
+            let changes = diff
+                .iter()
+                .filter(|change| pattern.touches(&change.path))
+                .cloned()
+                .collect::<Vec<_>>();
+
+            changes.is_empty() || tx.send(changes).is_ok()
+        });
+
+        let window = self.window;
+
+        // Subscribers whose receiver is gone are dropped
+        self.subscribers.retain_mut(|subscriber| {
+            if !diff.touches(&subscriber.path) {
+                return true;
+            }
+
+            if !window.is_zero() {
+                subscriber.due.get_or_insert(now + window);
+                return true;
+            }
+
+            subscriber.deliver(lookup(&new.inner, &subscriber.path))
+        });
+    }
+
+    // Delivers the held back changes whose window is over, with the value they
+    // have in `config` by then
+    fn flush(&mut self, config: &Config, now: Instant) {
+        self.subscribers
+            .retain_mut(|subscriber| match subscriber.due {
+                Some(due) if due <= now => {
+                    subscriber.due = None;
+                    subscriber.deliver(lookup(&config.inner, &subscriber.path))
+                }
+                _ => true,
+            });
+    }
+
+    fn next_due(&self) -> Option<Instant> {
+        self.subscribers.iter().filter_map(|m| m.due).min()
+    }
+}
+
+impl Subscriber {
+    // Sends `value` unless the subscriber already has it. False once the
+    // receiver is gone
+    fn deliver(&mut self, value: Option<&Value>) -> bool {
+        if self.sent.as_ref() == value {
+            return true;
+        }
+
+        self.sent = value.cloned();
+        self.tx.send(self.sent.clone()).is_ok()
+    }
+}
+
+// Recursive watches report changes at any depth; only those the locators would
+// find count
+fn within(dirs: &[(PathBuf, usize)], path: &Path) -> bool {
+    dirs.iter()
+        .any(|(dir, depth)| match path.strip_prefix(dir) {
+            Ok(rest) => rest.components().count() <= *depth,
+            Err(_) => false,
+        })
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use super::{Decoder, Failures, Keys};
+    use crate::{
+        Config, ConfigBuilder, ConfigFinder, DirLocator, Error, VetoError, WatchEvent, WatchMode,
+        WatchOptions,
+    };
+    use std::{
+        marker::PhantomData,
+        path::PathBuf,
+        sync::mpsc::{channel, sync_channel, RecvTimeoutError},
+        time::{Duration, Instant},
+    };
+    use vaerdi::{value, Value};
+
+    fn fixture(name: &str) -> (PathBuf, ConfigFinder) {
+        let dir = std::env::temp_dir().join(format!("johnfig-watch-{name}-{}", std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let finder = ConfigBuilder::new()
+            .with_name_pattern("*.{ext}")
+            .with_locator(DirLocator(dir.clone()))
+            .build()
+            .unwrap();
+
+        (dir, finder)
+    }
+
+    #[test]
+    fn key_receivers_end_when_dropped() {
+        let (_, finder) = fixture("drop");
+        let watchable = finder.watch_debounced(Duration::from_millis(10)).unwrap();
+        let key = watchable.watch_key("server.port").unwrap();
+
+        drop(watchable);
+
+        // Disconnected rather than timed out: the worker and its senders are gone
+        assert!(matches!(
+            key.recv_timeout(Duration::from_secs(5)),
+            Err(RecvTimeoutError::Disconnected)
+        ));
+    }
+
+    fn config(value: Value) -> Config {
+        match value {
+            Value::Map(inner) => Config {
+                inner,
+                ..Default::default()
+            },
+            _ => panic!("not a map"),
+        }
+    }
+
+    #[test]
+    fn key_changes_are_coalesced_per_window() {
+        let window = Duration::from_millis(100);
+        let mut keys = Keys::new(window);
+        let (tx, rx) = channel();
+        keys.subscribe("pool.size".parse().unwrap(), tx, Some(value!(1)));
+
+        let start = Instant::now();
+        let states = [
+            config(value!({ "pool": { "size": 1 } })),
+            config(value!({ "pool": { "size": 2 } })),
+            config(value!({ "pool": { "size": 3 } })),
+            config(value!({ "pool": { "size": 4 } })),
+        ];
+        for (idx, pair) in states.windows(2).enumerate() {
+            keys.changed(
+                &pair[0],
+                &pair[1],
+                start + Duration::from_millis(idx as u64 * 10),
+            );
+        }
+
+        // Still inside the window started by the first change
+        keys.flush(&states[3], start + Duration::from_millis(50));
+        assert!(rx.try_recv().is_err());
+
+        keys.flush(&states[3], start + window);
+        assert_eq!(rx.try_recv().unwrap(), Some(value!(4)));
+        assert!(rx.try_recv().is_err());
+        assert_eq!(keys.next_due(), None);
+    }
+
+    #[test]
+    fn key_changes_that_revert_within_the_window_are_not_delivered() {
+        let mut keys = Keys::new(Duration::from_millis(100));
+        let (tx, rx) = channel();
+        keys.subscribe("pool.size".parse().unwrap(), tx, Some(value!(1)));
+
+        let one = config(value!({ "pool": { "size": 1 } }));
+        let two = config(value!({ "pool": { "size": 2 } }));
+        let start = Instant::now();
+        keys.changed(&two, &one.diff(&two), start);
+        keys.changed(&one, &two.diff(&one), start);
+
+        keys.flush(&one, start + Duration::from_millis(100));
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn key_changes_without_a_window_are_sent_right_away() {
+        let mut keys = Keys::new(Duration::ZERO);
+        let (tx, rx) = channel();
+        keys.subscribe("pool.size".parse().unwrap(), tx, Some(value!(1)));
+
+        let one = config(value!({ "pool": { "size": 1 }, "other": 1 }));
+        let two = config(value!({ "pool": { "size": 2 }, "other": 1 }));
+        let three = config(value!({ "pool": { "size": 2 }, "other": 2 }));
+        keys.changed(&two, &one.diff(&two), Instant::now());
+        keys.changed(&three, &two.diff(&three), Instant::now());
+
+        assert_eq!(rx.try_recv().unwrap(), Some(value!(2)));
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn typed_values_that_fail_to_deserialize_are_skipped() {
+        let (tx, values) = channel();
+        let (errors, failed) = sync_channel(16);
+        let decoder = Decoder::<u16> {
+            name: "port".to_string(),
+            path: "port".parse().unwrap(),
+            values,
+            errors,
+            _type: PhantomData,
+        };
+
+        tx.send(Some(value!("not a port"))).unwrap();
+        tx.send(None).unwrap();
+        tx.send(Some(value!(8080))).unwrap();
+
+        assert_eq!(decoder.recv().unwrap(), 8080);
+        assert_eq!(failed.try_iter().count(), 2);
+
+        drop(tx);
+        assert!(decoder.recv().is_err());
+    }
+
+    #[test]
+    fn vetoed_reloads_keep_the_current_config() {
+        let (dir, finder) = fixture("veto");
+        std::fs::write(dir.join("app.json"), r#"{ "port": 1, "name": "a" }"#).unwrap();
+
+        let watchable = finder.watch_debounced(Duration::from_millis(50)).unwrap();
+        watchable.on_reload(|_, diff| {
+            if diff.touches(&"port".parse().unwrap()) {
+                return Err(VetoError::new("port can't change at runtime"));
+            }
+            Ok(())
+        });
+
+        std::fs::write(dir.join("app.json"), r#"{ "port": 2, "name": "a" }"#).unwrap();
+        let event = watchable.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert!(matches!(event, Err(Error::Vetoed(_))));
+        assert_eq!(watchable.config().get("port"), Some(&value!(1)));
+
+        std::fs::write(dir.join("app.json"), r#"{ "port": 1, "name": "b" }"#).unwrap();
+        let event = watchable.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert!(matches!(event, Ok(WatchEvent::Reloaded(_))));
+        assert_eq!(watchable.config().get("name"), Some(&value!("b")));
+    }
+
+    #[test]
+    fn dependencies_are_reported_without_reloading() {
+        let (dir, finder) = fixture("dependency");
+        let certs = dir.join("certs");
+        std::fs::create_dir_all(&certs).unwrap();
+        std::fs::write(certs.join("server.pem"), "one").unwrap();
+
+        let watchable = finder.watch_debounced(Duration::from_millis(50)).unwrap();
+        watchable.watch_also(certs.join("server.pem")).unwrap();
+
+        std::fs::write(certs.join("server.pem"), "two").unwrap();
+        match watchable.recv_timeout(Duration::from_secs(10)).unwrap() {
+            Ok(WatchEvent::DependencyChanged(path)) => assert!(path.ends_with("server.pem")),
+            other => panic!("expected a dependency change, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn replaced_configs_are_kept_until_the_next_reload() {
+        let (dir, finder) = fixture("replace");
+        std::fs::write(dir.join("app.json"), r#"{ "port": 1 }"#).unwrap();
+
+        let watchable = finder.watch_debounced(Duration::from_millis(50)).unwrap();
+        let port = watchable.watch_key("port").unwrap();
+
+        watchable.replace(config(value!({ "port": 9 })));
+        assert_eq!(port.try_recv().unwrap(), Some(value!(9)));
+        assert_eq!(watchable.snapshot().get("port"), Some(&value!(9)));
+        assert!(matches!(
+            watchable.try_recv(),
+            Ok(Ok(WatchEvent::Reloaded(_)))
+        ));
+
+        // Nothing reloads while frozen, and unfreezing catches up
+        watchable.freeze();
+        std::fs::write(dir.join("app.json"), r#"{ "port": 2 }"#).unwrap();
+        assert!(matches!(
+            watchable.recv_timeout(Duration::from_millis(500)),
+            Err(RecvTimeoutError::Timeout)
+        ));
+        assert_eq!(watchable.cell().load().get("port"), Some(&value!(9)));
+
+        watchable.unfreeze();
+        assert!(matches!(
+            watchable.try_recv(),
+            Ok(Ok(WatchEvent::Reloaded(_)))
+        ));
+        assert_eq!(watchable.cell().load().get("port"), Some(&value!(2)));
+    }
+
+    #[test]
+    fn failed_reloads_back_off_and_are_reported_sparingly() {
+        let debounce = Duration::from_millis(100);
+        let mut failures = Failures::new(3, Duration::from_secs(1));
+        let failure = || Error::NoUserConfig;
+
+        assert_eq!(failures.backoff(debounce), debounce);
+
+        assert!(matches!(
+            failures.failed(failure()),
+            Some(Error::NoUserConfig)
+        ));
+        assert_eq!(failures.backoff(debounce), Duration::from_millis(200));
+        assert!(failures.failed(failure()).is_none());
+        assert_eq!(failures.backoff(debounce), Duration::from_millis(400));
+        assert!(matches!(
+            failures.failed(failure()),
+            Some(Error::PersistentReload { failures: 3, .. })
+        ));
+        assert!(failures.failed(failure()).is_none());
+        assert!(failures.failed(failure()).is_none());
+        assert_eq!(failures.backoff(debounce), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn poll_mode_reloads_without_native_events() {
+        let (dir, finder) = fixture("poll");
+        std::fs::write(dir.join("app.json"), r#"{ "port": 1 }"#).unwrap();
+
+        let options = WatchOptions::new()
+            .with_debounce(Duration::from_millis(10))
+            .with_mode(WatchMode::Poll {
+                interval: Duration::from_millis(50),
+            });
+        let watchable = finder.watch_with(options).unwrap();
+
+        std::fs::write(dir.join("app.json"), r#"{ "port": 2 }"#).unwrap();
+        let event = watchable.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert!(matches!(event, Ok(WatchEvent::Reloaded(_))));
+        assert_eq!(watchable.config().get("port"), Some(&value!(2)));
+    }
+
+    #[test]
+    fn shared_watchers_outlive_each_other() {
+        let (dir, finder) = fixture("shared");
+        std::fs::write(dir.join("app.json"), r#"{ "port": 1 }"#).unwrap();
+
+        let options = WatchOptions::new()
+            .with_debounce(Duration::from_millis(50))
+            .with_mode(WatchMode::Shared);
+        let first = finder.watch_with(options.clone()).unwrap();
+        let second = finder.watch_with(options).unwrap();
+
+        std::fs::write(dir.join("app.json"), r#"{ "port": 2 }"#).unwrap();
+        for watchable in [&first, &second] {
+            let event = watchable.recv_timeout(Duration::from_secs(10)).unwrap();
+            assert!(matches!(event, Ok(WatchEvent::Reloaded(_))));
+        }
+
+        // The directory stays watched for the one that is left
+        drop(first);
+        std::fs::write(dir.join("app.json"), r#"{ "port": 3 }"#).unwrap();
+        let event = second.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert!(matches!(event, Ok(WatchEvent::Reloaded(_))));
+        assert_eq!(second.config().get("port"), Some(&value!(3)));
+    }
+
+    #[test]
+    fn pattern_subscribers_receive_matching_changes() {
+        let mut keys = Keys::new(Duration::ZERO);
+        let (tx, rx) = channel();
+        keys.patterns
+            .push(("services.*.endpoint".parse().unwrap(), tx));
+
+        let one = config(value!({
+            "services": { "api": { "endpoint": "a", "timeout": 1 } },
+            "logging": { "level": "info" }
+        }));
+        let two = config(value!({
+            "services": {
+                "api": { "endpoint": "b", "timeout": 2 },
+                "auth": { "endpoint": "c" }
+            },
+            "logging": { "level": "debug" }
+        }));
+
+        keys.changed(&two, &one.diff(&two), Instant::now());
+        let mut paths = rx
+            .try_recv()
+            .unwrap()
+            .into_iter()
+            .map(|change| change.path.to_string())
+            .collect::<Vec<_>>();
+        paths.sort();
+        assert_eq!(paths, ["services.api.endpoint", "services.auth"]);
+
+        // Nothing matching changed
+        let three = config(value!({
+            "services": {
+                "api": { "endpoint": "b", "timeout": 3 },
+                "auth": { "endpoint": "c" }
+            },
+            "logging": { "level": "debug" }
+        }));
+        keys.changed(&three, &two.diff(&three), Instant::now());
+        assert!(rx.try_recv().is_err());
+    }
+}
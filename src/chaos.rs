@@ -0,0 +1,72 @@
+use crate::Error;
+use std::{io, path::Path, time::Duration};
+
+#[derive(Debug, Clone)]
+pub enum Fault {
+    Io(io::ErrorKind),
+    Delay(Duration),
+    Malformed(Vec<u8>),
+}
+
+/// Injects faults into reads of matching files, to exercise degraded config handling.
+#[derive(Debug, Clone, Default)]
+pub struct Chaos {
+    rules: Vec<(glob::Pattern, Fault)>,
+}
+
+impl Chaos {
+    pub fn new() -> Chaos {
+        Chaos::default()
+    }
+
+    pub fn with_fault(mut self, pattern: &str, fault: Fault) -> Result<Self, Error> {
+        self.add_fault(pattern, fault)?;
+        Ok(self)
+    }
+
+    pub fn add_fault(&mut self, pattern: &str, fault: Fault) -> Result<&mut Self, Error> {
+        let pattern = glob::Pattern::new(pattern).map_err(|err| Error::Unknown(Box::new(err)))?;
+        self.rules.push((pattern, fault));
+        Ok(self)
+    }
+
+    pub fn fail_io(self, pattern: &str, kind: io::ErrorKind) -> Result<Self, Error> {
+        self.with_fault(pattern, Fault::Io(kind))
+    }
+
+    pub fn delay(self, pattern: &str, delay: Duration) -> Result<Self, Error> {
+        self.with_fault(pattern, Fault::Delay(delay))
+    }
+
+    pub fn malform(self, pattern: &str, bytes: impl Into<Vec<u8>>) -> Result<Self, Error> {
+        self.with_fault(pattern, Fault::Malformed(bytes.into()))
+    }
+
+    // Faults are applied in the order they were added; a failure stops the read
+    pub(crate) fn intercept(
+        &self,
+        path: &Path,
+        read: impl FnOnce() -> io::Result<Vec<u8>>,
+    ) -> io::Result<Vec<u8>> {
+        let mut replaced = None;
+
+        for (pattern, fault) in &self.rules {
+            if !pattern.matches_path(path) {
+                continue;
+            }
+
+            match fault {
+                Fault::Io(kind) => {
+                    return Err(io::Error::new(*kind, format!("injected fault: {:?}", path)))
+                }
+                Fault::Delay(delay) => std::thread::sleep(*delay),
+                Fault::Malformed(bytes) => replaced = Some(bytes.clone()),
+            }
+        }
+
+        match replaced {
+            Some(bytes) => Ok(bytes),
+            None => read(),
+        }
+    }
+}
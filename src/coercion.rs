@@ -0,0 +1,9 @@
+use crate::key_path::KeyPath;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Coercion {
+    pub origin: String,
+    pub path: KeyPath,
+    pub from: &'static str,
+    pub to: &'static str,
+}
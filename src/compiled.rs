@@ -0,0 +1,218 @@
+use crate::key_path::{KeyPath, Segment};
+use std::{collections::HashMap, sync::Arc};
+use vaerdi::{List, Map, Value};
+
+enum Slot {
+    Leaf(Value),
+    // Children sorted by key, so lookups are a binary search over interned keys
+    Map(Vec<(u32, u32)>),
+    List(Vec<u32>),
+}
+
+struct Arena {
+    keys: Vec<Box<str>>,
+    slots: Vec<Slot>,
+}
+
+/// An immutable snapshot of a config. All nodes live in one arena and keys are
+/// interned, so clones only bump a reference count.
+#[derive(Clone)]
+pub struct CompiledConfig(Arc<Arena>);
+
+impl std::fmt::Debug for CompiledConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CompiledConfig")
+            .field("keys", &self.0.keys.len())
+            .field("nodes", &self.0.slots.len())
+            .finish()
+    }
+}
+
+impl CompiledConfig {
+    pub(crate) fn new(map: &Map) -> CompiledConfig {
+        let mut builder = ArenaBuilder {
+            interned: HashMap::default(),
+            arena: Arena {
+                keys: Vec::default(),
+                slots: Vec::default(),
+            },
+        };
+
+        builder.add_map(map);
+
+        CompiledConfig(Arc::new(builder.arena))
+    }
+
+    pub fn root(&self) -> CompiledNode<'_> {
+        CompiledNode {
+            arena: &self.0,
+            idx: 0,
+        }
+    }
+
+    pub fn get(&self, path: &KeyPath) -> Option<CompiledNode<'_>> {
+        path.segments()
+            .iter()
+            .try_fold(self.root(), |node, segment| match segment {
+                Segment::Key(key) => node.get(key),
+                Segment::Index(idx) => node.index(*idx),
+            })
+    }
+
+    pub fn get_path(&self, path: &str) -> Option<CompiledNode<'_>> {
+        self.get(&path.parse().ok()?)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct CompiledNode<'a> {
+    arena: &'a Arena,
+    idx: u32,
+}
+
+impl<'a> CompiledNode<'a> {
+    fn slot(&self) -> &'a Slot {
+        &self.arena.slots[self.idx as usize]
+    }
+
+    fn node(&self, idx: u32) -> CompiledNode<'a> {
+        CompiledNode {
+            arena: self.arena,
+            idx,
+        }
+    }
+
+    pub fn value(&self) -> Option<&'a Value> {
+        match self.slot() {
+            Slot::Leaf(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn is_map(&self) -> bool {
+        matches!(self.slot(), Slot::Map(_))
+    }
+
+    pub fn is_list(&self) -> bool {
+        matches!(self.slot(), Slot::List(_))
+    }
+
+    pub fn len(&self) -> usize {
+        match self.slot() {
+            Slot::Leaf(_) => 0,
+            Slot::Map(entries) => entries.len(),
+            Slot::List(items) => items.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, key: &str) -> Option<CompiledNode<'a>> {
+        let entries = match self.slot() {
+            Slot::Map(entries) => entries,
+            _ => return None,
+        };
+
+        let keys = &self.arena.keys;
+        entries
+            .binary_search_by(|(k, _)| (*keys[*k as usize]).cmp(key))
+            .ok()
+            .map(|pos| self.node(entries[pos].1))
+    }
+
+    pub fn index(&self, idx: usize) -> Option<CompiledNode<'a>> {
+        match self.slot() {
+            Slot::List(items) => items.get(idx).map(|m| self.node(*m)),
+            _ => None,
+        }
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &'a str> + 'a {
+        let keys = &self.arena.keys;
+        let entries: &'a [(u32, u32)] = match self.slot() {
+            Slot::Map(entries) => entries,
+            _ => &[],
+        };
+        entries.iter().map(move |(k, _)| &*keys[*k as usize])
+    }
+
+    pub fn to_value(&self) -> Value {
+        match self.slot() {
+            Slot::Leaf(value) => value.clone(),
+            Slot::Map(entries) => {
+                let mut map = Map::default();
+                for (key, idx) in entries {
+                    map.insert(
+                        self.arena.keys[*key as usize].to_string(),
+                        self.node(*idx).to_value(),
+                    );
+                }
+                Value::Map(map)
+            }
+            Slot::List(items) => {
+                let mut list = List::default();
+                for idx in items {
+                    list.push(self.node(*idx).to_value());
+                }
+                Value::List(list)
+            }
+        }
+    }
+}
+
+struct ArenaBuilder {
+    interned: HashMap<String, u32>,
+    arena: Arena,
+}
+
+impl ArenaBuilder {
+    fn reserve(&mut self) -> u32 {
+        self.arena.slots.push(Slot::List(Vec::new()));
+        (self.arena.slots.len() - 1) as u32
+    }
+
+    fn intern(&mut self, key: &str) -> u32 {
+        if let Some(idx) = self.interned.get(key) {
+            return *idx;
+        }
+        let idx = self.arena.keys.len() as u32;
+        self.arena.keys.push(key.into());
+        self.interned.insert(key.to_string(), idx);
+        idx
+    }
+
+    fn add_map(&mut self, map: &Map) -> u32 {
+        let slot = self.reserve();
+
+        let mut entries = Vec::new();
+        for (key, value) in map.iter() {
+            let key = self.intern(key);
+            let child = self.add(value);
+            entries.push((key, child));
+        }
+
+        let keys = &self.arena.keys;
+        entries.sort_by(|a, b| keys[a.0 as usize].cmp(&keys[b.0 as usize]));
+
+        self.arena.slots[slot as usize] = Slot::Map(entries);
+        slot
+    }
+
+    fn add(&mut self, value: &Value) -> u32 {
+        match value {
+            Value::Map(map) => self.add_map(map),
+            Value::List(list) => {
+                let slot = self.reserve();
+                let items = list.iter().map(|item| self.add(item)).collect();
+                self.arena.slots[slot as usize] = Slot::List(items);
+                slot
+            }
+            value => {
+                self.arena.slots.push(Slot::Leaf(value.clone()));
+                (self.arena.slots.len() - 1) as u32
+            }
+        }
+    }
+}
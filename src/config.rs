@@ -1,10 +1,35 @@
-use std::path::PathBuf;
+use crate::access::AccessReport;
+#[cfg(feature = "serde")]
+use crate::borrowed::ValueRef;
+use crate::coercion::Coercion;
+use crate::compiled::CompiledConfig;
+use crate::diff::{diff, ConfigDiff};
+use crate::drift::{drift, Drift};
+use crate::key_path::{KeyPath, KeyPathError};
+use crate::provenance::{Origins, Source};
+use crate::values::{insert_at, lookup, lookup_mut, sort_keys};
+use crate::violation::Violation;
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
 use vaerdi::{merge, Map, Value};
 
 #[derive(Debug, Default, Clone)]
 pub struct Config {
     pub(crate) inner: Map,
     pub(crate) files: Vec<PathBuf>,
+    pub(crate) defaults: Map,
+    pub(crate) modified: HashMap<PathBuf, SystemTime>,
+    pub(crate) coercions: Vec<Coercion>,
+    pub(crate) violations: Vec<Violation>,
+    pub(crate) origins: Origins,
+    // Selected files the file predicate left out
+    pub(crate) vetoed: Vec<PathBuf>,
+    // Shared between clones, so reads through any copy end up in the same report
+    pub(crate) access: Option<Arc<Mutex<BTreeSet<KeyPath>>>>,
 }
 
 impl Config {
@@ -12,26 +37,157 @@ impl Config {
         &self.files
     }
 
+    pub fn coercions(&self) -> &[Coercion] {
+        &self.coercions
+    }
+
+    pub fn violations(&self) -> &[Violation] {
+        &self.violations
+    }
+
+    /// The file that set `key`, a dotted key path.
+    pub fn origin(&self, key: impl AsRef<str>) -> Option<&Path> {
+        self.source(key)?.path()
+    }
+
+    pub fn source(&self, key: impl AsRef<str>) -> Option<&Source> {
+        let path = key.as_ref().parse::<KeyPath>().ok()?;
+        self.origins.get(&path)
+    }
+
+    pub fn origins(&self) -> impl Iterator<Item = (KeyPath, Source)> + '_ {
+        self.origins
+            .iter()
+            .map(|(path, source)| (path.clone(), source.clone()))
+    }
+
     pub fn get(&self, name: impl AsRef<str>) -> Option<&Value> {
+        self.record_access(name.as_ref());
         self.inner.get(name.as_ref())
     }
 
+    /// Nested lookup by key path, e.g. `servers[0].port`.
+    pub fn get_path(&self, path: &str) -> Option<&Value> {
+        let path = path.parse::<KeyPath>().ok()?;
+        self.record_path(&path);
+        lookup(&self.inner, &path)
+    }
+
+    pub fn set_path(
+        &mut self,
+        path: &str,
+        value: impl Into<Value>,
+    ) -> Result<Option<Value>, KeyPathError> {
+        let path = path.parse::<KeyPath>()?;
+        let prev = insert_at(&mut self.inner, &path, value.into())?;
+        self.origins.clear(&path);
+        Ok(prev)
+    }
+
+    pub fn track_access(&mut self) {
+        if self.access.is_none() {
+            self.access = Some(Arc::default());
+        }
+    }
+
+    /// Reading a key reads everything below it, and reading into a leaf (a list
+    /// element, say) reads the leaf. Leaves come from the origins, so unread keys
+    /// are reported as deep as they were set, along with files nothing was read from.
+    pub fn access_report(&self) -> Option<AccessReport> {
+        let access = self.access.as_ref()?.lock().expect("access lock poisoned");
+
+        let is_read = |path: &KeyPath| {
+            access
+                .iter()
+                .any(|read| path.starts_with(read) || read.starts_with(path))
+        };
+
+        let mut unread = self
+            .origins
+            .iter()
+            .map(|(path, _)| path)
+            .filter(|path| !is_read(path))
+            .map(|path| path.to_string())
+            .collect::<Vec<_>>();
+
+        // Keys set after loading have no origin
+        unread.extend(
+            self.inner
+                .iter()
+                .map(|(key, _)| KeyPath::root().key(key))
+                .filter(|path| !is_read(path))
+                .filter(|path| !self.origins.iter().any(|(m, _)| m.starts_with(path)))
+                .map(|path| path.to_string()),
+        );
+        unread.sort();
+
+        let unread_files =
+            self.files
+                .iter()
+                .filter(|file| {
+                    !self.origins.iter().any(|(path, source)| {
+                        source.path() == Some(file.as_path()) && is_read(path)
+                    })
+                })
+                .cloned()
+                .collect();
+
+        Some(AccessReport {
+            read: access.iter().map(|path| path.to_string()).collect(),
+            unread,
+            unread_files,
+        })
+    }
+
+    pub(crate) fn record_access(&self, name: &str) {
+        self.record_path(&KeyPath::root().key(name))
+    }
+
+    fn record_path(&self, path: &KeyPath) {
+        if let Some(access) = &self.access {
+            let mut access = access.lock().expect("access lock poisoned");
+            if !access.contains(path) {
+                access.insert(path.clone());
+            }
+        }
+    }
+
     pub fn get_mut<K>(&mut self, name: impl AsRef<str>) -> Option<&mut Value> {
         self.inner.get_mut(name.as_ref())
     }
 
+    /// Like get_path, for changing the value in place. None when any part of the
+    /// path is missing or has the wrong type.
+    pub fn get_path_mut(&mut self, path: &str) -> Option<&mut Value> {
+        let path = path.parse::<KeyPath>().ok()?;
+        let value = lookup_mut(&mut self.inner, &path)?;
+        self.origins.clear(&path);
+        Some(value)
+    }
+
     #[cfg(feature = "serde")]
     pub fn try_get<'a, S: serde::Deserialize<'a>>(
         &self,
         name: &str,
     ) -> Result<S, vaerdi::de::DeserializerError> {
-        if let Some(v) = self.inner.get(name).cloned() {
-            S::deserialize(v)
-        } else {
-            Err(vaerdi::de::DeserializerError::Custom(format!(
+        self.record_access(name);
+        deserialize_field(name, self.inner.get(name).cloned())
+    }
+
+    /// Like try_get, but borrows strings from the config instead of cloning the
+    /// value, e.g. for `&str` fields.
+    #[cfg(feature = "serde")]
+    pub fn try_get_ref<'de, S: serde::Deserialize<'de>>(
+        &'de self,
+        name: &str,
+    ) -> Result<S, vaerdi::de::DeserializerError> {
+        self.record_access(name);
+        match self.inner.get(name) {
+            Some(value) => S::deserialize(ValueRef(value)),
+            None => Err(vaerdi::de::DeserializerError::Custom(format!(
                 "field not found: {}",
                 name
-            )))
+            ))),
         }
     }
 
@@ -41,11 +197,15 @@ impl Config {
         name: &str,
         value: S,
     ) -> Result<Option<Value>, vaerdi::ser::SerializerError> {
-        Ok(self.inner.insert(name, vaerdi::ser::to_value(value)?))
+        let value = vaerdi::ser::to_value(value)?;
+        self.origins.remove(&KeyPath::root().key(name));
+        Ok(self.inner.insert(name, value))
     }
 
     pub fn set(&mut self, name: impl ToString, value: impl Into<Value>) -> Option<Value> {
-        self.inner.insert(name.to_string(), value.into())
+        let name = name.to_string();
+        self.origins.remove(&KeyPath::root().key(&name));
+        self.inner.insert(name, value.into())
     }
 
     pub fn contains(&self, name: impl AsRef<str>) -> bool {
@@ -53,14 +213,127 @@ impl Config {
     }
 
     pub fn extend(&mut self, config: Config) {
+        self.origins.extend(&KeyPath::root(), config.origins);
+
         for (key, value) in config.inner.into_iter() {
-            if !self.inner.contains(&key) {
-                self.inner.insert(key, value);
-            } else {
-                let prev = self.inner.get_mut(&key).unwrap();
-                merge(prev, value);
+            match self.inner.get_mut(&key) {
+                Some(prev) => merge(prev, value),
+                None => {
+                    self.inner.insert(key, value);
+                }
+            }
+        }
+    }
+
+    pub fn mount(&mut self, prefix: &str, config: Config) {
+        let Config {
+            inner,
+            files,
+            modified,
+            origins,
+            vetoed,
+            ..
+        } = config;
+
+        let mut path = KeyPath::root();
+        for segment in prefix.split('.').filter(|m| !m.is_empty()) {
+            path.push_key(segment);
+        }
+        self.origins.extend(&path, origins);
+
+        let mut value = Value::Map(inner);
+        for segment in prefix.rsplit('.').filter(|m| !m.is_empty()) {
+            let mut map = Map::default();
+            map.insert(segment, value);
+            value = Value::Map(map);
+        }
+
+        if let Value::Map(inner) = value {
+            self.extend(Config {
+                inner,
+                ..Default::default()
+            });
+        }
+
+        for file in files {
+            if !self.files.contains(&file) {
+                self.files.push(file);
+            }
+        }
+
+        for path in vetoed {
+            if !self.vetoed.contains(&path) {
+                self.vetoed.push(path);
             }
         }
+
+        self.modified.extend(modified);
+    }
+
+    pub fn is_stale(&self) -> bool {
+        self.modified.iter().any(|(path, modified)| {
+            match std::fs::metadata(path).and_then(|meta| meta.modified()) {
+                Ok(current) => current != *modified,
+                Err(_) => true,
+            }
+        })
+    }
+
+    /// Top level entries in key order, whatever order the sources merged them in.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Value)> {
+        let mut entries = self
+            .inner
+            .iter()
+            .map(|(key, value)| -> (&str, &Value) { (key, value) })
+            .collect::<Vec<_>>();
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+        entries.into_iter()
+    }
+
+    /// A copy with keys sorted at every level, so serialized output is reproducible.
+    pub fn sorted(&self) -> Config {
+        let mut config = self.clone();
+        sort_keys(&mut config.inner);
+        config
+    }
+
+    pub fn sort_keys(&mut self) {
+        sort_keys(&mut self.inner);
+    }
+
+    pub fn compile(&self) -> CompiledConfig {
+        CompiledConfig::new(&self.inner)
+    }
+
+    /// What changed from this config to `other`.
+    pub fn diff(&self, other: &Config) -> ConfigDiff {
+        diff(&self.inner, &other.inner)
+    }
+
+    /// Serialized with the encoder matching the extension of `path`.
+    #[cfg(feature = "builder")]
+    pub fn save_to(&self, path: impl AsRef<Path>) -> Result<(), crate::Error> {
+        crate::builder::save_file(
+            &crate::builder::default_loader().build(),
+            path.as_ref(),
+            &self.inner,
+            &[],
+        )
+    }
+
+    /// Writes changed keys back to the files that provided them, and keys from
+    /// defaults, env or other layers to the overrides file of `plan`. Returns
+    /// the files written.
+    #[cfg(feature = "builder")]
+    pub fn save_split(
+        &self,
+        plan: &crate::builder::SplitPlan,
+    ) -> Result<Vec<PathBuf>, crate::Error> {
+        crate::builder::save_split(&crate::builder::default_loader().build(), self, plan)
+    }
+
+    pub fn drift_from_defaults(&self) -> Vec<Drift> {
+        drift(&self.defaults, &self.inner)
     }
 
     #[cfg(feature = "serde")]
@@ -75,17 +348,24 @@ impl<S: AsRef<str>> std::ops::Index<S> for Config {
     type Output = Value;
     fn index(&self, idx: S) -> &Self::Output {
         static NULL: Value = Value::Null;
+        self.record_access(idx.as_ref());
         self.inner.get(idx.as_ref()).unwrap_or(&NULL)
     }
 }
 
 impl<S: AsRef<str>> std::ops::IndexMut<S> for Config {
+    // Missing keys are inserted as null, so this can't fail. get_mut and
+    // get_path_mut are the lookups that don't insert
     fn index_mut(&mut self, idx: S) -> &mut Self::Output {
-        if !self.inner.contains(idx.as_ref()) {
-            self.inner.insert(idx.as_ref().to_owned(), Value::Null);
+        let key = idx.as_ref();
+        if !self.inner.contains(key) {
+            self.inner.insert(key.to_owned(), Value::Null);
         }
 
-        self.inner.get_mut(idx.as_ref()).unwrap()
+        match self.inner.get_mut(key) {
+            Some(value) => value,
+            None => unreachable!("{} is inserted above", key),
+        }
     }
 }
 
@@ -107,7 +387,255 @@ impl<'de> serde::Deserialize<'de> for Config {
     {
         Ok(Config {
             inner: Map::deserialize(deserializer)?,
-            files: Vec::default(),
+            ..Default::default()
+        })
+    }
+}
+
+// Lets frameworks run `T::deserialize(&config)`. Values are deserialized by
+// reference, so nothing is cloned but scalars, and strings can be borrowed.
+// Errors name the top level key they occurred under
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserializer<'de> for &'de Config {
+    type Error = vaerdi::de::DeserializerError;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        let entries = self
+            .inner
+            .iter()
+            .map(|(key, value)| -> (&str, &Value) { (key, value) })
+            .collect::<Vec<_>>();
+
+        visitor.visit_map(ConfigAccess {
+            config: self,
+            entries: entries.into_iter(),
+            next: None,
         })
     }
+
+    // A config is always there, so `Option<T>` and newtypes wrap the map itself
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+#[cfg(feature = "serde")]
+struct ConfigAccess<'de> {
+    config: &'de Config,
+    entries: std::vec::IntoIter<(&'de str, &'de Value)>,
+    next: Option<(&'de str, &'de Value)>,
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::de::MapAccess<'de> for ConfigAccess<'de> {
+    type Error = vaerdi::de::DeserializerError;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
+    where
+        K: serde::de::DeserializeSeed<'de>,
+    {
+        let (key, value) = match self.entries.next() {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+
+        self.next = Some((key, value));
+
+        let key = serde::de::value::BorrowedStrDeserializer::<Self::Error>::new(key);
+        seed.deserialize(key).map(Some)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error>
+    where
+        V: serde::de::DeserializeSeed<'de>,
+    {
+        let (key, value) = match self.next.take() {
+            Some(entry) => entry,
+            None => {
+                return Err(serde::de::Error::custom(
+                    "next_value_seed called before next_key_seed",
+                ))
+            }
+        };
+
+        self.config.record_access(key);
+
+        seed.deserialize(ValueRef(value))
+            .map_err(|err| vaerdi::de::DeserializerError::Custom(format!("{}: {}", key, err)))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.entries.len())
+    }
+}
+
+// The deserialization behind try_get, for a value that was looked up elsewhere
+#[cfg(feature = "serde")]
+pub(crate) fn deserialize_field<'a, S: serde::Deserialize<'a>>(
+    name: &str,
+    value: Option<Value>,
+) -> Result<S, vaerdi::de::DeserializerError> {
+    match value {
+        Some(value) => S::deserialize(value),
+        None => Err(vaerdi::de::DeserializerError::Custom(format!(
+            "field not found: {}",
+            name
+        ))),
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::Config;
+    use serde::Deserialize;
+    use std::collections::BTreeMap;
+    use vaerdi::{value, Value};
+
+    fn config(value: Value) -> Config {
+        match value {
+            Value::Map(inner) => Config {
+                inner,
+                ..Default::default()
+            },
+            _ => panic!("not a map"),
+        }
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Server {
+        host: String,
+        #[serde(flatten)]
+        tls: Tls,
+        #[serde(flatten)]
+        rest: BTreeMap<String, Value>,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Tls {
+        cert: String,
+    }
+
+    #[test]
+    fn flatten() {
+        let config = config(value!({
+            "server": {"host": "localhost", "cert": "server.pem", "workers": "4"}
+        }));
+
+        let server: Server = config.try_get_ref("server").unwrap();
+        assert_eq!(server.host, "localhost");
+        assert_eq!(server.tls.cert, "server.pem");
+        assert_eq!(
+            server.rest.get("workers"),
+            Some(&Value::String("4".to_string()))
+        );
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(tag = "kind", rename_all = "lowercase")]
+    enum Store {
+        Memory,
+        Disk { path: String },
+    }
+
+    #[test]
+    fn internally_tagged() {
+        let config = config(value!({
+            "cache": {"kind": "memory"},
+            "data": {"kind": "disk", "path": "/var/lib/app"}
+        }));
+
+        assert_eq!(config.try_get_ref::<Store>("cache").unwrap(), Store::Memory);
+        assert_eq!(
+            config.try_get_ref::<Store>("data").unwrap(),
+            Store::Disk {
+                path: "/var/lib/app".to_string()
+            }
+        );
+    }
+
+    #[derive(Debug, Default, Deserialize, PartialEq)]
+    #[serde(default, rename_all = "kebab-case")]
+    struct Limits {
+        max_connections: Option<String>,
+        idle_timeout: Option<String>,
+    }
+
+    #[test]
+    fn default_and_rename_all() {
+        let config = config(value!({"limits": {"max-connections": "10"}}));
+
+        assert_eq!(
+            config.try_get_ref::<Limits>("limits").unwrap(),
+            Limits {
+                max_connections: Some("10".to_string()),
+                idle_timeout: None,
+            }
+        );
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct App<'a> {
+        name: &'a str,
+        server: Server,
+    }
+
+    #[test]
+    fn whole_config_borrows() {
+        let config = config(value!({
+            "name": "app",
+            "server": {"host": "localhost", "cert": "server.pem"}
+        }));
+
+        let app = App::deserialize(&config).unwrap();
+        assert_eq!(app.name, "app");
+        assert_eq!(app.server.tls.cert, "server.pem");
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Wrapped<'a>(#[serde(borrow)] App<'a>);
+
+    #[test]
+    fn whole_config_as_option_and_newtype() {
+        let config = config(value!({
+            "name": "app",
+            "server": {"host": "localhost", "cert": "server.pem"}
+        }));
+
+        let app = Option::<App>::deserialize(&config).unwrap();
+        assert_eq!(app.map(|m| m.name), Some("app"));
+
+        let wrapped = Wrapped::deserialize(&config).unwrap();
+        assert_eq!(wrapped.0.server.tls.cert, "server.pem");
+    }
+
+    #[test]
+    fn errors_name_the_top_level_key() {
+        let config = config(value!({"server": {"host": "localhost"}}));
+
+        let err = BTreeMap::<String, Server>::deserialize(&config).unwrap_err();
+        assert!(err.to_string().starts_with("server: "));
+    }
 }
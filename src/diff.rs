@@ -0,0 +1,72 @@
+use crate::key_path::KeyPath;
+use vaerdi::{Map, Value};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub path: KeyPath,
+    pub old: Option<Value>,
+    pub new: Option<Value>,
+}
+
+/// Maps are compared key by key, anything else, lists included, as a whole.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConfigDiff {
+    pub added: Vec<Change>,
+    pub removed: Vec<Change>,
+    pub changed: Vec<Change>,
+}
+
+impl ConfigDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Change> {
+        self.added
+            .iter()
+            .chain(self.removed.iter())
+            .chain(self.changed.iter())
+    }
+
+    /// Whether anything at, above or below `path` changed.
+    pub fn touches(&self, path: &KeyPath) -> bool {
+        self.iter()
+            .any(|change| change.path.starts_with(path) || path.starts_with(&change.path))
+    }
+}
+
+pub(crate) fn diff(old: &Map, new: &Map) -> ConfigDiff {
+    let mut out = ConfigDiff::default();
+    diff_map(&KeyPath::root(), old, new, &mut out);
+    out
+}
+
+fn diff_map(path: &KeyPath, old: &Map, new: &Map, out: &mut ConfigDiff) {
+    for (key, old) in old.iter() {
+        let path = path.key(key);
+        match (old, new.get(key)) {
+            (Value::Map(old), Some(Value::Map(new))) => diff_map(&path, old, new, out),
+            (old, Some(new)) if old != new => out.changed.push(Change {
+                path,
+                old: Some(old.clone()),
+                new: Some(new.clone()),
+            }),
+            (_, Some(_)) => {}
+            (old, None) => out.removed.push(Change {
+                path,
+                old: Some(old.clone()),
+                new: None,
+            }),
+        }
+    }
+
+    for (key, new) in new.iter() {
+        if !old.contains(key) {
+            out.added.push(Change {
+                path: path.key(key),
+                old: None,
+                new: Some(new.clone()),
+            });
+        }
+    }
+}
@@ -0,0 +1,34 @@
+use crate::key_path::KeyPath;
+use vaerdi::{Map, Value};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Drift {
+    pub path: KeyPath,
+    pub default: Value,
+    pub value: Option<Value>,
+}
+
+pub(crate) fn drift(defaults: &Map, config: &Map) -> Vec<Drift> {
+    let mut out = Vec::default();
+    drift_map(&KeyPath::root(), defaults, Some(config), &mut out);
+    out
+}
+
+fn drift_map(path: &KeyPath, defaults: &Map, config: Option<&Map>, out: &mut Vec<Drift>) {
+    for (key, default) in defaults.iter() {
+        let path = path.key(key);
+        let value = config.and_then(|m| m.get(key));
+
+        match (default, value) {
+            (Value::Map(default), Some(Value::Map(value))) => {
+                drift_map(&path, default, Some(value), out)
+            }
+            (default, value) if Some(default) != value => out.push(Drift {
+                path,
+                default: default.clone(),
+                value: value.cloned(),
+            }),
+            _ => {}
+        }
+    }
+}
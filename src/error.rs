@@ -1,3 +1,8 @@
+use crate::builder::ValidationError;
+#[cfg(feature = "watch")]
+use crate::builder::VetoError;
+use crate::key_path::{KeyPath, KeyPathError};
+use std::path::PathBuf;
 use thiserror::Error as ThisError;
 use toback::Error as TobackError;
 
@@ -9,4 +14,65 @@ pub enum Error {
     Serialize(#[from] TobackError),
     #[error("io: {0}")]
     Io(#[from] std::io::Error),
+    #[error("deserialize: {error} (files: {files:?})")]
+    Deserialize {
+        files: Vec<PathBuf>,
+        error: vaerdi::de::DeserializerError,
+    },
+    #[error("command `{command}`: {reason}")]
+    Command { command: String, reason: String },
+    #[error("layer {0} is not a map")]
+    InvalidLayer(String),
+    #[error("{path:?}: {reason}")]
+    LimitExceeded { path: PathBuf, reason: String },
+    #[error("path is not valid unicode: {0:?}")]
+    NonUnicodePath(PathBuf),
+    #[error("{origin}: conflicting types at {path}: {existing} is overridden by {incoming}")]
+    MergeConflict {
+        path: KeyPath,
+        origin: String,
+        existing: &'static str,
+        incoming: &'static str,
+    },
+    #[error(transparent)]
+    KeyPath(#[from] KeyPathError),
+    #[error("{error}\ntrace:\n{}", .trace.join("\n"))]
+    Traced {
+        error: Box<Error>,
+        trace: Vec<String>,
+    },
+    #[error("{path}: {error} (from {file:?})")]
+    Typed {
+        path: KeyPath,
+        file: Option<PathBuf>,
+        error: vaerdi::de::DeserializerError,
+    },
+    #[error("ambiguous formats for the same config: {0:?}")]
+    AmbiguousFormats(Vec<PathBuf>),
+    #[error("global config is already initialized")]
+    GlobalInitialized,
+    #[error("global config is not initialized")]
+    GlobalUninitialized,
+    #[error("invalid spec entry `{entry}`: {reason}")]
+    InvalidSpec { entry: String, reason: String },
+    #[error("profile inheritance cycle: {}", .0.join(" -> "))]
+    ProfileCycle(Vec<String>),
+    #[error("validation failed: {}", .0.iter().map(|m| m.to_string()).collect::<Vec<_>>().join(", "))]
+    Validation(Vec<ValidationError>),
+    #[error("{0:?} does not serialize to a map")]
+    NotAMap(PathBuf),
+    #[error("no user config file is configured")]
+    NoUserConfig,
+    #[error("{path:?}: {reason} at byte {offset}")]
+    Decode {
+        path: PathBuf,
+        offset: usize,
+        reason: String,
+    },
+    #[cfg(feature = "watch")]
+    #[error(transparent)]
+    Vetoed(#[from] VetoError),
+    #[cfg(feature = "watch")]
+    #[error("reload failed {failures} times in a row: {error}")]
+    PersistentReload { failures: usize, error: Box<Error> },
 }
@@ -0,0 +1,193 @@
+use crate::KeyPath;
+use serde::de::{Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use std::fmt;
+use vaerdi::{List, Map, Value};
+
+/// What a decoder does with a key that appears twice in the same document.
+///
+/// The properties and plist encoders take a policy, and so do the [`Json`] and
+/// [`Yaml`] encoders, which replace the toback ones when added to a builder.
+/// TOML parsers always refuse duplicate keys, as the format requires.
+///
+/// [`Json`]: crate::formats::Json
+/// [`Yaml`]: crate::formats::Yaml
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicateKeys {
+    LastWins,
+    FirstWins,
+    /// Fails with a [`DuplicateKeyError`] naming the second occurrence.
+    Error,
+}
+
+impl Default for DuplicateKeys {
+    fn default() -> Self {
+        DuplicateKeys::LastWins
+    }
+}
+
+/// A key that appears twice in one document, under [`DuplicateKeys::Error`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateKeyError {
+    pub path: KeyPath,
+    /// The line of the second occurrence, for formats that keep track of it.
+    pub line: Option<usize>,
+}
+
+impl fmt::Display for DuplicateKeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: duplicate key {}", line, self.path),
+            None => write!(f, "duplicate key {}", self.path),
+        }
+    }
+}
+
+impl std::error::Error for DuplicateKeyError {}
+
+// A parsed document with map entries kept in order, duplicates included, for
+// parsers whose own maps would drop them silently
+pub(crate) enum Node {
+    Map(Vec<(String, Node)>),
+    List(Vec<Node>),
+    Scalar(Value),
+}
+
+impl Node {
+    pub(crate) fn resolve(
+        self,
+        path: &KeyPath,
+        duplicates: DuplicateKeys,
+    ) -> Result<Value, DuplicateKeyError> {
+        match self {
+            Node::Map(entries) => {
+                let mut map = Map::default();
+                for (key, node) in entries {
+                    let path = path.key(&key);
+                    if map.contains(&key) {
+                        match duplicates {
+                            DuplicateKeys::LastWins => {}
+                            DuplicateKeys::FirstWins => continue,
+                            DuplicateKeys::Error => {
+                                return Err(DuplicateKeyError { path, line: None })
+                            }
+                        }
+                    }
+                    let value = node.resolve(&path, duplicates)?;
+                    map.insert(key, value);
+                }
+                Ok(Value::Map(map))
+            }
+            Node::List(nodes) => {
+                let mut list = List::default();
+                for (idx, node) in nodes.into_iter().enumerate() {
+                    list.push(node.resolve(&path.index(idx), duplicates)?);
+                }
+                Ok(Value::List(list))
+            }
+            Node::Scalar(value) => Ok(value),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Node {
+    fn deserialize<D>(deserializer: D) -> Result<Node, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(NodeVisitor)
+    }
+}
+
+struct NodeVisitor;
+
+// Scalars are turned into values by vaerdi's own serializer
+fn scalar<T: serde::Serialize + ?Sized, E: serde::de::Error>(value: &T) -> Result<Node, E> {
+    vaerdi::ser::to_value(value)
+        .map(Node::Scalar)
+        .map_err(E::custom)
+}
+
+struct Bytes<'a>(&'a [u8]);
+
+impl<'a> serde::Serialize for Bytes<'a> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+impl<'de> Visitor<'de> for NodeVisitor {
+    type Value = Node;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a config value")
+    }
+
+    fn visit_bool<E: serde::de::Error>(self, v: bool) -> Result<Node, E> {
+        Ok(Node::Scalar(Value::Bool(v)))
+    }
+
+    fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<Node, E> {
+        scalar(&v)
+    }
+
+    fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<Node, E> {
+        scalar(&v)
+    }
+
+    fn visit_f64<E: serde::de::Error>(self, v: f64) -> Result<Node, E> {
+        scalar(&v)
+    }
+
+    fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Node, E> {
+        Ok(Node::Scalar(Value::String(v.to_string())))
+    }
+
+    fn visit_bytes<E: serde::de::Error>(self, v: &[u8]) -> Result<Node, E> {
+        scalar(&Bytes(v))
+    }
+
+    fn visit_none<E: serde::de::Error>(self) -> Result<Node, E> {
+        Ok(Node::Scalar(Value::Null))
+    }
+
+    fn visit_unit<E: serde::de::Error>(self) -> Result<Node, E> {
+        Ok(Node::Scalar(Value::Null))
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Node, D::Error> {
+        Node::deserialize(deserializer)
+    }
+
+    // Plist dates and UIDs arrive wrapped in a newtype
+    fn visit_newtype_struct<D: Deserializer<'de>>(self, deserializer: D) -> Result<Node, D::Error> {
+        Node::deserialize(deserializer)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Node, A::Error> {
+        let mut nodes = Vec::new();
+        while let Some(node) = seq.next_element()? {
+            nodes.push(node);
+        }
+        Ok(Node::List(nodes))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Node, A::Error> {
+        let mut entries = Vec::new();
+        while let Some((key, node)) = map.next_entry::<String, Node>()? {
+            entries.push((key, node));
+        }
+        Ok(Node::Map(entries))
+    }
+}
+
+// The top level of a document that must be a map
+#[derive(Debug)]
+pub(crate) struct NotAMap(pub(crate) &'static str);
+
+impl fmt::Display for NotAMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the top level of {} must be a map", self.0)
+    }
+}
+
+impl std::error::Error for NotAMap {}
@@ -0,0 +1,73 @@
+use super::duplicates::{DuplicateKeys, Node, NotAMap};
+use crate::KeyPath;
+use toback::{Encoder, Error as TobackError};
+use vaerdi::{Map, Value};
+
+/// JSON with a [`DuplicateKeys`] policy. Added to a builder it replaces the
+/// toback JSON encoder, which keeps the last of duplicate keys.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Json {
+    duplicates: DuplicateKeys,
+}
+
+impl Json {
+    pub fn with_duplicates(duplicates: DuplicateKeys) -> Json {
+        Json { duplicates }
+    }
+}
+
+impl Encoder<Map> for Json {
+    fn extensions(&self) -> &[&str] {
+        &["json"]
+    }
+
+    fn load(&self, content: &[u8]) -> Result<Map, TobackError> {
+        let node: Node = serde_json::from_slice(content)
+            .map_err(|err| TobackError::Deserialize(Box::new(err)))?;
+
+        match node.resolve(&KeyPath::root(), self.duplicates) {
+            Ok(Value::Map(map)) => Ok(map),
+            Ok(_) => Err(TobackError::Deserialize(Box::new(NotAMap("a JSON config")))),
+            Err(err) => Err(TobackError::Deserialize(Box::new(err))),
+        }
+    }
+
+    fn save(&self, content: &Map) -> Result<Vec<u8>, TobackError> {
+        serde_json::to_vec_pretty(content).map_err(|err| TobackError::Serialize(Box::new(err)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Json;
+    use crate::formats::{DuplicateKeyError, DuplicateKeys};
+    use toback::{Encoder, Error as TobackError};
+    use vaerdi::{value, Value};
+
+    const DOCUMENT: &[u8] = br#"{ "server": { "port": 1, "port": 2 } }"#;
+
+    fn port(duplicates: DuplicateKeys) -> Value {
+        let map = Json::with_duplicates(duplicates).load(DOCUMENT).unwrap();
+        match map.get("server") {
+            Some(Value::Map(server)) => server.get("port").cloned().unwrap(),
+            other => panic!("expected a map, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn duplicate_keys_follow_the_policy() {
+        assert_eq!(port(DuplicateKeys::LastWins), value!(2));
+        assert_eq!(port(DuplicateKeys::FirstWins), value!(1));
+
+        match Json::with_duplicates(DuplicateKeys::Error).load(DOCUMENT) {
+            Err(TobackError::Deserialize(err)) => {
+                let err = err.downcast_ref::<DuplicateKeyError>().unwrap();
+                assert_eq!(err.path.to_string(), "server.port");
+            }
+            other => panic!(
+                "expected a duplicate key error, got {:?}",
+                other.map(|_| ())
+            ),
+        }
+    }
+}
@@ -0,0 +1,19 @@
+mod duplicates;
+#[cfg(feature = "json")]
+mod json;
+#[cfg(feature = "plist")]
+mod plist;
+#[cfg(feature = "properties")]
+pub mod properties;
+#[cfg(feature = "yaml")]
+mod yaml;
+
+pub use self::duplicates::{DuplicateKeyError, DuplicateKeys};
+#[cfg(feature = "json")]
+pub use self::json::Json;
+#[cfg(feature = "plist")]
+pub use self::plist::Plist;
+#[cfg(feature = "properties")]
+pub use self::properties::Properties;
+#[cfg(feature = "yaml")]
+pub use self::yaml::Yaml;
@@ -0,0 +1,43 @@
+use super::duplicates::{DuplicateKeys, Node, NotAMap};
+use crate::KeyPath;
+use toback::{Encoder, Error as TobackError};
+use vaerdi::{Map, Value};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Plist {
+    duplicates: DuplicateKeys,
+}
+
+impl Plist {
+    pub fn with_duplicates(duplicates: DuplicateKeys) -> Plist {
+        Plist { duplicates }
+    }
+}
+
+impl Encoder<Map> for Plist {
+    fn extensions(&self) -> &[&str] {
+        &["plist"]
+    }
+
+    fn load(&self, content: &[u8]) -> Result<Map, TobackError> {
+        // Detects both the XML and the binary property list format. Read into a
+        // tree that keeps every entry, since a dict would drop duplicate keys silently
+        let node: Node =
+            plist::from_bytes(content).map_err(|err| TobackError::Deserialize(Box::new(err)))?;
+
+        match node.resolve(&KeyPath::root(), self.duplicates) {
+            Ok(Value::Map(map)) => Ok(map),
+            Ok(_) => Err(TobackError::Deserialize(Box::new(NotAMap(
+                "a property list",
+            )))),
+            Err(err) => Err(TobackError::Deserialize(Box::new(err))),
+        }
+    }
+
+    fn save(&self, content: &Map) -> Result<Vec<u8>, TobackError> {
+        let mut out = Vec::new();
+        plist::to_writer_xml(&mut out, content)
+            .map_err(|err| TobackError::Serialize(Box::new(err)))?;
+        Ok(out)
+    }
+}
@@ -0,0 +1,404 @@
+use super::{DuplicateKeyError, DuplicateKeys};
+use crate::{
+    values::{insert_path, restore_lists},
+    KeyPath,
+};
+use std::{collections::HashSet, fmt};
+use toback::{Encoder, Error as TobackError};
+use vaerdi::{Map, Value};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PropertiesError {
+    Syntax { line: usize, message: &'static str },
+    Duplicate(DuplicateKeyError),
+}
+
+impl fmt::Display for PropertiesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PropertiesError::Syntax { line, message } => write!(f, "line {}: {}", line, message),
+            PropertiesError::Duplicate(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for PropertiesError {}
+
+/// Properties files only hold strings. Scalars are saved in their display form and
+/// load back as strings, nulls as empty strings; schema coercion can restore the
+/// types. Lists are saved as `key.0`, `key.1`, ... and maps keyed by consecutive
+/// indices from 0 load back as lists. Empty maps and lists are not written at all.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Properties {
+    duplicates: DuplicateKeys,
+}
+
+impl Properties {
+    pub fn with_duplicates(duplicates: DuplicateKeys) -> Properties {
+        Properties { duplicates }
+    }
+}
+
+impl Encoder<Map> for Properties {
+    fn extensions(&self) -> &[&str] {
+        &["properties"]
+    }
+
+    fn load(&self, content: &[u8]) -> Result<Map, TobackError> {
+        let content =
+            std::str::from_utf8(content).map_err(|err| TobackError::Deserialize(Box::new(err)))?;
+        // Duplicates are reported as the error all encoders use for them
+        from_str_with(content, self.duplicates).map_err(|err| match err {
+            PropertiesError::Duplicate(err) => TobackError::Deserialize(Box::new(err)),
+            err => TobackError::Deserialize(Box::new(err)),
+        })
+    }
+
+    fn save(&self, content: &Map) -> Result<Vec<u8>, TobackError> {
+        Ok(to_string(content).into_bytes())
+    }
+}
+
+pub fn from_str(input: &str) -> Result<Map, PropertiesError> {
+    from_str_with(input, DuplicateKeys::default())
+}
+
+pub fn from_str_with(input: &str, duplicates: DuplicateKeys) -> Result<Map, PropertiesError> {
+    let mut map = Map::default();
+    let mut seen = HashSet::new();
+
+    for (line, content) in logical_lines(input) {
+        let (key, value) = split(&content);
+        // Split before unescaping, so an escaped `\.` stays inside its segment
+        let path = split_key(key)
+            .into_iter()
+            .map(|segment| unescape(segment, line))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if !seen.insert(path.clone()) {
+            match duplicates {
+                DuplicateKeys::LastWins => {}
+                DuplicateKeys::FirstWins => continue,
+                DuplicateKeys::Error => {
+                    let path = path
+                        .iter()
+                        .fold(KeyPath::root(), |path, segment| path.key(segment));
+                    return Err(PropertiesError::Duplicate(DuplicateKeyError {
+                        path,
+                        line: Some(line),
+                    }));
+                }
+            }
+        }
+
+        let value = unescape(value, line)?;
+        insert_path(&mut map, &path, Value::String(value));
+    }
+
+    let mut out = Map::default();
+    for (key, value) in map.into_iter() {
+        out.insert(key, restore_lists(value));
+    }
+
+    Ok(out)
+}
+
+pub fn to_string(map: &Map) -> String {
+    let mut out = String::new();
+    for (key, value) in map.iter() {
+        write_value(&mut out, &escape_key(key), value);
+    }
+    out
+}
+
+// Joins continuation lines (a line ending in an odd number of backslashes) and
+// drops blank lines and comments. Yields the line number each entry starts on.
+fn logical_lines(input: &str) -> Vec<(usize, String)> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    let mut start = 0;
+    let mut continuing = false;
+
+    for (idx, line) in input.lines().enumerate() {
+        let line = line.trim_start();
+
+        if !continuing {
+            if line.is_empty() || line.starts_with('#') || line.starts_with('!') {
+                continue;
+            }
+            start = idx + 1;
+        }
+
+        current.push_str(line);
+
+        let trailing = current.chars().rev().take_while(|c| *c == '\\').count();
+        if trailing % 2 == 1 {
+            current.pop();
+            continuing = true;
+        } else {
+            lines.push((start, std::mem::take(&mut current)));
+            continuing = false;
+        }
+    }
+
+    if continuing {
+        lines.push((start, current));
+    }
+
+    lines
+}
+
+fn split(line: &str) -> (&str, &str) {
+    let mut escaped = false;
+
+    for (idx, c) in line.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+
+        match c {
+            '\\' => escaped = true,
+            '=' | ':' => return (&line[..idx], line[idx + 1..].trim_start()),
+            c if c.is_whitespace() => {
+                let rest = line[idx..].trim_start();
+                let rest = rest
+                    .strip_prefix(|c: char| c == '=' || c == ':')
+                    .unwrap_or(rest)
+                    .trim_start();
+                return (&line[..idx], rest);
+            }
+            _ => {}
+        }
+    }
+
+    (line, "")
+}
+
+// Splits a raw key on the dots that aren't escaped
+fn split_key(key: &str) -> Vec<&str> {
+    let mut out = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+
+    for (idx, c) in key.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+
+        match c {
+            '\\' => escaped = true,
+            '.' => {
+                out.push(&key[start..idx]);
+                start = idx + 1;
+            }
+            _ => {}
+        }
+    }
+
+    out.push(&key[start..]);
+    out
+}
+
+fn unescape(input: &str, line: usize) -> Result<String, PropertiesError> {
+    let mut out = String::with_capacity(input.len());
+    let mut chars = input.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some('f') => out.push('\u{c}'),
+            Some('u') => {
+                let unit = read_unit(&mut chars, line)?;
+                let code = if (0xD800..0xDC00).contains(&unit) {
+                    // Characters outside the BMP are escaped as a surrogate pair
+                    if chars.next() != Some('\\') || chars.next() != Some('u') {
+                        return Err(error(line, "unpaired surrogate in unicode escape"));
+                    }
+                    let low = read_unit(&mut chars, line)?;
+                    if !(0xDC00..0xE000).contains(&low) {
+                        return Err(error(line, "unpaired surrogate in unicode escape"));
+                    }
+                    0x10000 + ((unit - 0xD800) << 10) + (low - 0xDC00)
+                } else {
+                    unit
+                };
+                let c =
+                    char::from_u32(code).ok_or_else(|| error(line, "invalid unicode escape"))?;
+                out.push(c);
+            }
+            Some(other) => out.push(other),
+            None => {}
+        }
+    }
+
+    Ok(out)
+}
+
+fn read_unit(chars: &mut std::str::Chars<'_>, line: usize) -> Result<u32, PropertiesError> {
+    let hex = chars.take(4).collect::<String>();
+    if hex.len() != 4 {
+        return Err(error(line, "truncated unicode escape"));
+    }
+    u32::from_str_radix(&hex, 16).map_err(|_| error(line, "invalid unicode escape"))
+}
+
+fn error(line: usize, message: &'static str) -> PropertiesError {
+    PropertiesError::Syntax { line, message }
+}
+
+fn write_value(out: &mut String, key: &str, value: &Value) {
+    match value {
+        Value::Map(map) => {
+            for (child, value) in map.iter() {
+                write_value(out, &format!("{}.{}", key, escape_key(child)), value);
+            }
+        }
+        Value::List(list) => {
+            for (idx, value) in list.iter().enumerate() {
+                write_value(out, &format!("{}.{}", key, idx), value);
+            }
+        }
+        Value::Null => write_line(out, key, ""),
+        Value::String(s) => write_line(out, key, s),
+        other => write_line(out, key, &other.to_string()),
+    }
+}
+
+// `key` is already escaped, segment by segment
+fn write_line(out: &mut String, key: &str, value: &str) {
+    out.push_str(key);
+    out.push('=');
+
+    for (idx, c) in value.chars().enumerate() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            ' ' if idx == 0 => out.push_str("\\ "),
+            _ => escape_control(out, c),
+        }
+    }
+
+    out.push('\n');
+}
+
+// Escapes one key segment. Dots are escaped too, since they separate segments
+fn escape_key(key: &str) -> String {
+    let mut out = String::with_capacity(key.len());
+    for c in key.chars() {
+        match c {
+            ' ' | '=' | ':' | '#' | '!' | '\\' | '.' => {
+                out.push('\\');
+                out.push(c);
+            }
+            _ => escape_control(&mut out, c),
+        }
+    }
+    out
+}
+
+fn escape_control(out: &mut String, c: char) {
+    match c {
+        '\t' => out.push_str("\\t"),
+        '\n' => out.push_str("\\n"),
+        '\r' => out.push_str("\\r"),
+        '\u{c}' => out.push_str("\\f"),
+        c => out.push(c),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_str, from_str_with, to_string, PropertiesError};
+    use crate::formats::DuplicateKeys;
+    use vaerdi::{value, Value};
+
+    fn parse(input: &str) -> Value {
+        Value::Map(from_str(input).unwrap())
+    }
+
+    #[test]
+    fn separators_comments_and_continuations() {
+        let input = "# comment\n\
+                     ! also a comment\n\
+                     a=1\n\
+                     b : 2\n\
+                     c 3\n\
+                     d\n\
+                     long = one \\\n    two\n";
+
+        assert_eq!(
+            parse(input),
+            value!({"a": "1", "b": "2", "c": "3", "d": "", "long": "one two"})
+        );
+    }
+
+    #[test]
+    fn nested_keys_and_lists() {
+        assert_eq!(
+            parse("db.host=localhost\nhosts.1=b\nhosts.0=a\nescaped\\.key=x\n"),
+            value!({
+                "db": {"host": "localhost"},
+                "hosts": ["a", "b"],
+                "escaped.key": "x"
+            })
+        );
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(
+            parse("key\\ with\\ spaces=tab\\there\nemoji=\\ud83d\\ude00\nu=\\u00e9\n"),
+            value!({"key with spaces": "tab\there", "emoji": "\u{1F600}", "u": "\u{e9}"})
+        );
+
+        let err = from_str("\n\na=\\ud83d\n").unwrap_err();
+        assert_eq!(
+            err,
+            PropertiesError::Syntax {
+                line: 3,
+                message: "unpaired surrogate in unicode escape"
+            }
+        );
+        assert!(from_str("a=\\u00").is_err());
+    }
+
+    #[test]
+    fn duplicates() {
+        let input = "a=1\nb=2\na=3\n";
+
+        assert_eq!(parse(input), value!({"a": "3", "b": "2"}));
+        assert_eq!(
+            Value::Map(from_str_with(input, DuplicateKeys::FirstWins).unwrap()),
+            value!({"a": "1", "b": "2"})
+        );
+        match from_str_with(input, DuplicateKeys::Error) {
+            Err(PropertiesError::Duplicate(err)) => assert_eq!(err.line, Some(3)),
+            other => panic!("expected a duplicate key error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        let value = value!({
+            "a key": " leading space",
+            "dotted.key": "back\\slash",
+            "lines": "one\ntwo",
+            "hosts": ["a", "b"],
+            "db": {"port": "5432"}
+        });
+        let map = match &value {
+            Value::Map(map) => map,
+            _ => unreachable!(),
+        };
+
+        assert_eq!(parse(&to_string(map)), value);
+    }
+}
@@ -0,0 +1,46 @@
+use crate::{Config, ConfigFinder, Error};
+use serde::de::DeserializeOwned;
+use std::sync::{Arc, OnceLock, RwLock};
+
+struct Global {
+    finder: ConfigFinder,
+    config: RwLock<Arc<Config>>,
+}
+
+static GLOBAL: OnceLock<Global> = OnceLock::new();
+
+pub fn init(finder: ConfigFinder) -> Result<(), Error> {
+    let config = finder.config()?;
+
+    GLOBAL
+        .set(Global {
+            finder,
+            config: RwLock::new(Arc::new(config)),
+        })
+        .map_err(|_| Error::GlobalInitialized)
+}
+
+pub fn is_initialized() -> bool {
+    GLOBAL.get().is_some()
+}
+
+pub fn config() -> Result<Arc<Config>, Error> {
+    let global = GLOBAL.get().ok_or(Error::GlobalUninitialized)?;
+    let config = global.config.read().expect("global config lock poisoned");
+    Ok(config.clone())
+}
+
+pub fn get<T: DeserializeOwned>(key: &str) -> Result<T, Error> {
+    let config = config()?;
+    config.try_get(key).map_err(|error| Error::Deserialize {
+        files: config.files().to_vec(),
+        error,
+    })
+}
+
+pub fn reload() -> Result<(), Error> {
+    let global = GLOBAL.get().ok_or(Error::GlobalUninitialized)?;
+    let config = global.finder.config()?;
+    *global.config.write().expect("global config lock poisoned") = Arc::new(config);
+    Ok(())
+}
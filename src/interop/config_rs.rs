@@ -0,0 +1,36 @@
+use crate::{ConfigFinder, Error, Value};
+use ::config_rs::{ConfigError, Map, Source};
+use serde::Deserialize;
+
+/// Mounts the files discovered by a finder into a `config` crate stack.
+#[derive(Clone)]
+pub struct JohnfigSource(pub ConfigFinder);
+
+impl std::fmt::Debug for JohnfigSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("JohnfigSource").finish()
+    }
+}
+
+impl Source for JohnfigSource {
+    fn clone_into_box(&self) -> Box<dyn Source + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn collect(&self) -> Result<Map<String, ::config_rs::Value>, ConfigError> {
+        let config = self
+            .0
+            .config()
+            .map_err(|err| ConfigError::Foreign(Box::new(err)))?;
+
+        to_value(Value::Map(config.inner))?.into_table()
+    }
+}
+
+pub fn to_value(value: Value) -> Result<::config_rs::Value, ConfigError> {
+    ::config_rs::Value::deserialize(value).map_err(|err| ConfigError::Message(err.to_string()))
+}
+
+pub fn from_value(value: &::config_rs::Value) -> Result<Value, Error> {
+    vaerdi::ser::to_value(value).map_err(|err| Error::Unknown(Box::new(err)))
+}
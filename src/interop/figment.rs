@@ -0,0 +1,56 @@
+use crate::{Config, ConfigFinder, Value};
+use ::figment::{
+    value::{Dict, Map},
+    Error, Figment, Metadata, Profile, Provider,
+};
+use serde::Deserialize;
+
+/// Mounts the files discovered by a finder into a figment stack.
+pub struct JohnfigProvider {
+    finder: ConfigFinder,
+    profile: Profile,
+}
+
+impl JohnfigProvider {
+    pub fn new(finder: ConfigFinder) -> JohnfigProvider {
+        JohnfigProvider {
+            finder,
+            profile: Profile::Default,
+        }
+    }
+
+    pub fn with_profile(mut self, profile: impl Into<Profile>) -> Self {
+        self.profile = profile.into();
+        self
+    }
+}
+
+impl Provider for JohnfigProvider {
+    fn metadata(&self) -> Metadata {
+        Metadata::named("johnfig")
+    }
+
+    fn data(&self) -> Result<Map<Profile, Dict>, Error> {
+        let config = self
+            .finder
+            .config()
+            .map_err(|err| Error::from(err.to_string()))?;
+
+        let dict = to_dict(config.inner)?;
+
+        Ok(self.profile.collect(dict))
+    }
+}
+
+pub fn to_dict(map: vaerdi::Map) -> Result<Dict, Error> {
+    let value = ::figment::value::Value::deserialize(Value::Map(map))
+        .map_err(|err| Error::from(err.to_string()))?;
+
+    value
+        .into_dict()
+        .ok_or_else(|| Error::from(String::from("config is not a map")))
+}
+
+pub fn from_figment(figment: &Figment) -> Result<Config, Error> {
+    figment.extract()
+}
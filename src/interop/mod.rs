@@ -0,0 +1,4 @@
+#[cfg(feature = "config-rs")]
+pub mod config_rs;
+#[cfg(feature = "figment")]
+pub mod figment;
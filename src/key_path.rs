@@ -0,0 +1,265 @@
+use std::{fmt, str::FromStr};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyPathError(String);
+
+impl KeyPathError {
+    pub(crate) fn new(path: impl ToString) -> KeyPathError {
+        KeyPathError(path.to_string())
+    }
+}
+
+impl fmt::Display for KeyPathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid key path: {}", self.0)
+    }
+}
+
+impl std::error::Error for KeyPathError {}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct KeyPath(Vec<Segment>);
+
+impl KeyPath {
+    pub fn root() -> KeyPath {
+        KeyPath::default()
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.0
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn push_key(&mut self, key: impl ToString) {
+        self.0.push(Segment::Key(key.to_string()));
+    }
+
+    pub fn push_index(&mut self, idx: usize) {
+        self.0.push(Segment::Index(idx));
+    }
+
+    pub fn key(&self, key: impl ToString) -> KeyPath {
+        let mut path = self.clone();
+        path.push_key(key);
+        path
+    }
+
+    pub fn index(&self, idx: usize) -> KeyPath {
+        let mut path = self.clone();
+        path.push_index(idx);
+        path
+    }
+
+    pub fn starts_with(&self, other: &KeyPath) -> bool {
+        self.0.starts_with(&other.0)
+    }
+}
+
+impl fmt::Display for KeyPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (idx, segment) in self.0.iter().enumerate() {
+            match segment {
+                Segment::Key(key) if idx == 0 => write!(f, "{}", key)?,
+                Segment::Key(key) => write!(f, ".{}", key)?,
+                Segment::Index(i) => write!(f, "[{}]", i)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for KeyPath {
+    type Err = KeyPathError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut path = KeyPath::root();
+
+        if s.is_empty() {
+            return Ok(path);
+        }
+
+        let invalid = || KeyPathError(s.to_string());
+
+        for part in s.split('.') {
+            let (key, mut rest) = match part.find('[') {
+                Some(idx) => (&part[..idx], &part[idx..]),
+                None => (part, ""),
+            };
+
+            if key.is_empty() {
+                return Err(invalid());
+            }
+
+            path.push_key(key);
+
+            while !rest.is_empty() {
+                let end = rest.find(']').ok_or_else(invalid)?;
+                if !rest.starts_with('[') {
+                    return Err(invalid());
+                }
+                let idx = rest[1..end].parse::<usize>().map_err(|_| invalid())?;
+                path.push_index(idx);
+                rest = &rest[end + 1..];
+            }
+        }
+
+        Ok(path)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum PatternSegment {
+    Exact(Segment),
+    // `*`, any one key or index
+    Any,
+    // `**`, any number of segments, none included
+    Deep,
+}
+
+/// A key path with wildcards: `*` stands for any one segment and `**` for any
+/// number of them, so `services.*.endpoint` matches `services.api.endpoint` and
+/// `logging.**` matches `logging` and everything below it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct KeyPattern(Vec<PatternSegment>);
+
+impl KeyPattern {
+    /// Whether `path` matches the whole pattern.
+    pub fn matches(&self, path: &KeyPath) -> bool {
+        matches(&self.0, &path.0, false)
+    }
+
+    /// Whether `path` matches, or is above or below a path that would, like
+    /// [`ConfigDiff::touches`](crate::ConfigDiff::touches). A change there
+    /// changes a matching path.
+    pub fn touches(&self, path: &KeyPath) -> bool {
+        matches(&self.0, &path.0, true)
+    }
+}
+
+// With `prefix`, running out of either before the other ends counts as a match
+fn matches(pattern: &[PatternSegment], path: &[Segment], prefix: bool) -> bool {
+    match (pattern.first(), path.first()) {
+        (None, None) => true,
+        (None, Some(_)) => prefix,
+        (Some(PatternSegment::Deep), _) => {
+            matches(&pattern[1..], path, prefix)
+                || (!path.is_empty() && matches(pattern, &path[1..], prefix))
+        }
+        (Some(_), None) => prefix,
+        (Some(PatternSegment::Any), Some(_)) => matches(&pattern[1..], &path[1..], prefix),
+        (Some(PatternSegment::Exact(segment)), Some(next)) => {
+            segment == next && matches(&pattern[1..], &path[1..], prefix)
+        }
+    }
+}
+
+impl fmt::Display for KeyPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (idx, segment) in self.0.iter().enumerate() {
+            let dot = if idx == 0 { "" } else { "." };
+            match segment {
+                PatternSegment::Exact(Segment::Key(key)) => write!(f, "{dot}{key}")?,
+                PatternSegment::Exact(Segment::Index(i)) => write!(f, "[{i}]")?,
+                PatternSegment::Any => write!(f, "{dot}*")?,
+                PatternSegment::Deep => write!(f, "{dot}**")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for KeyPattern {
+    type Err = KeyPathError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut pattern = Vec::new();
+
+        if s.is_empty() {
+            return Ok(KeyPattern(pattern));
+        }
+
+        for part in s.split('.') {
+            match part {
+                "*" => pattern.push(PatternSegment::Any),
+                "**" => pattern.push(PatternSegment::Deep),
+                // A key with its indices, parsed as a path of its own
+                part => {
+                    let path = part
+                        .parse::<KeyPath>()
+                        .map_err(|_| KeyPathError(s.to_string()))?;
+                    pattern.extend(path.0.into_iter().map(PatternSegment::Exact));
+                }
+            }
+        }
+
+        Ok(KeyPattern(pattern))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KeyPath, KeyPattern};
+
+    fn path(s: &str) -> KeyPath {
+        s.parse().unwrap()
+    }
+
+    fn pattern(s: &str) -> KeyPattern {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn single_wildcards_match_one_segment() {
+        let pattern = pattern("services.*.endpoint");
+        assert!(pattern.matches(&path("services.api.endpoint")));
+        assert!(!pattern.matches(&path("services.endpoint")));
+        assert!(!pattern.matches(&path("services.api.v1.endpoint")));
+        assert!(!pattern.matches(&path("services.api")));
+        assert!(pattern.matches(&path("services[0].endpoint")));
+    }
+
+    #[test]
+    fn deep_wildcards_match_any_number_of_segments() {
+        let pattern = pattern("logging.**");
+        assert!(pattern.matches(&path("logging")));
+        assert!(pattern.matches(&path("logging.level")));
+        assert!(pattern.matches(&path("logging.targets[1].file")));
+        assert!(!pattern.matches(&path("log")));
+
+        let pattern = self::pattern("**.port");
+        assert!(pattern.matches(&path("port")));
+        assert!(pattern.matches(&path("server.http.port")));
+        assert!(!pattern.matches(&path("server.host")));
+    }
+
+    #[test]
+    fn paths_above_or_below_a_match_touch_the_pattern() {
+        let pattern = pattern("services.*.endpoint");
+        assert!(pattern.touches(&path("services")));
+        assert!(pattern.touches(&path("services.api")));
+        assert!(pattern.touches(&path("services.api.endpoint.host")));
+        assert!(!pattern.touches(&path("logging")));
+        assert!(!pattern.touches(&path("services.api.timeout")));
+    }
+
+    #[test]
+    fn patterns_display_as_parsed() {
+        for s in ["services.*.endpoint", "logging.**", "a[0].b", "**"] {
+            assert_eq!(pattern(s).to_string(), s);
+        }
+        assert!("services..endpoint".parse::<KeyPattern>().is_err());
+    }
+}
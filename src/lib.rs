@@ -1,19 +1,73 @@
 #[cfg(feature = "builder")]
 mod builder;
+#[cfg(feature = "chaos")]
+pub mod chaos;
 #[cfg(feature = "builder")]
 mod error;
 #[cfg(feature = "builder")]
+pub mod formats;
+#[cfg(feature = "builder")]
+pub mod global;
+#[cfg(feature = "builder")]
+pub mod interop;
+#[cfg(feature = "builder")]
 mod locator;
+#[cfg(feature = "builder")]
+mod path;
 
+mod access;
+#[cfg(feature = "serde")]
+mod borrowed;
+mod coercion;
+mod compiled;
 mod config;
+mod diff;
+mod drift;
+mod key_path;
+mod provenance;
 
-pub use self::config::Config;
+#[cfg(feature = "testing")]
+pub mod testing;
 
-pub use vaerdi::{value, Value};
+pub mod values;
+mod violation;
+
+pub use self::{
+    access::AccessReport,
+    coercion::Coercion,
+    compiled::{CompiledConfig, CompiledNode},
+    config::Config,
+    diff::{Change, ConfigDiff},
+    drift::Drift,
+    key_path::{KeyPath, KeyPathError, KeyPattern, Segment},
+    provenance::Source,
+    violation::Violation,
+};
+
+pub use vaerdi::{value, Map, Value};
 
 #[cfg(feature = "builder")]
 pub use self::{
-    builder::{ConfigBuilder, ConfigFinder},
+    builder::{
+        CheckReport, CommandResolver, ConfigBuilder, ConfigFile, ConfigFinder, ConflictPolicy,
+        DecodePolicy, Diagnostic, Encoding, Layer, LazyConfig, MergeReport, Override, Scope,
+        SearchPlan, SearchStep, Severity, SiblingPolicy, SortPolicy, SourceDescriptor, SplitPlan,
+        Trust, ValidationError,
+    },
     error::Error,
-    locator::{DirLocator, DirWalkLocator, Locator},
+    locator::{DirLocator, DirWalkLocator, LocatedItem, Locator, MatchStrategy, MemoryLocator},
+};
+
+#[cfg(feature = "archive")]
+pub use self::locator::ArchiveLocator;
+
+#[cfg(feature = "async")]
+pub use self::builder::{AsyncConfigFinder, BlockingTask, Spawner};
+
+#[cfg(feature = "watch")]
+pub use self::builder::{Johnfig, Subscription};
+
+#[cfg(feature = "watch")]
+pub use self::builder::{
+    ConfigCell, TypedWatch, VetoError, WatchEvent, WatchMode, WatchOptions, WatchableConfig,
 };
@@ -0,0 +1,193 @@
+use super::{BoxItemIterator, BoxIterator, LocatedItem, Locator, MatchStrategy};
+use crate::path::MATCH_OPTIONS;
+use std::{
+    fs::File,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+/// Reads config files straight out of a zip, tar or tar.gz archive, told apart by
+/// extension. Entries are matched on their path inside the archive, and the
+/// archive is read again on every load.
+pub struct ArchiveLocator(pub PathBuf);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+impl ArchiveLocator {
+    fn kind(&self) -> io::Result<Kind> {
+        let name = self
+            .0
+            .file_name()
+            .map(|m| m.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        if name.ends_with(".zip") {
+            Ok(Kind::Zip)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Ok(Kind::TarGz)
+        } else if name.ends_with(".tar") {
+            Ok(Kind::Tar)
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{:?} is not a zip, tar or tar.gz archive", self.0),
+            ))
+        }
+    }
+
+    // The files whose name `wanted` accepts, with their content
+    fn entries(&self, wanted: impl Fn(&str) -> bool) -> io::Result<Vec<(String, Vec<u8>)>> {
+        let file = File::open(&self.0)?;
+
+        match self.kind()? {
+            Kind::Zip => {
+                let mut archive = zip::ZipArchive::new(file).map_err(other)?;
+                let mut out = Vec::new();
+                for idx in 0..archive.len() {
+                    let mut entry = archive.by_index(idx).map_err(other)?;
+                    if entry.is_dir() || !wanted(entry.name()) {
+                        continue;
+                    }
+                    let name = entry.name().to_string();
+                    let mut bytes = Vec::new();
+                    entry.read_to_end(&mut bytes)?;
+                    out.push((name, bytes));
+                }
+                Ok(out)
+            }
+            Kind::Tar => tar_entries(tar::Archive::new(file), wanted),
+            Kind::TarGz => tar_entries(
+                tar::Archive::new(flate2::read::GzDecoder::new(file)),
+                wanted,
+            ),
+        }
+    }
+}
+
+fn tar_entries<R: Read>(
+    mut archive: tar::Archive<R>,
+    wanted: impl Fn(&str) -> bool,
+) -> io::Result<Vec<(String, Vec<u8>)>> {
+    let mut out = Vec::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+
+        let name = entry.path()?.to_string_lossy().into_owned();
+        if !wanted(&name) {
+            continue;
+        }
+
+        let mut bytes = Vec::new();
+        entry.read_to_end(&mut bytes)?;
+        out.push((name, bytes));
+    }
+    Ok(out)
+}
+
+fn other(err: zip::result::ZipError) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, err)
+}
+
+impl Locator for ArchiveLocator {
+    type Error = io::Error;
+
+    fn root(&self) -> &PathBuf {
+        &self.0
+    }
+
+    fn locate<'a>(
+        &'a self,
+        _search_names: &'a [glob::Pattern],
+    ) -> Result<BoxIterator<'a>, Self::Error> {
+        Ok(Box::new(std::iter::empty()))
+    }
+
+    fn items<'a>(
+        &'a self,
+        search_names: &'a [glob::Pattern],
+    ) -> Result<BoxItemIterator<'a>, Self::Error> {
+        let entries = self.entries(|name| {
+            search_names
+                .iter()
+                .any(|pattern| pattern.matches_path_with(Path::new(name), MATCH_OPTIONS))
+        })?;
+
+        Ok(Box::new(entries.into_iter().map(|(name, bytes)| {
+            LocatedItem::Content {
+                name,
+                bytes,
+                format: None,
+            }
+        })))
+    }
+
+    fn strategy(&self, _search_names: &[glob::Pattern]) -> MatchStrategy {
+        MatchStrategy::Scan
+    }
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use super::ArchiveLocator;
+    use crate::ConfigBuilder;
+    use std::path::PathBuf;
+    use vaerdi::value;
+
+    fn tar_gz(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("johnfig-{name}-{}.tar.gz", std::process::id()));
+        let encoder = flate2::write::GzEncoder::new(
+            std::fs::File::create(&path).unwrap(),
+            flate2::Compression::default(),
+        );
+
+        let mut builder = tar::Builder::new(encoder);
+        for (name, content) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, name, content.as_bytes())
+                .unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+
+        path
+    }
+
+    #[test]
+    fn matching_entries_are_loaded_from_the_archive() {
+        let path = tar_gz(
+            "archive",
+            &[
+                ("app.json", r#"{ "name": "app", "port": 1 }"#),
+                ("other.txt", "not config"),
+                ("nested/app.json", r#"{ "port": 2 }"#),
+            ],
+        );
+
+        let config = ConfigBuilder::new()
+            .with_name_pattern("app.{ext}")
+            .with_locator(ArchiveLocator(path))
+            .build_config()
+            .unwrap();
+
+        assert_eq!(config.get("name"), Some(&value!("app")));
+        assert_eq!(config.get("port"), Some(&value!(1)));
+    }
+
+    #[test]
+    fn unknown_archive_kinds_are_refused() {
+        let locator = ArchiveLocator(PathBuf::from("config.rar"));
+        assert!(locator.kind().is_err());
+    }
+}
@@ -1,6 +1,7 @@
 use std::path::PathBuf;
 
-use super::{BoxIterator, Locator};
+use super::{BoxIterator, Locator, MatchStrategy};
+use crate::path::{is_reserved, MATCH_OPTIONS};
 
 pub struct DirLocator(pub PathBuf);
 
@@ -8,13 +9,28 @@ impl Locator for DirLocator {
     type Error = std::io::Error;
 
     fn root(&self) -> &PathBuf {
-        todo!()
+        &self.0
     }
 
     fn locate<'a>(
         &'a self,
         search_names: &'a [glob::Pattern],
     ) -> Result<BoxIterator<'a>, Self::Error> {
+        // Literal names can only match the file with that exact name, so a stat
+        // each is enough and large directories are never listed
+        if stat_only(search_names) {
+            std::fs::metadata(&self.0)?;
+
+            let iter = search_names
+                .iter()
+                .map(|pattern| self.0.join(pattern.as_str()))
+                .filter(|path| {
+                    path.is_file() && !path.file_name().map(is_reserved).unwrap_or(true)
+                });
+
+            return Ok(Box::new(iter));
+        }
+
         let iter = DirLocatorIter {
             root: &self.0,
             iter: std::fs::read_dir(&self.0)?,
@@ -23,6 +39,27 @@ impl Locator for DirLocator {
 
         Ok(Box::new(iter.flatten()))
     }
+
+    fn strategy(&self, search_names: &[glob::Pattern]) -> MatchStrategy {
+        if stat_only(search_names) {
+            MatchStrategy::Stat
+        } else {
+            MatchStrategy::Scan
+        }
+    }
+}
+
+// Where matching ignores case (Windows), a literal name matches files whose name
+// is spelled differently, and the directory has to be listed to report those
+fn stat_only(search_names: &[glob::Pattern]) -> bool {
+    MATCH_OPTIONS.case_sensitive && search_names.iter().all(is_literal)
+}
+
+fn is_literal(pattern: &glob::Pattern) -> bool {
+    let name = pattern.as_str();
+    !name.is_empty()
+        && glob::Pattern::escape(name) == name
+        && !name.chars().any(std::path::is_separator)
 }
 
 pub struct DirLocatorIter<'a> {
@@ -55,6 +92,10 @@ impl<'a> Iterator for DirLocatorIter<'a> {
                 continue;
             }
 
+            if is_reserved(&next.file_name()) {
+                continue;
+            }
+
             let path = next.path();
 
             let filename = match pathdiff::diff_paths(&path, &self.root) {
@@ -65,7 +106,7 @@ impl<'a> Iterator for DirLocatorIter<'a> {
             };
 
             let iter = self.patterns.iter().filter_map(move |pattern| {
-                if pattern.matches_path(&filename) {
+                if pattern.matches_path_with(&filename, MATCH_OPTIONS) {
                     Some(next.path())
                 } else {
                     None
@@ -1,3 +1,5 @@
+use super::MatchStrategy;
+use crate::path::{canonicalize, is_reserved, MATCH_OPTIONS};
 use crate::Locator;
 use std::path::{Path, PathBuf};
 
@@ -8,7 +10,7 @@ pub struct DirWalkLocator {
 
 impl DirWalkLocator {
     pub fn new(root: PathBuf, depth: usize) -> std::io::Result<DirWalkLocator> {
-        let root = std::fs::canonicalize(root)?;
+        let root = canonicalize(root)?;
         Ok(DirWalkLocator { root, depth })
     }
 }
@@ -45,8 +47,12 @@ impl Locator for DirWalkLocator {
                     None => return None,
                 };
 
+                if is_reserved(file.as_os_str()) {
+                    return None;
+                }
+
                 for pattern in search_names {
-                    if pattern.matches_path(&file) {
+                    if pattern.matches_path_with(&file, MATCH_OPTIONS) {
                         return Some(path);
                     }
                 }
@@ -56,4 +62,8 @@ impl Locator for DirWalkLocator {
 
         Ok(Box::new(iter))
     }
+
+    fn strategy(&self, _search_names: &[glob::Pattern]) -> MatchStrategy {
+        MatchStrategy::Walk { depth: self.depth }
+    }
 }
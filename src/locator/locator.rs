@@ -2,6 +2,30 @@ use std::path::PathBuf;
 
 pub type BoxIterator<'a> = Box<dyn Iterator<Item = PathBuf> + 'a>;
 
+pub type BoxItemIterator<'a> = Box<dyn Iterator<Item = LocatedItem> + 'a>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LocatedItem {
+    Path(PathBuf),
+    // Content that doesn't live on disk. Without a format, the extension of `name` is used
+    Content {
+        name: String,
+        bytes: Vec<u8>,
+        format: Option<String>,
+    },
+}
+
+/// How a locator goes about matching search names, as reported by search plans.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchStrategy {
+    // Each name is checked directly, without listing the directory
+    Stat,
+    Scan,
+    Walk { depth: usize },
+    Memory,
+    Custom,
+}
+
 pub trait Locator {
     // type Iter: Iterator<Item = PathBuf>;
     type Error;
@@ -11,6 +35,18 @@ pub trait Locator {
         &'a self,
         search_names: &'a [glob::Pattern],
     ) -> Result<BoxIterator<'a>, Self::Error>;
+
+    fn items<'a>(
+        &'a self,
+        search_names: &'a [glob::Pattern],
+    ) -> Result<BoxItemIterator<'a>, Self::Error> {
+        let iter = self.locate(search_names)?;
+        Ok(Box::new(iter.map(LocatedItem::Path)))
+    }
+
+    fn strategy(&self, _search_names: &[glob::Pattern]) -> MatchStrategy {
+        MatchStrategy::Custom
+    }
 }
 
 pub type BoxLocator = Box<dyn Locator<Error = Box<dyn std::error::Error>> + Send + Sync>;
@@ -35,6 +71,18 @@ where
         let iter = self.0.locate(search_names)?;
         Ok(iter)
     }
+
+    fn items<'a>(
+        &'a self,
+        search_names: &'a [glob::Pattern],
+    ) -> Result<BoxItemIterator<'a>, Self::Error> {
+        let iter = self.0.items(search_names)?;
+        Ok(iter)
+    }
+
+    fn strategy(&self, search_names: &[glob::Pattern]) -> MatchStrategy {
+        self.0.strategy(search_names)
+    }
 }
 
 pub fn locatorbox<L: Locator + 'static>(locator: L) -> BoxLocator
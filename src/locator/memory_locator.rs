@@ -0,0 +1,72 @@
+use super::{BoxItemIterator, BoxIterator, LocatedItem, Locator, MatchStrategy};
+use crate::path::MATCH_OPTIONS;
+use std::path::{Path, PathBuf};
+
+pub struct MemoryLocator {
+    root: PathBuf,
+    items: Vec<(String, Vec<u8>, Option<String>)>,
+}
+
+impl MemoryLocator {
+    pub fn new(root: impl Into<PathBuf>) -> MemoryLocator {
+        MemoryLocator {
+            root: root.into(),
+            items: Vec::default(),
+        }
+    }
+
+    pub fn with_content(mut self, name: impl ToString, bytes: impl Into<Vec<u8>>) -> Self {
+        self.add_content(name, bytes, None);
+        self
+    }
+
+    pub fn add_content(
+        &mut self,
+        name: impl ToString,
+        bytes: impl Into<Vec<u8>>,
+        format: Option<String>,
+    ) -> &mut Self {
+        self.items.push((name.to_string(), bytes.into(), format));
+        self
+    }
+}
+
+impl Locator for MemoryLocator {
+    type Error = std::convert::Infallible;
+
+    fn root(&self) -> &PathBuf {
+        &self.root
+    }
+
+    fn locate<'a>(
+        &'a self,
+        _search_names: &'a [glob::Pattern],
+    ) -> Result<BoxIterator<'a>, Self::Error> {
+        Ok(Box::new(std::iter::empty()))
+    }
+
+    fn items<'a>(
+        &'a self,
+        search_names: &'a [glob::Pattern],
+    ) -> Result<BoxItemIterator<'a>, Self::Error> {
+        let iter = self
+            .items
+            .iter()
+            .filter(move |(name, _, _)| {
+                search_names
+                    .iter()
+                    .any(|pattern| pattern.matches_path_with(Path::new(name), MATCH_OPTIONS))
+            })
+            .map(|(name, bytes, format)| LocatedItem::Content {
+                name: name.clone(),
+                bytes: bytes.clone(),
+                format: format.clone(),
+            });
+
+        Ok(Box::new(iter))
+    }
+
+    fn strategy(&self, _search_names: &[glob::Pattern]) -> MatchStrategy {
+        MatchStrategy::Memory
+    }
+}
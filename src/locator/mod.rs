@@ -1,5 +1,11 @@
+#[cfg(feature = "archive")]
+mod archive_locator;
 mod dir_locator;
 mod dir_walk_locator;
 mod locator;
+mod memory_locator;
 
-pub use self::{dir_locator::*, dir_walk_locator::*, locator::*};
+pub use self::{dir_locator::*, dir_walk_locator::*, locator::*, memory_locator::*};
+
+#[cfg(feature = "archive")]
+pub use self::archive_locator::ArchiveLocator;
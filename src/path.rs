@@ -0,0 +1,158 @@
+use std::{
+    ffi::OsStr,
+    io,
+    path::{Path, PathBuf},
+};
+
+pub(crate) const MATCH_OPTIONS: glob::MatchOptions = glob::MatchOptions {
+    case_sensitive: !cfg!(windows),
+    require_literal_separator: false,
+    require_literal_leading_dot: false,
+};
+
+// Paths at or above MAX_PATH only work on Windows in their verbatim (\\?\) form
+const MAX_PATH: usize = 260;
+
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+pub(crate) fn system_config_dir() -> PathBuf {
+    if cfg!(windows) {
+        std::env::var_os("PROGRAMDATA")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(r"C:\ProgramData"))
+    } else {
+        PathBuf::from("/etc")
+    }
+}
+
+pub(crate) fn user_config_dir() -> Option<PathBuf> {
+    if cfg!(windows) {
+        return std::env::var_os("APPDATA").map(PathBuf::from);
+    }
+
+    match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+        _ => std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")),
+    }
+}
+
+pub(crate) fn canonicalize(path: impl AsRef<Path>) -> io::Result<PathBuf> {
+    let path = std::fs::canonicalize(path)?;
+    Ok(simplify(path))
+}
+
+#[cfg(windows)]
+fn simplify(path: PathBuf) -> PathBuf {
+    match path.to_str().and_then(simplify_verbatim) {
+        Some(simplified) => PathBuf::from(simplified),
+        None => path,
+    }
+}
+
+#[cfg(not(windows))]
+fn simplify(path: PathBuf) -> PathBuf {
+    path
+}
+
+// The plain form of a verbatim drive or UNC path, when it is short enough to
+// work without the prefix. Plain string logic, so it is tested on every platform
+#[cfg_attr(not(windows), allow(dead_code))]
+fn simplify_verbatim(path: &str) -> Option<String> {
+    let simplified = if let Some(rest) = path.strip_prefix(r"\\?\UNC\") {
+        format!(r"\\{}", rest)
+    } else if let Some(rest) = path.strip_prefix(r"\\?\") {
+        if rest.chars().nth(1) != Some(':') {
+            return None;
+        }
+        rest.to_string()
+    } else {
+        return None;
+    };
+
+    if simplified.len() < MAX_PATH {
+        Some(simplified)
+    } else {
+        None
+    }
+}
+
+pub(crate) fn is_reserved(file_name: &OsStr) -> bool {
+    cfg!(windows) && matches!(file_name.to_str(), Some(name) if is_reserved_name(name))
+}
+
+// Whether Windows reserves `name` for a device, whatever its extension
+fn is_reserved_name(name: &str) -> bool {
+    let stem = name.split('.').next().unwrap_or(name).trim_end();
+
+    RESERVED_NAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(stem))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn simplifies_verbatim_paths() {
+        assert_eq!(
+            simplify_verbatim(r"\\?\C:\config\app.json").as_deref(),
+            Some(r"C:\config\app.json")
+        );
+        assert_eq!(
+            simplify_verbatim(r"\\?\UNC\server\share\app.json").as_deref(),
+            Some(r"\\server\share\app.json")
+        );
+        // Not a drive path, so the verbatim prefix is needed
+        assert_eq!(simplify_verbatim(r"\\?\Volume{1234}\app.json"), None);
+        assert_eq!(simplify_verbatim(r"C:\config\app.json"), None);
+        assert_eq!(simplify_verbatim("/etc/app.json"), None);
+    }
+
+    #[test]
+    fn keeps_long_paths_verbatim() {
+        let long = format!(r"\\?\C:\{}\app.json", "a".repeat(MAX_PATH));
+        assert_eq!(simplify_verbatim(&long), None);
+
+        let long = format!(r"\\?\UNC\server\{}\app.json", "a".repeat(MAX_PATH));
+        assert_eq!(simplify_verbatim(&long), None);
+    }
+
+    #[test]
+    fn detects_reserved_names() {
+        for name in ["CON", "con", "nul.json", "Com1.yaml", "LPT9", "aux .toml"] {
+            assert!(is_reserved_name(name), "{}", name);
+        }
+
+        for name in ["config.json", "console.json", "COM10", "lpt.yaml", "xcon"] {
+            assert!(!is_reserved_name(name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn reserved_only_on_windows() {
+        assert_eq!(is_reserved(OsStr::new("CON")), cfg!(windows));
+        assert!(!is_reserved(OsStr::new("config.json")));
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn paths_stay_as_they_are_elsewhere() {
+        assert_eq!(
+            simplify(PathBuf::from(r"\\?\C:\config\app.json")),
+            PathBuf::from(r"\\?\C:\config\app.json")
+        );
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn simplifies_on_windows() {
+        assert_eq!(
+            simplify(PathBuf::from(r"\\?\C:\config\app.json")),
+            PathBuf::from(r"C:\config\app.json")
+        );
+    }
+}
@@ -0,0 +1,107 @@
+use crate::key_path::{KeyPath, Segment};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+use vaerdi::{Map, Value};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Defaults,
+    Layer(String),
+    File(PathBuf),
+    Content(String),
+}
+
+impl Source {
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Source::File(path) => Some(path),
+            _ => None,
+        }
+    }
+}
+
+// The source of every leaf in a merged config. Lists are leaves, since merging
+// replaces them as a whole
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Origins(BTreeMap<KeyPath, Source>);
+
+impl Origins {
+    pub fn record(&mut self, map: &Map, source: &Source) {
+        self.record_map(&KeyPath::root(), map, source)
+    }
+
+    fn record_map(&mut self, path: &KeyPath, map: &Map, source: &Source) {
+        for (key, value) in map.iter() {
+            let path = path.key(key);
+            match value {
+                Value::Map(child) => self.record_map(&path, child, source),
+                _ => self.set(path, source.clone()),
+            }
+        }
+    }
+
+    // A leaf replaces whatever was recorded above or below it
+    pub fn set(&mut self, path: KeyPath, source: Source) {
+        self.clear(&path);
+        self.0.insert(path, source);
+    }
+
+    pub fn clear(&mut self, path: &KeyPath) {
+        let mut ancestor = KeyPath::root();
+        for segment in path.segments() {
+            self.0.remove(&ancestor);
+            push(&mut ancestor, segment);
+        }
+
+        self.remove(path);
+    }
+
+    pub fn remove(&mut self, path: &KeyPath) {
+        let nested = self
+            .0
+            .range(path.clone()..)
+            .take_while(|(m, _)| m.starts_with(path))
+            .map(|(m, _)| m.clone())
+            .collect::<Vec<_>>();
+
+        for path in nested {
+            self.0.remove(&path);
+        }
+    }
+
+    // The source of the value at `path`, or of the leaf containing it. Maps
+    // merged from several sources have no single origin
+    pub fn get(&self, path: &KeyPath) -> Option<&Source> {
+        let mut current = KeyPath::root();
+        for segment in path.segments() {
+            push(&mut current, segment);
+            if let Some(source) = self.0.get(&current) {
+                return Some(source);
+            }
+        }
+        None
+    }
+
+    pub fn extend(&mut self, prefix: &KeyPath, other: Origins) {
+        for (path, source) in other.0 {
+            let mut full = prefix.clone();
+            for segment in path.segments() {
+                push(&mut full, segment);
+            }
+            self.set(full, source);
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&KeyPath, &Source)> {
+        self.0.iter()
+    }
+}
+
+fn push(path: &mut KeyPath, segment: &Segment) {
+    match segment {
+        Segment::Key(key) => path.push_key(key),
+        Segment::Index(idx) => path.push_index(*idx),
+    }
+}
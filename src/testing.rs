@@ -0,0 +1,49 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct TreeSpec {
+    pub files: usize,
+    pub keys: usize,
+    pub depth: usize,
+}
+
+pub fn generate_tree(root: &Path, spec: &TreeSpec) -> io::Result<Vec<PathBuf>> {
+    std::fs::create_dir_all(root)?;
+
+    let mut files = Vec::with_capacity(spec.files);
+
+    for file in 0..spec.files {
+        let mut content = String::new();
+        write_object(&mut content, file, spec.keys, spec.depth);
+
+        let path = root.join(format!("{:05}.config.json", file));
+        std::fs::write(&path, content)?;
+        files.push(path);
+    }
+
+    Ok(files)
+}
+
+fn write_object(out: &mut String, file: usize, keys: usize, depth: usize) {
+    out.push('{');
+
+    for key in 0..keys {
+        if key > 0 {
+            out.push(',');
+        }
+        out.push_str(&format!("\"key{}\":\"value-{}-{}\"", key, file, key));
+    }
+
+    if depth > 1 {
+        if keys > 0 {
+            out.push(',');
+        }
+        out.push_str("\"nested\":");
+        write_object(out, file, keys, depth - 1);
+    }
+
+    out.push('}');
+}
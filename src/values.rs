@@ -0,0 +1,507 @@
+use crate::key_path::{KeyPath, KeyPathError, Segment};
+use vaerdi::{List, Map, Value};
+
+pub fn canonicalize(value: &mut Value) {
+    match value {
+        Value::Map(map) => canonicalize_map(map),
+        Value::List(list) => {
+            for item in list.iter_mut() {
+                canonicalize(item);
+            }
+        }
+        Value::Null | Value::Bool(_) | Value::String(_) => {}
+        _ => normalize_number(value),
+    }
+}
+
+pub fn canonicalize_map(map: &mut Map) {
+    let mut entries = std::mem::take(map)
+        .into_iter()
+        .filter(|(_, value)| !matches!(value, Value::Null))
+        .collect::<Vec<_>>();
+
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+    for (key, mut value) in entries {
+        canonicalize(&mut value);
+        map.insert(key, value);
+    }
+}
+
+// Integers become i64 where they fit and floats without a fraction become
+// integers, so `1`, `1u8` and `1.0` from different encoders compare equal
+#[cfg(feature = "serde")]
+fn normalize_number(value: &mut Value) {
+    use serde::Deserialize;
+
+    let normalized = if let Ok(int) = i64::deserialize(value.clone()) {
+        vaerdi::ser::to_value(int)
+    } else if let Ok(int) = u64::deserialize(value.clone()) {
+        vaerdi::ser::to_value(int)
+    } else {
+        match f64::deserialize(value.clone()) {
+            Ok(float)
+                if float.fract() == 0.0 && float >= i64::MIN as f64 && float < i64::MAX as f64 =>
+            {
+                vaerdi::ser::to_value(float as i64)
+            }
+            Ok(float) => vaerdi::ser::to_value(float),
+            // Not a number
+            Err(_) => return,
+        }
+    };
+
+    if let Ok(normalized) = normalized {
+        *value = normalized;
+    }
+}
+
+#[cfg(not(feature = "serde"))]
+fn normalize_number(_value: &mut Value) {}
+
+/// Map keeps insertion order, so reinserting in key order makes iteration and
+/// serialized output sorted. Unlike canonicalize, null entries are kept.
+pub fn sort_keys(map: &mut Map) {
+    let mut entries = std::mem::take(map).into_iter().collect::<Vec<_>>();
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+    for (key, mut value) in entries {
+        sort_value(&mut value);
+        map.insert(key, value);
+    }
+}
+
+fn sort_value(value: &mut Value) {
+    match value {
+        Value::Map(map) => sort_keys(map),
+        Value::List(list) => {
+            for item in list.iter_mut() {
+                sort_value(item);
+            }
+        }
+        _ => {}
+    }
+}
+
+pub fn flatten(map: &Map, separator: char) -> Map {
+    let mut out = Map::default();
+    flatten_into(&mut out, None, map, separator);
+    out
+}
+
+fn flatten_into(out: &mut Map, prefix: Option<&str>, map: &Map, separator: char) {
+    for (key, value) in map.iter() {
+        let key = escape_key(key, separator);
+        let key = match prefix {
+            Some(prefix) => format!("{}{}{}", prefix, separator, key),
+            None => key,
+        };
+
+        match value {
+            Value::Map(child) if !child.is_empty() => {
+                flatten_into(out, Some(&key), child, separator)
+            }
+            value => {
+                out.insert(key, value.clone());
+            }
+        }
+    }
+}
+
+pub fn unflatten(map: Map, separator: char) -> Map {
+    let mut out = Map::default();
+    for (key, value) in map.into_iter() {
+        let path = split_key(&key, separator);
+        insert_path(&mut out, &path, value);
+    }
+    out
+}
+
+pub(crate) fn lookup<'a>(map: &'a Map, path: &KeyPath) -> Option<&'a Value> {
+    let (first, rest) = path.segments().split_first()?;
+
+    let mut value = match first {
+        Segment::Key(key) => map.get(key.as_str())?,
+        Segment::Index(_) => return None,
+    };
+
+    for segment in rest {
+        value = match (segment, value) {
+            (Segment::Key(key), Value::Map(map)) => map.get(key.as_str())?,
+            (Segment::Index(idx), Value::List(list)) => list.iter().nth(*idx)?,
+            _ => return None,
+        };
+    }
+
+    Some(value)
+}
+
+pub(crate) fn lookup_mut<'a>(map: &'a mut Map, path: &KeyPath) -> Option<&'a mut Value> {
+    let (first, rest) = path.segments().split_first()?;
+
+    let mut value = match first {
+        Segment::Key(key) => map.get_mut(key.as_str())?,
+        Segment::Index(_) => return None,
+    };
+
+    for segment in rest {
+        value = match (segment, value) {
+            (Segment::Key(key), Value::Map(map)) => map.get_mut(key.as_str())?,
+            (Segment::Index(idx), Value::List(list)) => list.iter_mut().nth(*idx)?,
+            _ => return None,
+        };
+    }
+
+    Some(value)
+}
+
+// Sets the value at `path`, creating missing maps on the way. A list index may
+// be one past the end, which appends
+pub(crate) fn insert_at(
+    map: &mut Map,
+    path: &KeyPath,
+    value: Value,
+) -> Result<Option<Value>, KeyPathError> {
+    let invalid = || KeyPathError::new(path);
+
+    let (first, rest) = path.segments().split_first().ok_or_else(invalid)?;
+    let key = match first {
+        Segment::Key(key) => key,
+        Segment::Index(_) => return Err(invalid()),
+    };
+
+    let (last, middle) = match rest.split_last() {
+        Some(ret) => ret,
+        None => return Ok(map.insert(key.as_str(), value)),
+    };
+
+    let mut current = child(map, key).ok_or_else(invalid)?;
+
+    for segment in middle {
+        current = match (segment, current) {
+            (Segment::Key(key), Value::Map(map)) => child(map, key).ok_or_else(invalid)?,
+            (Segment::Index(idx), Value::List(list)) => {
+                list.iter_mut().nth(*idx).ok_or_else(invalid)?
+            }
+            _ => return Err(invalid()),
+        };
+    }
+
+    match (last, current) {
+        (Segment::Key(key), Value::Map(map)) => Ok(map.insert(key.as_str(), value)),
+        (Segment::Index(idx), Value::List(list)) => {
+            let len = list.len();
+            match list.iter_mut().nth(*idx) {
+                Some(slot) => Ok(Some(std::mem::replace(slot, value))),
+                None if *idx == len => {
+                    list.push(value);
+                    Ok(None)
+                }
+                None => Err(invalid()),
+            }
+        }
+        _ => Err(invalid()),
+    }
+}
+
+fn child<'a>(map: &'a mut Map, key: &str) -> Option<&'a mut Value> {
+    if matches!(map.get(key), None | Some(Value::Null)) {
+        map.insert(key, Value::Map(Map::default()));
+    }
+    map.get_mut(key)
+}
+
+pub(crate) fn insert_path(map: &mut Map, path: &[String], value: Value) {
+    let (first, rest) = match path.split_first() {
+        Some(ret) => ret,
+        None => return,
+    };
+
+    if rest.is_empty() {
+        map.insert(first.as_str(), value);
+        return;
+    }
+
+    if !matches!(map.get(first), Some(Value::Map(_))) {
+        map.insert(first.as_str(), Value::Map(Map::default()));
+    }
+
+    if let Some(Value::Map(child)) = map.get_mut(first) {
+        insert_path(child, rest, value);
+    }
+}
+
+// A separator inside a key is escaped with a backslash, as is the backslash itself
+fn escape_key(key: &str, separator: char) -> String {
+    let mut out = String::with_capacity(key.len());
+    for c in key.chars() {
+        if c == '\\' || c == separator {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+fn split_key(key: &str, separator: char) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut chars = key.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.peek() {
+                Some(&next) if next == '\\' || next == separator => {
+                    current.push(next);
+                    chars.next();
+                }
+                _ => current.push(c),
+            }
+        } else if c == separator {
+            parts.push(std::mem::take(&mut current));
+        } else {
+            current.push(c);
+        }
+    }
+
+    parts.push(current);
+    parts
+}
+
+pub fn expand_dotted_keys(map: Map) -> Map {
+    let mut out = Map::default();
+    for (key, value) in map.into_iter() {
+        let value = match value {
+            Value::Map(map) => Value::Map(expand_dotted_keys(map)),
+            value => value,
+        };
+        let path = key.split('.').map(String::from).collect::<Vec<_>>();
+        merge_path(&mut out, &path, value);
+    }
+    out
+}
+
+fn merge_path(map: &mut Map, path: &[String], value: Value) {
+    let (first, rest) = match path.split_first() {
+        Some(ret) => ret,
+        None => return,
+    };
+
+    if rest.is_empty() {
+        if let Value::Map(incoming) = value {
+            if let Some(Value::Map(existing)) = map.get_mut(first) {
+                for (key, value) in incoming.into_iter() {
+                    merge_path(existing, &[key], value);
+                }
+                return;
+            }
+            map.insert(first.as_str(), Value::Map(incoming));
+        } else {
+            map.insert(first.as_str(), value);
+        }
+        return;
+    }
+
+    if !matches!(map.get(first), Some(Value::Map(_))) {
+        map.insert(first.as_str(), Value::Map(Map::default()));
+    }
+
+    if let Some(Value::Map(child)) = map.get_mut(first) {
+        merge_path(child, rest, value);
+    }
+}
+
+/// Visits every value, containers before their children.
+pub fn walk<F: FnMut(&KeyPath, &Value)>(value: &Value, mut f: F) {
+    walk_value(&KeyPath::root(), value, &mut f)
+}
+
+fn walk_value<F: FnMut(&KeyPath, &Value)>(path: &KeyPath, value: &Value, f: &mut F) {
+    f(path, value);
+
+    match value {
+        Value::Map(map) => {
+            for (key, value) in map.iter() {
+                walk_value(&path.key(key), value, f);
+            }
+        }
+        Value::List(list) => {
+            for (idx, value) in list.iter().enumerate() {
+                walk_value(&path.index(idx), value, f);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Like walk, but children are visited after the callback, so a value replaced
+/// by the callback has its new children walked.
+pub fn walk_mut<F: FnMut(&KeyPath, &mut Value)>(value: &mut Value, mut f: F) {
+    walk_value_mut(&KeyPath::root(), value, &mut f)
+}
+
+fn walk_value_mut<F: FnMut(&KeyPath, &mut Value)>(path: &KeyPath, value: &mut Value, f: &mut F) {
+    f(path, value);
+
+    match value {
+        Value::Map(map) => {
+            for (key, value) in map.iter_mut() {
+                walk_value_mut(&path.key(key), value, f);
+            }
+        }
+        Value::List(list) => {
+            for (idx, value) in list.iter_mut().enumerate() {
+                walk_value_mut(&path.index(idx), value, f);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Removes map entries and list items the predicate rejects. A rejected
+/// container is dropped as a whole, without visiting its children.
+pub fn retain<F: FnMut(&KeyPath, &Value) -> bool>(value: &mut Value, mut f: F) {
+    retain_value(&KeyPath::root(), value, &mut f)
+}
+
+fn retain_value<F: FnMut(&KeyPath, &Value) -> bool>(path: &KeyPath, value: &mut Value, f: &mut F) {
+    match value {
+        Value::Map(map) => {
+            for (key, mut value) in std::mem::take(map).into_iter() {
+                let path = path.key(&key);
+                if f(&path, &value) {
+                    retain_value(&path, &mut value, f);
+                    map.insert(key, value);
+                }
+            }
+        }
+        Value::List(list) => {
+            for (idx, mut value) in std::mem::take(list).into_iter().enumerate() {
+                let path = path.index(idx);
+                if f(&path, &value) {
+                    retain_value(&path, &mut value, f);
+                    list.push(value);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Transforms every leaf, keeping the map and list structure intact.
+pub fn map_values<F: FnMut(&KeyPath, Value) -> Value>(value: Value, mut f: F) -> Value {
+    map_value(&KeyPath::root(), value, &mut f)
+}
+
+fn map_value<F: FnMut(&KeyPath, Value) -> Value>(path: &KeyPath, value: Value, f: &mut F) -> Value {
+    match value {
+        Value::Map(map) => {
+            let mut out = Map::default();
+            for (key, value) in map.into_iter() {
+                let value = map_value(&path.key(&key), value, f);
+                out.insert(key, value);
+            }
+            Value::Map(out)
+        }
+        Value::List(mut list) => {
+            for (idx, value) in list.iter_mut().enumerate() {
+                let item = std::mem::replace(value, Value::Null);
+                *value = map_value(&path.index(idx), item, f);
+            }
+            Value::List(list)
+        }
+        value => f(path, value),
+    }
+}
+
+const ELLIPSIS: &str = "…";
+
+/// An abbreviated copy for logs and error reports. Containers below `depth`,
+/// entries past `max_items` and string tails past `max_string_len` are replaced
+/// by `…` markers.
+pub fn truncate(value: &Value, depth: usize, max_items: usize, max_string_len: usize) -> Value {
+    match value {
+        Value::Map(_) | Value::List(_) if depth == 0 => Value::String(ELLIPSIS.to_string()),
+        Value::Map(map) => {
+            let mut out = Map::default();
+            let mut count = 0;
+            for (key, value) in map.iter() {
+                count += 1;
+                if count > max_items {
+                    out.insert(ELLIPSIS, Value::String(ELLIPSIS.to_string()));
+                    break;
+                }
+                out.insert(
+                    key.clone(),
+                    truncate(value, depth - 1, max_items, max_string_len),
+                );
+            }
+            Value::Map(out)
+        }
+        Value::List(list) => {
+            let mut out = List::default();
+            for (idx, value) in list.iter().enumerate() {
+                if idx >= max_items {
+                    out.push(Value::String(ELLIPSIS.to_string()));
+                    break;
+                }
+                out.push(truncate(value, depth - 1, max_items, max_string_len));
+            }
+            Value::List(out)
+        }
+        Value::String(string) if string.chars().count() > max_string_len => {
+            let mut out = string.chars().take(max_string_len).collect::<String>();
+            out.push_str(ELLIPSIS);
+            Value::String(out)
+        }
+        value => value.clone(),
+    }
+}
+
+// Turns maps keyed `0`, `1`, ... without gaps into lists, for flat formats that
+// can only spell a list as indexed keys
+pub(crate) fn restore_lists(value: Value) -> Value {
+    let map = match value {
+        Value::Map(map) => map,
+        value => return value,
+    };
+
+    let mut entries = map
+        .into_iter()
+        .map(|(key, value)| (key, restore_lists(value)))
+        .collect::<Vec<_>>();
+
+    let mut indices = entries
+        .iter()
+        .map(|(key, _)| index(key))
+        .collect::<Option<Vec<_>>>()
+        .unwrap_or_default();
+    indices.sort_unstable();
+
+    let is_list = !indices.is_empty() && indices.iter().enumerate().all(|(pos, idx)| pos == *idx);
+
+    if is_list {
+        entries.sort_by_key(|(key, _)| index(key));
+        let mut list = List::default();
+        for (_, value) in entries {
+            list.push(value);
+        }
+        return Value::List(list);
+    }
+
+    let mut out = Map::default();
+    for (key, value) in entries {
+        out.insert(key, value);
+    }
+    Value::Map(out)
+}
+
+// Only the canonical spelling counts, so `01` stays a map key
+fn index(key: &str) -> Option<usize> {
+    let idx = key.parse::<usize>().ok()?;
+    if idx.to_string() == key {
+        Some(idx)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,8 @@
+use crate::key_path::KeyPath;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    pub origin: String,
+    pub path: KeyPath,
+    pub reason: &'static str,
+}
@@ -0,0 +1,85 @@
+#![cfg(all(feature = "builder", feature = "json"))]
+
+// The merge plan must come out the same no matter which order locators are
+// added in or directories are listed in, and must not need to parse anything
+
+use johnfig::{value, ConfigBuilder, DirLocator, MemoryLocator, SourceDescriptor};
+use std::path::PathBuf;
+
+fn fixture() -> (PathBuf, PathBuf) {
+    let base = std::env::temp_dir().join(format!("johnfig-merge-order-{}", std::process::id()));
+    let (first, second) = (base.join("first"), base.join("second"));
+
+    // Written out of name order, so listing order can't line up by accident
+    for (dir, name) in [
+        (&second, "b.json"),
+        (&first, "c.json"),
+        (&second, "a.json"),
+        (&first, "a.json"),
+    ] {
+        std::fs::create_dir_all(dir).unwrap();
+        std::fs::write(dir.join(name), "{}").unwrap();
+    }
+
+    (first, second)
+}
+
+fn plan(roots: &[&PathBuf]) -> Vec<SourceDescriptor> {
+    let mut builder = ConfigBuilder::new()
+        .with_name_pattern("*.{ext}")
+        .with_default(|_| {})
+        .with_value_layer("early", -10, value!({}))
+        .with_value_layer("late", 10, value!({}));
+
+    for root in roots {
+        builder = builder.with_locator(DirLocator((*root).clone()));
+    }
+
+    // Not valid JSON, the plan must be computed without parsing it
+    builder
+        .with_locator(MemoryLocator::new("memory").with_content("inline.json", "not json"))
+        .build()
+        .unwrap()
+        .merge_plan()
+        .unwrap()
+}
+
+fn file(root: &PathBuf, name: &str) -> SourceDescriptor {
+    SourceDescriptor::File {
+        path: root.join(name),
+        root: Some(root.clone()),
+        pattern: Some("*.json".to_string()),
+    }
+}
+
+#[test]
+fn merge_plan_is_deterministic() {
+    let (first, second) = fixture();
+
+    let plan = plan(&[&first, &second]);
+
+    assert_eq!(
+        plan,
+        vec![
+            SourceDescriptor::Defaults,
+            SourceDescriptor::Layer {
+                name: "early".to_string(),
+                priority: -10,
+            },
+            file(&first, "a.json"),
+            file(&first, "c.json"),
+            file(&second, "a.json"),
+            file(&second, "b.json"),
+            SourceDescriptor::Content {
+                name: "inline.json".to_string(),
+                root: PathBuf::from("memory"),
+            },
+            SourceDescriptor::Layer {
+                name: "late".to_string(),
+                priority: 10,
+            },
+        ]
+    );
+
+    assert_eq!(plan, self::plan(&[&second, &first]));
+}
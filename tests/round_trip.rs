@@ -0,0 +1,79 @@
+#![cfg(feature = "builder")]
+
+// Loads each golden file, saves it and loads it again. Canonicalized, both loads
+// must be equal, and typed formats must also equal the JSON golden file
+
+use johnfig::{values::canonicalize, ConfigBuilder, Value};
+use std::path::PathBuf;
+
+fn golden(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(name)
+}
+
+fn load(path: PathBuf) -> Value {
+    let finder = ConfigBuilder::new().build().unwrap();
+    let mut value = Value::Map(finder.load_file(path).unwrap().config);
+    canonicalize(&mut value);
+    value
+}
+
+fn round_trip(name: &str) -> Value {
+    let finder = ConfigBuilder::new().build().unwrap();
+
+    let first = load(golden(name));
+
+    let mut file = finder.load_file(golden(name)).unwrap();
+    let dir = std::env::temp_dir().join(format!("johnfig-round-trip-{}", std::process::id()));
+    file.path = dir.join(name);
+    file.save().unwrap();
+
+    let second = load(file.path.clone());
+    std::fs::remove_file(&file.path).ok();
+
+    assert_eq!(first, second, "{} changed after a save", name);
+    first
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn json() {
+    round_trip("config.json");
+}
+
+#[cfg(all(feature = "json", feature = "yaml"))]
+#[test]
+fn yaml() {
+    assert_eq!(round_trip("config.yaml"), load(golden("config.json")));
+}
+
+#[cfg(all(feature = "json", feature = "toml"))]
+#[test]
+fn toml() {
+    assert_eq!(round_trip("config.toml"), load(golden("config.json")));
+}
+
+#[cfg(all(feature = "json", feature = "ron"))]
+#[test]
+fn ron() {
+    assert_eq!(round_trip("config.ron"), load(golden("config.json")));
+}
+
+#[cfg(all(feature = "json", feature = "gura"))]
+#[test]
+fn gura() {
+    assert_eq!(round_trip("config.gura"), load(golden("config.json")));
+}
+
+#[cfg(all(feature = "json", feature = "plist"))]
+#[test]
+fn plist() {
+    assert_eq!(round_trip("config.plist"), load(golden("config.json")));
+}
+
+#[cfg(feature = "properties")]
+#[test]
+fn properties() {
+    round_trip("config.properties");
+}